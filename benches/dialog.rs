@@ -0,0 +1,128 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use xaskpass::config::{self, IndicatorType, StringType};
+use xaskpass::dialog::layout::Layout;
+use xaskpass::dialog::Dialog;
+
+fn indicators() -> [(&'static str, IndicatorType); 5] {
+    let strings = |strings| IndicatorType::Strings {
+        strings: config::IndicatorStrings {
+            strings,
+            ..config::IndicatorStrings::default()
+        },
+    };
+    [
+        (
+            "circle",
+            IndicatorType::Circle {
+                circle: config::IndicatorCircle::default(),
+            },
+        ),
+        (
+            "classic",
+            IndicatorType::Classic {
+                classic: config::IndicatorClassic::default(),
+            },
+        ),
+        (
+            "asterisk",
+            strings(StringType::Asterisk {
+                asterisk: config::Asterisk::default(),
+            }),
+        ),
+        (
+            "disco",
+            strings(StringType::Disco {
+                disco: config::Disco::default(),
+            }),
+        ),
+        (
+            "custom",
+            strings(StringType::Custom {
+                custom: config::Custom::default(),
+            }),
+        ),
+    ]
+}
+
+fn dialog_config(layout: Layout, indicator_type: IndicatorType) -> config::Dialog {
+    let mut cfg = config::Dialog::default();
+    cfg.layout_opts.layout = layout;
+    cfg.indicator.indicator_type = indicator_type;
+    cfg
+}
+
+fn surface(width: i32, height: i32) -> (cairo::ImageSurface, cairo::Context) {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).unwrap();
+    let cr = cairo::Context::new(&surface).unwrap();
+    (surface, cr)
+}
+
+// Dialog::new does the layout computation
+fn layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("layout");
+    for layout in [
+        Layout::BottomLeft,
+        Layout::Center,
+        Layout::MiddleCompact,
+        Layout::TopRight,
+    ] {
+        for (name, indicator_type) in indicators() {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{layout:?}"), name),
+                &indicator_type,
+                |b, indicator_type| {
+                    let (_surface, cr) = surface(1, 1);
+                    b.iter(|| {
+                        Dialog::new(
+                            dialog_config(layout, indicator_type.clone()),
+                            &cr,
+                            96.0 / 25.4,
+                            None,
+                            None,
+                            None,
+                        )
+                        .unwrap()
+                    });
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn paint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("paint");
+    for (name, indicator_type) in indicators() {
+        let (_surface, cr) = surface(1, 1);
+        let mut dialog = Dialog::new(
+            dialog_config(Layout::Center, indicator_type),
+            &cr,
+            96.0 / 25.4,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        dialog.fill_demo();
+        let (width, height) = dialog.window_size(&cr);
+        let (_surface, cr) = surface(width.into(), height.into());
+        dialog.cairo_context_changed(&cr);
+        group.bench_function(name, |b| b.iter(|| dialog.init(&cr)));
+    }
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    // The indicator timeouts need a runtime
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let _guard = runtime.enter();
+    layout(c);
+    paint(c);
+}
+
+criterion_group!(dialog, benches);
+criterion_main!(dialog);
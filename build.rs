@@ -48,7 +48,7 @@ fn main() {
     man = man.replace("{VERSION}", &full_version);
     std::fs::write(out_path.join("xaskpass.man"), man).unwrap();
 
-    let deps = [
+    let mut deps = vec![
         (
             &[("xkbcommon", "0.10"), ("xkbcommon-x11", "0.10")] as &[(&str, &str)],
             &[
@@ -66,12 +66,29 @@ fn main() {
             )],
         ),
     ];
+    if std::env::var_os("CARGO_FEATURE_XSMP").is_some() {
+        deps.push((
+            &[("sm", "1.2"), ("ice", "1.0")],
+            &[("src/session/ffi.h", "smlib.rs", "Smc.*|Ice.*|Sm.*")],
+        ));
+    }
+    if std::env::var_os("CARGO_FEATURE_SVG").is_some() {
+        deps.push((
+            &[("librsvg-2.0", "2.46")],
+            &[(
+                "src/svg/ffi.h",
+                "rsvg.rs",
+                "rsvg_handle_new_from_file|rsvg_handle_render_document",
+            )],
+        ));
+    }
 
     println!(
         "cargo:rustc-env=XASKPASS_BUILD_HEADER_DIR={}",
         out_path.display()
     );
 
+    let mut lib_versions = vec![];
     for (libs, headers) in deps {
         let mut include_paths = vec![];
         for (dep, version) in libs {
@@ -83,7 +100,10 @@ fn main() {
                     eprintln!("{}", s);
                     std::process::exit(1);
                 }
-                Ok(lib) => include_paths.extend(lib.include_paths),
+                Ok(lib) => {
+                    lib_versions.push(format!("{} {}", dep, lib.version));
+                    include_paths.extend(lib.include_paths);
+                }
             }
         }
 
@@ -113,4 +133,8 @@ fn main() {
                 .expect("Couldn't write bindings!");
         }
     }
+    println!(
+        "cargo:rustc-env=XASKPASS_BUILD_LIBS={}",
+        lib_versions.join(", ")
+    );
 }
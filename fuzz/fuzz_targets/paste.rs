@@ -0,0 +1,54 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use xaskpass::config::{self, IndicatorType, StringType};
+use xaskpass::dialog::Dialog;
+
+// Feeds the data to the dialog as if it had been received in a SelectionNotify. Zero bytes
+// split the data into separate pastes. The first byte chooses the indicator.
+fuzz_target!(|data: &[u8]| {
+    let Some((&kind, data)) = data.split_first() else {
+        return;
+    };
+    let mut cfg = config::Dialog::default();
+    cfg.indicator.indicator_type = match kind % 5 {
+        0 => IndicatorType::Circle {
+            circle: config::IndicatorCircle::default(),
+        },
+        1 => IndicatorType::Classic {
+            classic: config::IndicatorClassic::default(),
+        },
+        n => IndicatorType::Strings {
+            strings: config::IndicatorStrings {
+                strings: match n {
+                    2 => StringType::Asterisk {
+                        asterisk: config::Asterisk::default(),
+                    },
+                    3 => StringType::Disco {
+                        disco: config::Disco::default(),
+                    },
+                    _ => StringType::Custom {
+                        custom: config::Custom::default(),
+                    },
+                },
+                ..config::IndicatorStrings::default()
+            },
+        },
+    };
+
+    // The indicator timeouts need a runtime
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let _guard = runtime.enter();
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+    let cr = cairo::Context::new(&surface).unwrap();
+    let mut dialog = Dialog::new(cfg, &cr, 96.0 / 25.4, None, None, None).unwrap();
+    let (width, height) = dialog.window_size(&cr);
+    dialog.resize(&cr, width, height, true);
+    for selection in data.split(|&b| b == 0) {
+        dialog.paste(selection.to_vec());
+        dialog.repaint(&cr);
+    }
+});
@@ -0,0 +1,174 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::os::unix::net::UnixDatagram;
+
+use serde::Serialize;
+
+use crate::errors::{Context as _, Result};
+use crate::NAME;
+
+/// The `audit_log` value that sends the records to the systemd journal.
+pub const JOURNALD: &str = "journald";
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// How a prompt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ending {
+    Ok,
+    Cancel,
+    Timeout,
+}
+
+impl Ending {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Cancel => "cancel",
+            Self::Timeout => "timeout",
+        }
+    }
+}
+
+/// How a prompt ended, as written to the audit log. Never has the secret.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub time: String,
+    pub user: &'a str,
+    pub caller: Option<&'a str>,
+    pub title: &'a str,
+    pub label: Option<&'a str>,
+    pub outcome: Ending,
+}
+
+impl Record<'_> {
+    /// Appends a JSON line to the file at `target` or sends it to the journal.
+    pub fn write(&self, target: &str) -> Result<()> {
+        if target == JOURNALD {
+            return self.send_journal();
+        }
+        let mut line = serde_json::to_string(self).context("audit record")?;
+        line.push('\n');
+        // The command line of the caller may have secrets of its own
+        std::fs::File::options()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(target)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("audit log {target}"))?;
+        Ok(())
+    }
+
+    fn send_journal(&self) -> Result<()> {
+        let socket = UnixDatagram::unbound().context("journal socket")?;
+        socket
+            .send_to(self.journal_message().as_bytes(), JOURNAL_SOCKET)
+            .context("journal send")?;
+        Ok(())
+    }
+
+    // The native protocol, one KEY=value per line
+    fn journal_message(&self) -> String {
+        let mut fields = vec![
+            (
+                "MESSAGE",
+                format!("prompt {}: {}", self.outcome.as_str(), self.title),
+            ),
+            ("PRIORITY", "6".into()),
+            ("SYSLOG_IDENTIFIER", NAME.into()),
+            ("XASKPASS_USER", self.user.into()),
+            ("XASKPASS_TITLE", self.title.into()),
+            ("XASKPASS_OUTCOME", self.outcome.as_str().into()),
+        ];
+        if let Some(caller) = self.caller {
+            fields.push(("XASKPASS_CALLER", caller.into()));
+        }
+        if let Some(label) = self.label {
+            fields.push(("XASKPASS_LABEL", label.into()));
+        }
+        let mut message = String::new();
+        for (key, value) in fields {
+            // A newline would need the binary form
+            let _ = writeln!(message, "{key}={}", value.replace('\n', " "));
+        }
+        message
+    }
+}
+
+/// The current time in UTC as e.g. `2024-03-01T12:00:00Z`.
+pub fn now() -> String {
+    let mut buf = [0u8; 32];
+    unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::gmtime_r(std::ptr::from_ref(&t), std::ptr::from_mut(&mut tm));
+        let len = libc::strftime(
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            c"%Y-%m-%dT%H:%M:%SZ".as_ptr(),
+            std::ptr::from_ref(&tm),
+        );
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    fn record(label: Option<&str>) -> Record<'_> {
+        Record {
+            time: "2024-03-01T12:00:00Z".into(),
+            user: "alice",
+            caller: Some("ssh host [1234]"),
+            title: "ssh[1234]@host",
+            label,
+            outcome: Ending::Ok,
+        }
+    }
+
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("xaskpass-audit-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let target = path.to_str().unwrap();
+        record(Some("Password:")).write(target).unwrap();
+        record(None).write(target).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"time":"2024-03-01T12:00:00Z","user":"alice","caller":"ssh host [1234]","title":"ssh[1234]@host","label":"Password:","outcome":"ok"}"#
+        );
+        assert!(lines[1].contains(r#""label":null"#));
+    }
+
+    #[test]
+    fn journal_newlines() {
+        let message =
+            record(Some("The authenticity of host\ncan't be established.\n")).journal_message();
+        assert!(
+            message.contains("XASKPASS_LABEL=The authenticity of host can't be established. \n")
+        );
+        assert!(message.contains("MESSAGE=prompt ok: ssh[1234]@host\n"));
+        // Every line is a field
+        assert!(message.lines().all(|line| line.contains('=')));
+        assert_eq!(message.lines().count(), 8);
+    }
+
+    #[test]
+    fn time() {
+        let time = now();
+        assert_eq!(time.len(), "2024-03-01T12:00:00Z".len());
+        assert!(time.ends_with('Z'));
+        assert_eq!(time.as_bytes()[10], b'T');
+    }
+}
@@ -43,7 +43,7 @@ pub struct Cookie<'a> {
     caps: Option<x11rb::cookie::Cookie<'a, XCBConnection, present::QueryCapabilitiesReply>>,
     window: xproto::Window,
     surface: XcbSurface<'a>,
-    pub(super) cr: cairo::Context,
+    pub cr: cairo::Context,
 }
 
 impl<'a> Cookie<'a> {
@@ -117,6 +117,11 @@ impl<'a> Backbuffer<'a> {
         }
     }
 
+    /// Resizes the dialog to a window size it was not laid out for.
+    pub fn request_resize(&mut self, width: u16, height: u16) {
+        self.resize_requested = Some((width, height));
+    }
+
     pub fn init(&mut self, window: xproto::Window, dialog: &mut Dialog) -> Result<()> {
         trace!("init");
         self.eid = Some(self.conn.generate_id()?);
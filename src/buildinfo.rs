@@ -0,0 +1,52 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use crate::errors::{Context as _, Result};
+
+const FEATURES: [(&str, bool); 4] = [
+    ("dbus", cfg!(feature = "dbus")),
+    ("keyring", cfg!(feature = "keyring")),
+    ("xsmp", cfg!(feature = "xsmp")),
+    ("svg", cfg!(feature = "svg")),
+];
+
+/// Prints the version, features and libraries for bug reports.
+pub fn print() -> Result<()> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{} {} (crate {})",
+        crate::NAME,
+        env!("XASKPASS_BUILD_FULL_VERSION"),
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    writeln!(out, "features: {}", features.join(", ")).unwrap();
+    writeln!(out, "built with: {}", env!("XASKPASS_BUILD_LIBS")).unwrap();
+    writeln!(
+        out,
+        "running with: cairo {}, pango {}",
+        cairo::version_string(),
+        pango::version_string()
+    )
+    .unwrap();
+    let defaults =
+        toml::to_string_pretty(&crate::config::Config::default()).context("toml serialize")?;
+    writeln!(out, "default config: {:016x}", fnv1a(defaults.as_bytes())).unwrap();
+    std::io::stdout()
+        .write_all(out.as_bytes())
+        .context("write build info")?;
+    Ok(())
+}
+
+// Stable across Rust versions unlike the std hasher
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
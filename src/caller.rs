@@ -0,0 +1,169 @@
+use std::os::fd::{FromRawFd as _, OwnedFd, RawFd};
+
+use log::{debug, warn};
+use procfs::process::Process;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+// How many ancestors to show
+const DEPTH: usize = 3;
+// Long command lines are cut to this many chars
+const MAX_CMDLINE: usize = 60;
+
+/// Describes the processes that started us, e.g. `ssh -T host [1234] ← bash [1200] ← tmux [900]`.
+/// The first one is shown with its command line and the rest by name.
+pub fn describe() -> Option<String> {
+    let mut pid = i32::try_from(std::os::unix::process::parent_id()).ok()?;
+    let mut chain = Vec::with_capacity(DEPTH);
+    while chain.len() < DEPTH && pid > 1 {
+        let Ok(process) = Process::new(pid) else {
+            break;
+        };
+        let Ok(stat) = process.stat() else {
+            break;
+        };
+        let name = if chain.is_empty() {
+            process
+                .cmdline()
+                .ok()
+                .filter(|args| !args.is_empty())
+                .map_or_else(|| stat.comm.clone(), |args| truncate(&args.join(" ")))
+        } else {
+            stat.comm.clone()
+        };
+        chain.push(format!("{name} [{pid}]"));
+        pid = stat.ppid;
+    }
+    if chain.is_empty() {
+        return None;
+    }
+    let caller = chain.join(" ← ");
+    debug!("caller: {}", caller);
+    Some(caller)
+}
+
+/// A process whose exit cancels the prompt.
+pub struct Watch {
+    pid: i32,
+    fd: std::io::Result<OwnedFd>,
+}
+
+impl Watch {
+    /// The parent process. Taken first thing, as its pid may be reused once it exits. `None`
+    /// if it has already exited and we have been reparented to init.
+    pub fn parent() -> Option<Self> {
+        let parent = std::os::unix::process::parent_id();
+        let pid = i32::try_from(parent).ok().filter(|&pid| pid > 1)?;
+        let fd = pidfd_open(pid);
+        // Still our parent, so the fd is not for a process that got its pid since
+        if std::os::unix::process::parent_id() != parent {
+            debug!("parent {} exited at startup", pid);
+            return None;
+        }
+        Some(Self { pid, fd })
+    }
+
+    pub fn pid(pid: i32) -> Self {
+        Self {
+            pid,
+            fd: pidfd_open(pid),
+        }
+    }
+
+    /// Resolves when the process exits. Never resolves if the process cannot be watched.
+    pub async fn exited(self) {
+        let ret = match self
+            .fd
+            .and_then(|fd| AsyncFd::with_interest(fd, Interest::READABLE))
+        {
+            // The fd becomes readable once the process has exited
+            Ok(fd) => fd.readable().await.map(drop),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = ret {
+            warn!("cannot watch process {}: {}", self.pid, err);
+            std::future::pending::<()>().await;
+        }
+        debug!("process {} exited", self.pid);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: i32) -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(RawFd::try_from(fd).expect("fd fits in an int")) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_open(_pid: i32) -> std::io::Result<OwnedFd> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "watching a process needs pidfd_open of Linux",
+    ))
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() <= MAX_CMDLINE {
+        return s.to_owned();
+    }
+    let mut s: String = s.chars().take(MAX_CMDLINE - 1).collect();
+    s.push('…');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn child_exited() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("0.1")
+            .spawn()
+            .unwrap();
+        let watch = Watch::pid(i32::try_from(child.id()).unwrap());
+        tokio::time::timeout(Duration::from_secs(10), watch.exited())
+            .await
+            .expect("exit noticed");
+        child.wait().unwrap();
+    }
+
+    #[tokio::test]
+    async fn reaped_child() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = i32::try_from(child.id()).unwrap();
+        child.wait().unwrap();
+        // Gone, so it is not watched rather than taken for whatever gets its pid
+        let watch = Watch::pid(pid);
+        assert!(watch.fd.is_err());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), watch.exited())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parent() {
+        let watch = Watch::parent().expect("test runner is alive");
+        assert_eq!(
+            u32::try_from(watch.pid).unwrap(),
+            std::os::unix::process::parent_id()
+        );
+        assert!(watch.fd.is_ok());
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(truncate("ssh host"), "ssh host");
+        let long = "x".repeat(MAX_CMDLINE + 1);
+        let short = truncate(&long);
+        assert_eq!(short.chars().count(), MAX_CMDLINE);
+        assert!(short.ends_with('…'));
+    }
+}
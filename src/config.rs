@@ -1,40 +1,199 @@
 use std::io::Write;
 use std::path::Path;
 
+use log::{debug, warn};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use toml::Value;
 
-use crate::errors::{Context as _, Error, Result};
+use crate::errors::{self, Context as _, Error};
 use crate::{bail, NAME};
 
+/// Bumped when options are renamed or change meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
 pub struct Loader {
     pub xdg_dirs: xdg::BaseDirectories,
 }
 impl Loader {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME).unwrap();
         Self { xdg_dirs }
     }
 
-    pub fn load(&self) -> Result<Config> {
+    pub fn load(&self, profile: &Profile) -> errors::Result<Config> {
         self.xdg_dirs
             .find_config_file(format!("{NAME}.toml"))
             .as_deref()
-            .map_or_else(|| Ok(Config::default()), Self::load_path)
+            .map_or_else(
+                || {
+                    if let Some(name) = profile.explicit {
+                        bail!("no profile {} without a config file", name);
+                    }
+                    Ok(Config::deserialize(profile.fallback.clone()).context("Config Toml")?)
+                },
+                |path| Self::load_path(path, profile),
+            )
+    }
+
+    /// The dialog sections of the theme config files. Relative paths are looked up like the
+    /// config file.
+    pub fn load_themes(&self, paths: &[String]) -> Vec<Dialog> {
+        paths
+            .iter()
+            .filter_map(|path| {
+                let found = if Path::new(path).is_absolute() {
+                    Some(path.into())
+                } else {
+                    self.xdg_dirs.find_config_file(path)
+                };
+                let Some(found) = found else {
+                    warn!("theme {} not found", path);
+                    return None;
+                };
+                match Self::load_path(&found, &Profile::default()) {
+                    Ok(config) => Some(config.dialog),
+                    Err(err) => {
+                        warn!("theme {}: {:#}", path, err);
+                        None
+                    }
+                }
+            })
+            .collect()
     }
 
-    pub fn load_path(path: &Path) -> Result<Config> {
+    pub fn load_path(path: &Path, profile: &Profile) -> errors::Result<Config> {
         let data = std::fs::read_to_string(path).context("Config file")?;
-        Ok(toml::from_str(&data).context("Config Toml")?)
+        Self::parse(&data, profile)
+    }
+
+    pub fn parse(data: &str, profile: &Profile) -> errors::Result<Config> {
+        let mut table: toml::Table = toml::from_str(data).context("Config Toml")?;
+        let mut profiles = match table.remove("profiles") {
+            None => toml::Table::new(),
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => bail!("profiles must be a table"),
+        };
+        let name = if let Some(name) = profile.explicit {
+            if !profiles.contains_key(name) {
+                bail!("no profile {}", name);
+            }
+            Some(name)
+        } else {
+            profile
+                .candidates
+                .iter()
+                .map(String::as_str)
+                .find(|name| profiles.contains_key(*name))
+        };
+        if let Some(name) = name {
+            debug!("using profile {}", name);
+            let Some(Value::Table(overlay)) = profiles.remove(name) else {
+                bail!("profile {} must be a table", name);
+            };
+            merge(&mut table, overlay);
+        }
+        migrate(&mut table);
+        let mut config = profile.fallback.clone();
+        merge(&mut config, table);
+        let config = Config::deserialize(config).context("Config Toml")?;
+        config.check_schema_version();
+        Ok(config)
     }
 
-    pub fn print(cfg: &Config) -> Result<()> {
+    pub fn print(cfg: &Config) -> errors::Result<()> {
         let toml = toml::to_string_pretty(cfg).context("toml serialize")?;
         std::io::stdout()
             .write_all(toml.as_bytes())
             .expect("Unable to write data");
         Ok(())
     }
+
+    pub fn print_schema() -> errors::Result<()> {
+        let mut schema =
+            serde_json::to_value(schemars::schema_for!(Config)).context("json serialize")?;
+        null_to_none(&mut schema);
+        let json = serde_json::to_string_pretty(&schema).context("json serialize")?;
+        println!("{json}");
+        Ok(())
+    }
+}
+
+/// Unset options are written as `none` instead of being left out.
+fn null_to_none(schema: &mut serde_json::Value) {
+    use serde_json::{json, Value as Json};
+    if let Json::Object(map) = schema {
+        if map.get("default") == Some(&Json::Null) {
+            map.insert("default".into(), json!("none"));
+        }
+        if map.get("type") == Some(&json!("null")) {
+            map.remove("type");
+            map.insert("const".into(), json!("none"));
+        }
+        if let Some(Json::Array(types)) = map.get_mut("type") {
+            if let Some(i) = types.iter().position(|t| t == "null") {
+                types.remove(i);
+                let types = match map.remove("type") {
+                    Some(Json::Array(mut types)) if types.len() == 1 => types.remove(0),
+                    types => types.unwrap(),
+                };
+                map.insert(
+                    "anyOf".into(),
+                    json!([{ "type": types }, { "const": "none" }]),
+                );
+            }
+        }
+    }
+    match schema {
+        Json::Object(map) => map.values_mut().for_each(null_to_none),
+        Json::Array(items) => items.iter_mut().for_each(null_to_none),
+        _ => {}
+    }
+}
+
+/// Which `[profiles.<name>]` section to apply on top of the rest of the config.
+#[derive(Debug, Default)]
+pub struct Profile<'a> {
+    /// Asked for by name so it must exist
+    pub explicit: Option<&'a str>,
+    /// The first of these that exists is used
+    pub candidates: Vec<String>,
+    /// Settings below the config file
+    pub fallback: toml::Table,
+}
+
+// Maps removed options to what replaced them.
+fn migrate(table: &mut toml::Table) {
+    if let Some(show) = table.remove("show_hostname") {
+        warn!("show_hostname is replaced by {{hostname}} in title");
+        let title = match table.get("title") {
+            Some(Value::String(title)) if title.to_lowercase() != "none" => Some(title.clone()),
+            _ => None,
+        };
+        let title = match (title, show.as_bool().unwrap_or(true)) {
+            (None, true) => None,
+            (None, false) => Some("{cmd}[{pid}]".into()),
+            (Some(title), true) => Some(format!("{title}@{{hostname}}")),
+            (Some(title), false) => Some(title),
+        };
+        if let Some(title) = title {
+            table.insert("title".into(), Value::String(title));
+        }
+    }
+}
+
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
 }
 
 pub fn option_explicit_none<'de, T, D>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
@@ -62,6 +221,15 @@ where
     }
 }
 
+fn string_schema(pattern: Option<&str>) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    };
+    schema.string().pattern = pattern.map(Into::into);
+    schema.into()
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Rgba {
     pub red: u8,
@@ -70,14 +238,20 @@ pub struct Rgba {
     pub alpha: u8,
 }
 
-impl Serialize for Rgba {
-    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+impl std::fmt::Display for Rgba {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let hex = if self.alpha == u8::MAX {
             hex::encode([self.red, self.green, self.blue])
         } else {
             hex::encode([self.red, self.green, self.blue, self.alpha])
         };
-        serializer.serialize_str(&format!("#{hex}"))
+        write!(f, "#{hex}")
+    }
+}
+
+impl Serialize for Rgba {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
@@ -88,6 +262,16 @@ impl<'de> Deserialize<'de> for Rgba {
     }
 }
 
+impl JsonSchema for Rgba {
+    fn schema_name() -> String {
+        "Rgba".into()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        string_schema(Some("^#?([0-9a-fA-F]{6}|[0-9a-fA-F]{8})$"))
+    }
+}
+
 impl std::str::FromStr for Rgba {
     type Err = Error;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
@@ -121,34 +305,276 @@ impl std::str::FromStr for Rgba {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A key with modifiers, e.g. `Ctrl+Shift+P`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct KeyChord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// Keysym name
+    pub key: String,
+}
+
+impl std::fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (active, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.shift, "Shift"),
+            (self.alt, "Alt"),
+        ] {
+            if active {
+                write!(f, "{name}+")?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+impl Serialize for KeyChord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for KeyChord {
+    fn schema_name() -> String {
+        "KeyChord".into()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        string_schema(None)
+    }
+}
+
+impl std::str::FromStr for KeyChord {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        if crate::keyboard::keysyms_from_name(key).is_empty() {
+            bail!("unknown key '{}' in '{}'", key, s);
+        }
+        let mut chord = Self {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key: key.into(),
+        };
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                _ => bail!("unknown modifier '{}' in '{}'", modifier, s),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+/// A length in pixels, e.g. `300` or `300px`, or in millimeters, e.g. `80mm`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(f64),
+    Millimeters(f64),
+}
+
+impl Length {
+    pub fn to_pixels(self, px_per_mm: f64) -> f64 {
+        match self {
+            Self::Pixels(px) => px,
+            Self::Millimeters(mm) => mm * px_per_mm,
+        }
+    }
+}
+
+impl Serialize for Length {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Self::Pixels(px) => px.serialize(serializer),
+            Self::Millimeters(mm) => serializer.collect_str(&format_args!("{mm}mm")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Integer(px) => i32::try_from(px)
+                .map(|px| Self::Pixels(f64::from(px)))
+                .map_err(serde::de::Error::custom),
+            Value::Float(px) => Ok(Self::Pixels(px)),
+            Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+            value => Err(serde::de::Error::custom(format!("invalid length: {value}"))),
+        }
+    }
+}
+
+impl JsonSchema for Length {
+    fn schema_name() -> String {
+        "Length".into()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(vec![InstanceType::Number, InstanceType::String].into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl std::str::FromStr for Length {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let (number, unit): (&str, fn(f64) -> Self) = if let Some(mm) = s.strip_suffix("mm") {
+            (mm, Self::Millimeters)
+        } else {
+            (s.strip_suffix("px").unwrap_or(s), Self::Pixels)
+        };
+        let value: f64 = number
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid length '{s}'"))?;
+        if value < 0.0 {
+            bail!("negative length '{}'", s);
+        }
+        Ok(unit(value))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
+    /// Version of the option names and meanings the file was written for
+    pub schema_version: u32,
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
     pub title: Option<String>,
     pub grab_keyboard: bool,
-    pub show_hostname: bool,
+    pub cancel_on_focus_loss: bool,
+    /// Milliseconds the focus may be away before cancelling
+    pub focus_loss_grace: u64,
+    pub cancel_on_parent_exit: bool,
     pub resizable: bool,
+    /// Rescale when moved to a monitor with a different pixel density
+    pub follow_monitor_dpi: bool,
+    /// Center on the primary monitor, window frame included, instead of leaving the placement
+    /// to the window manager
+    pub center: bool,
+    /// Move back to the center once if the window manager places the window elsewhere right
+    /// after showing it
+    pub recenter: bool,
+    /// Be a modal dialog of the window given by --parent or $WINDOWID instead of staying above
+    /// all the windows
+    pub modal: bool,
+    /// Ask for no title bar and border and move the window by dragging the dialog
+    pub frameless: bool,
+    pub above_removed: AboveRemoved,
+    pub warp_pointer: WarpPointer,
+    pub early_focus_loss: EarlyFocusLoss,
+    /// Wake the display from DPMS standby and the screen saver when the dialog appears
+    pub wake_display: bool,
+    /// Keep the screen from blanking while the dialog is open
+    pub inhibit_screensaver: bool,
+    /// Milliseconds by which redraws after typing are held back and batched on a remote display
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub remote_redraw_interval: Option<u64>,
+    /// Name of the keyboard LED to flash on each typed character
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub led_feedback: Option<String>,
+    /// Keyboard layout locked while the dialog has the focus
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub force_layout: Option<String>,
+    /// Wait for the prompts of the other xaskpass processes to close first
+    pub queue_prompts: bool,
+    /// PNG file set as the window icon
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub icon: Option<String>,
     pub depth: u8,
+    pub prompt_heuristics: bool,
+    pub sudo_header: bool,
+    pub show_caller: bool,
+    pub basic_auth_output: BasicAuthOutput,
+    /// File to append a JSON line to for every prompt, or `journald`
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub audit_log: Option<String>,
+    pub hooks: Hooks,
     pub dialog: Dialog,
 }
 
+impl Config {
+    fn check_schema_version(&self) {
+        match self.schema_version.cmp(&SCHEMA_VERSION) {
+            std::cmp::Ordering::Equal => {}
+            std::cmp::Ordering::Less => warn!(
+                "config schema_version {} is older than {}: some options may be ignored or \
+                 mean something else, compare with {} --gen-config",
+                self.schema_version, SCHEMA_VERSION, NAME
+            ),
+            std::cmp::Ordering::Greater => warn!(
+                "config schema_version {} is newer than {}: options of a later {} are ignored",
+                self.schema_version, SCHEMA_VERSION, NAME
+            ),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             title: None,
             grab_keyboard: false,
-            show_hostname: true,
+            cancel_on_focus_loss: false,
+            focus_loss_grace: 500,
+            cancel_on_parent_exit: false,
             resizable: false,
+            follow_monitor_dpi: false,
+            center: false,
+            recenter: false,
+            modal: false,
+            frameless: false,
+            above_removed: AboveRemoved::Allow,
+            warp_pointer: WarpPointer::Never,
+            early_focus_loss: EarlyFocusLoss::Ignore,
+            wake_display: false,
+            inhibit_screensaver: false,
+            remote_redraw_interval: None,
+            led_feedback: None,
+            force_layout: None,
+            queue_prompts: false,
+            icon: None,
             depth: 32,
+            prompt_heuristics: true,
+            sudo_header: true,
+            show_caller: false,
+            basic_auth_output: BasicAuthOutput::Colon,
+            audit_log: None,
+            hooks: Hooks::default(),
             dialog: Dialog::default(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Dialog {
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
@@ -164,19 +590,168 @@ pub struct Dialog {
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
     pub scale: Option<f64>,
+    /// Dither the gradients on visuals with less than 10 bits per color
+    pub dither: bool,
+    /// Empty to hide it
     pub indicator_label: String,
+    /// By default only for the Circle indicator
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub show_indicator_label: Option<bool>,
+    /// Shown with `early_focus_loss` 'Hint'
+    pub focus_hint: String,
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
     pub input_timeout: Option<u64>,
+    pub timeout_action: TimeoutAction,
+    pub require_non_empty: bool,
+    pub submit_on_paste: SubmitOnPaste,
+    /// The first Enter only arms the OK
+    pub confirm_submit: bool,
+    /// Milliseconds the second Enter has to come in
+    pub confirm_submit_window: u64,
+    /// Milliseconds OK stays disabled on the second `--attempt`, doubled for each later one
+    pub retry_backoff: u64,
+    /// Estimated bits below which a new passphrase needs OK pressed twice
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub min_entropy: Option<u32>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub plaintext_key: Option<KeyChord>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub delete_word_key: Option<KeyChord>,
+    /// Wipes what has been typed and cancels at once
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub wipe_key: Option<KeyChord>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub delete_char_key: Option<KeyChord>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub submit_key: Option<KeyChord>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub keypad_submit_key: Option<KeyChord>,
+    pub cancel_keys: Vec<KeyChord>,
+    /// Also cancel when there is nothing to type, like in the confirmation mode
+    pub confirm_cancel_keys: Vec<KeyChord>,
+    /// A cancel key clears the input first if there is any
+    pub cancel_clears: bool,
+    /// Locks the next keyboard layout
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub layout_switch_key: Option<KeyChord>,
+    /// Show the name of the layout for a moment when it changes
+    pub show_layout_change: bool,
+    /// Switches to the colors of the next of the themes
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub theme_key: Option<KeyChord>,
+    /// Config files whose dialog colors `theme_key` cycles through
+    pub themes: Vec<String>,
     pub foreground: Rgba,
     pub indicator_label_foreground: Rgba,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub secret_phrase: Option<String>,
+    pub header_foreground: Rgba,
+    pub caller_foreground: Rgba,
+    /// Colors of the `--error` strip
+    pub error_foreground: Rgba,
+    pub error_background: Rgba,
+    pub username_label: String,
+    pub password_label: String,
     pub background: Rgba,
+    /// Paint the background opaque even with a compositor
+    pub reduce_transparency: bool,
+    /// Rounds the corners of the window when it can be see-through
+    pub corner_radius: f64,
+    /// Where the button icons are looked up before hicolor
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub icon_theme: Option<String>,
     pub layout_opts: Layout,
     pub ok_button: TextButton,
     pub cancel_button: TextButton,
     pub clipboard_button: ClipboardButton,
     pub plaintext_button: TextButton,
+    pub remember_button: TextButton,
     pub indicator: Indicator,
+    /// Shown under the indicator, only following the length of the passphrase
+    pub secondary_indicators: Vec<Indicator>,
+    pub maximized: Maximized,
+    pub logo: Logo,
+}
+
+/// How the dialog changes when maximized or fullscreen.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Maximized {
+    /// Multiplies the spacing between the components
+    pub spacing: f64,
+    /// Scales everything up by at most this much as long as it fits the window
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub scale: Option<f64>,
+}
+
+impl Default for Maximized {
+    fn default() -> Self {
+        Self {
+            spacing: 2.0,
+            scale: None,
+        }
+    }
+}
+
+/// Commands started after the dialog has closed. Each is a program followed by its arguments,
+/// empty for none.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Hooks {
+    pub on_ok: Vec<String>,
+    pub on_cancel: Vec<String>,
+    /// When `dialog.input_timeout` or `--deadline` ran out
+    pub on_timeout: Vec<String>,
+}
+
+/// A picture next to the label, e.g. a company logo.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Logo {
+    /// PNG file, or SVG with the svg feature
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub path: Option<String>,
+    /// Scaled down to fit in these
+    pub max_width: f64,
+    pub max_height: f64,
+    pub placement: LogoPlacement,
+    /// A personal picture like `secret_phrase`, left out of screenshots
+    pub secret: bool,
+}
+
+impl Default for Logo {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_width: 48.0,
+            max_height: 48.0,
+            placement: LogoPlacement::Left,
+            secret: false,
+        }
+    }
+}
+
+/// Where the logo is, the rest of the dialog moving aside for it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum LogoPlacement {
+    Left,
+    Right,
+    Top,
 }
 
 impl Default for Dialog {
@@ -185,6 +760,7 @@ impl Default for Dialog {
         let ok_button = TextButton {
             label: "OK".into(),
             foreground: "#5c616c".parse().unwrap(),
+            icon: None,
             button: button.clone(),
         };
         let cancel_button = TextButton {
@@ -197,33 +773,83 @@ impl Default for Dialog {
             ..ok_button.clone()
         };
 
+        let remember_button = TextButton {
+            label: "Remember".into(),
+            ..ok_button.clone()
+        };
+
         Self {
             foreground: "#5c616c".parse().unwrap(),
             indicator_label_foreground: "#5c616c".parse().unwrap(),
+            secret_phrase: None,
+            header_foreground: "#3465a4".parse().unwrap(),
+            caller_foreground: "#9a9da4".parse().unwrap(),
+            error_foreground: "#ffffff".parse().unwrap(),
+            error_background: "#cc0000".parse().unwrap(),
+            username_label: "Username".into(),
+            password_label: "Password".into(),
             background: "#f5f6f7ee".parse().unwrap(),
+            reduce_transparency: false,
+            corner_radius: 0.0,
+            icon_theme: None,
             label: "Please enter your authentication passphrase:".into(),
             alignment: PangoAlignment::Left,
             indicator_label: "Secret:".into(),
+            show_indicator_label: None,
+            focus_hint: "Click here to type".into(),
             input_timeout: Some(30),
+            timeout_action: TimeoutAction::Cancel,
+            require_non_empty: false,
+            submit_on_paste: SubmitOnPaste::Never,
+            confirm_submit: false,
+            confirm_submit_window: 1500,
+            retry_backoff: 1000,
+            min_entropy: None,
+            plaintext_key: Some("Ctrl+Shift+P".parse().unwrap()),
+            delete_word_key: Some("Ctrl+BackSpace".parse().unwrap()),
+            wipe_key: Some("Ctrl+Shift+BackSpace".parse().unwrap()),
+            cancel_keys: vec!["Escape".parse().unwrap()],
+            confirm_cancel_keys: Vec::new(),
+            cancel_clears: false,
+            layout_switch_key: None,
+            show_layout_change: true,
+            theme_key: None,
+            themes: Vec::new(),
+            delete_char_key: Some("Ctrl+H".parse().unwrap()),
+            submit_key: Some("Return".parse().unwrap()),
+            keypad_submit_key: Some("KP_Enter".parse().unwrap()),
             font: Some("default 11".into()),
             direction: None,
             scale: None,
+            dither: true,
             font_file: None,
             layout_opts: Layout::default(),
             ok_button,
             cancel_button,
             plaintext_button,
+            remember_button,
             clipboard_button: ClipboardButton {
-                foreground: "#5c616c".parse().unwrap(),
                 button,
+                ..ClipboardButton::default()
             },
             indicator: Indicator::default(),
+            secondary_indicators: Vec::new(),
+            maximized: Maximized::default(),
+            logo: Logo::default(),
         }
     }
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct ClipboardButton {
+    pub source: PasteSource,
+    /// Labels of the choices with `source = 'Choose'`
+    pub clipboard_label: String,
+    pub primary_label: String,
+    /// SVG file drawn in the foreground color instead of the clipboard glyph
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub image: Option<String>,
     pub foreground: Rgba,
     #[serde(flatten)]
     pub button: Button,
@@ -232,17 +858,34 @@ pub struct ClipboardButton {
 impl Default for ClipboardButton {
     fn default() -> Self {
         Self {
+            source: PasteSource::Clipboard,
+            clipboard_label: "Clipboard".into(),
+            primary_label: "Primary".into(),
+            image: None,
             foreground: "#5c616c".parse().unwrap(),
             button: Button::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The selection the clipboard button pastes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PasteSource {
+    Clipboard,
+    Primary,
+    /// Clipboard but a long press shows a choice between the two
+    Choose,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct TextButton {
     pub label: String,
     pub foreground: Rgba,
+    /// A PNG or SVG file or the name of an icon in the icon themes, shown before the label
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub icon: Option<String>,
     #[serde(flatten)]
     pub button: Button,
 }
@@ -252,12 +895,22 @@ impl Default for TextButton {
         Self {
             label: "label".into(),
             foreground: "#5c616c".parse().unwrap(),
+            icon: None,
             button: Button::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How `border_width` is measured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum BorderMode {
+    /// Like the other sizes so it grows with the scale
+    Scaled,
+    /// In device pixels, aligned to them to stay sharp at any scale
+    Hairline,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Button {
     #[serde(deserialize_with = "option_explicit_none")]
@@ -267,6 +920,7 @@ pub struct Button {
     #[serde(deserialize_with = "option_explicit_none")]
     pub vertical_spacing: Option<f64>,
     pub border_width: f64,
+    pub border_mode: BorderMode,
     pub radius_x: f64,
     pub radius_y: f64,
     pub pressed_adjustment_x: f64,
@@ -285,6 +939,9 @@ pub struct Button {
     #[serde(deserialize_with = "option_explicit_none")]
     pub background_hover_stop: Option<Rgba>,
     pub background_hover: Rgba,
+    pub foreground_disabled: Rgba,
+    pub background_disabled: Rgba,
+    pub border_color_disabled: Rgba,
 }
 
 impl Default for Button {
@@ -299,17 +956,21 @@ impl Default for Button {
             horizontal_spacing: None,
             vertical_spacing: None,
             border_width: 1.0,
+            border_mode: BorderMode::Scaled,
             border_color: "#cfd6e6".parse().unwrap(),
             border_color_pressed: "#b7c0d3".parse().unwrap(),
             radius_x: 2.0,
             radius_y: 2.0,
             pressed_adjustment_x: 1.0,
             pressed_adjustment_y: 1.0,
+            foreground_disabled: "#b8babf".parse().unwrap(),
+            background_disabled: "#f5f6f7".parse().unwrap(),
+            border_color_disabled: "#dde1e8".parse().unwrap(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Layout {
     pub layout: crate::dialog::layout::Layout,
@@ -322,6 +983,46 @@ pub struct Layout {
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
     pub text_width: Option<u32>,
+    /// From top to bottom
+    #[serde(deserialize_with = "rows")]
+    pub rows: Vec<crate::dialog::layout::Row>,
+    pub indicator_label_side: crate::dialog::layout::Side,
+    /// Between the indicator and what is next to it
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    indicator_spacing: Option<f64>,
+    /// Extra space above and below the rows
+    pub label_margin: f64,
+    pub indicator_margin: f64,
+    pub buttons_margin: f64,
+    /// Around everything, in window pixels or millimeters like the sizes below
+    pub padding: Length,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub min_width: Option<Length>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub max_width: Option<Length>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub min_height: Option<Length>,
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub max_height: Option<Length>,
+}
+
+fn rows<'de, D>(deserializer: D) -> std::result::Result<Vec<crate::dialog::layout::Row>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use crate::dialog::layout::Row;
+    let rows = Vec::<Row>::deserialize(deserializer)?;
+    if rows.len() != Row::ALL.len() || !Row::ALL.iter().all(|r| rows.contains(r)) {
+        return Err(serde::de::Error::custom(
+            "rows must list Label, Indicator and Buttons once each",
+        ));
+    }
+    Ok(rows)
 }
 
 impl Layout {
@@ -333,6 +1034,20 @@ impl Layout {
         self.vertical_spacing
             .unwrap_or_else(|| (text_height / 1.7).round())
     }
+    pub fn indicator_spacing(&self, text_height: f64) -> f64 {
+        self.indicator_spacing
+            .unwrap_or_else(|| (text_height / 4.0).round())
+    }
+    /// The same layout with the spacing multiplied by `factor`.
+    #[must_use]
+    pub fn spaced(&self, factor: f64, text_height: f64) -> Self {
+        Self {
+            horizontal_spacing: Some((self.horizontal_spacing(text_height) * factor).round()),
+            vertical_spacing: Some((self.vertical_spacing(text_height) * factor).round()),
+            indicator_spacing: Some((self.indicator_spacing(text_height) * factor).round()),
+            ..self.clone()
+        }
+    }
 }
 
 impl Default for Layout {
@@ -342,11 +1057,22 @@ impl Default for Layout {
             horizontal_spacing: None,
             vertical_spacing: None,
             text_width: None,
+            rows: crate::dialog::layout::Row::ALL.into(),
+            indicator_label_side: crate::dialog::layout::Side::Left,
+            indicator_spacing: None,
+            label_margin: 0.0,
+            indicator_margin: 0.0,
+            buttons_margin: 0.0,
+            padding: Length::Pixels(0.0),
+            min_width: None,
+            max_width: None,
+            min_height: None,
+            max_height: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Copy)]
 #[serde(default)]
 pub struct IndicatorClassic {
     pub min_count: u16,
@@ -362,6 +1088,18 @@ pub struct IndicatorClassic {
     #[serde(serialize_with = "option_explicit_serialize")]
     #[serde(deserialize_with = "option_explicit_none")]
     pub element_width: Option<f64>,
+    pub fill: ClassicFill,
+}
+
+/// Which boxes light up as the passphrase grows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ClassicFill {
+    /// One box moving left to right
+    Single,
+    /// Left to right up to the last box and then again from the start
+    Progressive,
+    /// Like Progressive but from the center outwards
+    CenterOut,
 }
 
 impl Default for IndicatorClassic {
@@ -374,11 +1112,12 @@ impl Default for IndicatorClassic {
             horizontal_spacing: None,
             element_height: None,
             element_width: None,
+            fill: ClassicFill::Single,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Copy)]
 #[serde(default)]
 pub struct IndicatorCircle {
     #[serde(serialize_with = "option_explicit_serialize")]
@@ -387,6 +1126,11 @@ pub struct IndicatorCircle {
     pub rotate: bool,
     pub rotation_speed_start: f64,
     pub rotation_speed_gain: f64,
+    pub rotation_easing: Easing,
+    /// 'none' to animate at the refresh rate of the display
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub max_fps: Option<u32>,
     pub light_up: bool,
     pub spacing_angle: f64,
     pub indicator_count: u32,
@@ -404,6 +1148,8 @@ impl Default for IndicatorCircle {
             light_up: true,
             rotation_speed_start: 0.10,
             rotation_speed_gain: 1.05,
+            rotation_easing: Easing::EaseIn,
+            max_fps: Some(60),
             spacing_angle: 0.5,
             indicator_count: 3,
             indicator_width: None,
@@ -412,12 +1158,31 @@ impl Default for IndicatorCircle {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How the rotation speed changes during the animation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Easing {
+    /// Speeds up by the gain every frame
+    EaseIn,
+    /// Constant speed
+    Linear,
+    /// Slows down towards the end
+    EaseOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Disco {
     pub min_count: u16,
     pub max_count: u16,
     pub three_states: bool,
+    /// The first one is shown on paste and the rest dance
+    #[serde(deserialize_with = "dancers")]
+    pub dancers: Vec<String>,
+    pub separator: String,
+    /// Beats per minute to dance on a timer too
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub tempo: Option<u32>,
 }
 
 impl Default for Disco {
@@ -426,11 +1191,89 @@ impl Default for Disco {
             min_count: 3,
             max_count: 3,
             three_states: false,
+            dancers: ["┗(･o･)┛", "┏(･o･)┛", "┗(･o･)┓", "┏(･o･)┓"]
+                .map(String::from)
+                .into(),
+            separator: " ♪ ".into(),
+            tempo: None,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+fn dancers<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let dancers = Vec::<String>::deserialize(deserializer)?;
+    if dancers.len() < 3 {
+        return Err(serde::de::Error::custom(
+            "at least three dancers are needed",
+        ));
+    }
+    Ok(dancers)
+}
+
+/// How `--basic-auth` prints the credentials.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum BasicAuthOutput {
+    /// `username:password`
+    Colon,
+    /// Username and password on separate lines
+    Lines,
+}
+
+/// What happens when `input_timeout` expires.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TimeoutAction {
+    Cancel,
+    /// Accept what has been typed so far
+    Accept,
+    /// Accept an empty passphrase
+    AcceptEmpty,
+}
+
+/// What happens when the window manager or the user takes the dialog off the top.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AboveRemoved {
+    /// Ask to be above the other windows again
+    Reassert,
+    /// Only log it
+    Allow,
+}
+
+/// Where the pointer is moved when the dialog appears.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum WarpPointer {
+    /// Leave it where it is
+    Never,
+    /// The middle of the dialog
+    Center,
+    /// The middle of the OK button
+    Ok,
+}
+
+/// What happens when the dialog does not get the focus or loses it right after appearing, as
+/// under focus-follows-mouse with the pointer elsewhere.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum EarlyFocusLoss {
+    /// Only log it
+    Ignore,
+    /// Ask the window manager for the focus once
+    Refocus,
+    /// Show `focus_hint` until the focus comes
+    Hint,
+}
+
+/// Whether pasting presses OK.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SubmitOnPaste {
+    Never,
+    Always,
+    /// Only when nothing had been typed before the paste
+    IfEmpty,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum PangoAlignment {
     Left,
     Center,
@@ -447,7 +1290,7 @@ impl From<PangoAlignment> for pango::Alignment {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum PangoDirection {
     Ltr,
     Neutral,
@@ -483,7 +1326,7 @@ where
     Ok(arr)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Custom {
     pub alignment: PangoAlignment,
@@ -511,7 +1354,7 @@ impl Default for Custom {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "strings")]
 pub enum StringType {
     Disco {
@@ -528,7 +1371,7 @@ pub enum StringType {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct IndicatorStrings {
     #[serde(serialize_with = "option_explicit_serialize")]
@@ -539,6 +1382,16 @@ pub struct IndicatorStrings {
     pub vertical_spacing: Option<f64>,
     pub radius_x: f64,
     pub radius_y: f64,
+    /// Pasted characters are shown briefly with these
+    pub paste_foreground: Rgba,
+    pub paste_underline: bool,
+    pub plaintext_overflow: PlaintextOverflow,
+    pub caret_shape: CaretShape,
+    pub caret_width: f64,
+    /// 'none' to use the foreground color
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub caret_color: Option<Rgba>,
     #[serde(flatten)]
     pub strings: StringType,
 }
@@ -550,6 +1403,12 @@ impl Default for IndicatorStrings {
             vertical_spacing: None,
             radius_x: 2.0,
             radius_y: 2.0,
+            paste_foreground: "#5294e2".parse().unwrap(),
+            paste_underline: true,
+            plaintext_overflow: PlaintextOverflow::Ellipsize,
+            caret_shape: CaretShape::Bar,
+            caret_width: 1.0,
+            caret_color: None,
             strings: StringType::Asterisk {
                 asterisk: Asterisk::default(),
             },
@@ -557,7 +1416,26 @@ impl Default for IndicatorStrings {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How the shown passphrase fits the indicator when it is too long.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PlaintextOverflow {
+    /// Leave out the middle
+    Ellipsize,
+    /// Scroll to keep the caret in view
+    Scroll,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum CaretShape {
+    /// A vertical line before the next character
+    Bar,
+    /// A line under the next character
+    Underline,
+    /// The next character in inverted colors
+    Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum IndicatorType {
     Strings {
@@ -574,7 +1452,7 @@ pub enum IndicatorType {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Indicator {
     #[serde(flatten)]
@@ -595,11 +1473,14 @@ impl Default for Indicator {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Copy)]
 #[serde(default)]
 pub struct IndicatorCommon {
     pub border_width: f64,
+    pub border_mode: BorderMode,
     pub blink: bool,
+    /// False to keep the indicator still while typing, e.g. with `led_feedback`
+    pub feedback: bool,
     pub foreground: Rgba,
     pub background: Rgba,
     #[serde(deserialize_with = "option_explicit_none")]
@@ -617,10 +1498,12 @@ impl Default for IndicatorCommon {
     fn default() -> Self {
         Self {
             border_width: 1.0,
+            border_mode: BorderMode::Scaled,
             foreground: "#5c616c".parse().unwrap(),
             background: "#ffffff".parse().unwrap(),
             background_stop: None,
             blink: true,
+            feedback: true,
             border_color: "#cfd6e6".parse().unwrap(),
             border_color_focused: "#5294e2".parse().unwrap(),
             indicator_color: "#d3d8e2".parse().unwrap(),
@@ -629,13 +1512,25 @@ impl Default for IndicatorCommon {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Asterisk {
     pub alignment: PangoAlignment,
     pub asterisk: String,
+    /// Overrides asterisk
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub preset: Option<AsteriskPreset>,
+    /// Played when a character is typed or erased
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub animation: Option<AsteriskAnimation>,
     pub min_count: u16,
     pub max_count: u16,
+    /// Each character is centered in a cell this wide. 'none' to use the width of the character
+    #[serde(serialize_with = "option_explicit_serialize")]
+    #[serde(deserialize_with = "option_explicit_none")]
+    pub cell_width: Option<f64>,
 }
 
 impl Default for Asterisk {
@@ -643,8 +1538,112 @@ impl Default for Asterisk {
         Self {
             alignment: PangoAlignment::Center,
             asterisk: "*".into(),
+            preset: None,
+            animation: None,
             min_count: 10,
             max_count: 20,
+            cell_width: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AsteriskPreset {
+    /// •
+    Bullet,
+    /// ●
+    Circle,
+    /// ✱
+    Star,
+}
+
+impl AsteriskPreset {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Bullet => "•",
+            Self::Circle => "●",
+            Self::Star => "✱",
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AsteriskAnimation {
+    /// Grows in and bursts out
+    Pop,
+    /// Fades in and out
+    Fade,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles() {
+        let data = r"
+            grab_keyboard = false
+            [dialog]
+            label = 'base'
+            [profiles.sudo]
+            grab_keyboard = true
+            [profiles.sudo.dialog]
+            background = '#ff0000'
+        ";
+        let base = Loader::parse(data, &Profile::default()).unwrap();
+        assert!(!base.grab_keyboard);
+
+        let profile = Profile {
+            explicit: None,
+            candidates: vec!["xaskpass".into(), "sudo".into()],
+            ..Profile::default()
+        };
+        let sudo = Loader::parse(data, &profile).unwrap();
+        assert!(sudo.grab_keyboard);
+        assert_eq!(sudo.dialog.label, "base");
+        assert_eq!(sudo.dialog.background.to_string(), "#ff0000");
+
+        let missing = Profile {
+            explicit: Some("ssh"),
+            candidates: vec![],
+            ..Profile::default()
+        };
+        assert!(Loader::parse(data, &missing).is_err());
+    }
+
+    #[test]
+    fn show_hostname() {
+        let parse = |s: &str| Loader::parse(s, &Profile::default()).unwrap().title;
+        assert_eq!(
+            parse("show_hostname = false").as_deref(),
+            Some("{cmd}[{pid}]")
+        );
+        assert_eq!(parse("show_hostname = true"), None);
+        assert_eq!(
+            parse("show_hostname = true\ntitle = 'x'").as_deref(),
+            Some("x@{hostname}")
+        );
+    }
+
+    #[test]
+    fn length() {
+        let parse = |s: &str| s.parse::<Length>().ok();
+        assert_eq!(parse("12"), Some(Length::Pixels(12.0)));
+        assert_eq!(parse("12.5px"), Some(Length::Pixels(12.5)));
+        assert_eq!(parse(" 80 mm"), Some(Length::Millimeters(80.0)));
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse("1cm"), None);
+        let layout: Layout = toml::from_str("max_width = '90mm'\nmin_width = 200").unwrap();
+        assert_eq!(layout.max_width, Some(Length::Millimeters(90.0)));
+        assert_eq!(layout.min_width, Some(Length::Pixels(200.0)));
+    }
+
+    #[test]
+    fn key_chord() {
+        let chord: KeyChord = "control + shift+p".parse().unwrap();
+        assert!(chord.ctrl && chord.shift && !chord.alt);
+        assert_eq!(chord.to_string(), "Ctrl+Shift+p");
+        assert!("Ctrl+NoSuchKey".parse::<KeyChord>().is_err());
+        assert!("Hyper+p".parse::<KeyChord>().is_err());
+    }
+}
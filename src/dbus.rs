@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt as _;
+use log::debug;
+use tokio::sync::{mpsc, oneshot, Notify};
+use zbus::zvariant::OwnedValue;
+use zbus::{fdo, interface, SignalContext};
+
+use crate::config;
+use crate::errors::{Context as _, Result};
+use crate::secret::{Passphrase, SecBuf};
+
+pub const BUS_NAME: &str = "io.github.user827.Xaskpass";
+pub const OBJECT_PATH: &str = "/io/github/user827/Xaskpass";
+
+/// The result of a prompt. The status values match the exit codes.
+pub enum Outcome {
+    Ok(Passphrase),
+    Cancelled,
+    Error(String),
+}
+
+impl Outcome {
+    fn status(&self) -> u32 {
+        match self {
+            Self::Ok(_) => 0,
+            Self::Cancelled => 1,
+            Self::Error(_) => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub id: Option<String>,
+    pub input_timeout: Option<u64>,
+}
+
+impl Options {
+    fn parse(options: HashMap<String, OwnedValue>) -> fdo::Result<Self> {
+        let mut me = Self::default();
+        for (key, value) in options {
+            match key.as_str() {
+                "id" => {
+                    let id = String::try_from(value)
+                        .map_err(|err| fdo::Error::InvalidArgs(format!("{key}: {err}")))?;
+                    me.id = Some(id);
+                }
+                "input_timeout" => {
+                    let secs = u32::try_from(value)
+                        .map_err(|err| fdo::Error::InvalidArgs(format!("{key}: {err}")))?;
+                    me.input_timeout = Some(secs.into());
+                }
+                _ => return Err(fdo::Error::InvalidArgs(format!("unknown option {key}"))),
+            }
+        }
+        Ok(me)
+    }
+
+    pub fn apply(&self, config: &mut config::Config) {
+        if self.input_timeout.is_some() {
+            config.dialog.input_timeout = self.input_timeout;
+        }
+    }
+}
+
+pub struct Request {
+    pub label: String,
+    pub options: Options,
+    pub cancel: Arc<Notify>,
+    /// Label updates for the dialog
+    pub labels: mpsc::UnboundedReceiver<String>,
+    pub reply: oneshot::Sender<Outcome>,
+}
+
+// The passphrase of a reply, serialized straight from the buffer that is wiped on drop
+struct Secret(SecBuf<u8>);
+
+impl serde::Serialize for Secret {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.0.unsecure().serialize(serializer)
+    }
+}
+
+impl zbus::zvariant::Type for Secret {
+    fn signature() -> zbus::zvariant::Signature<'static> {
+        <Vec<u8>>::signature()
+    }
+}
+
+// The handles of a prompt being shown
+struct Prompt {
+    cancel: Arc<Notify>,
+    labels: mpsc::UnboundedSender<String>,
+}
+
+struct Service {
+    requests: mpsc::Sender<Request>,
+    // The prompts being shown by id
+    prompts: Mutex<HashMap<String, Prompt>>,
+    next_id: AtomicU64,
+}
+
+#[interface(name = "io.github.user827.Xaskpass1")]
+impl Service {
+    /// Shows a dialog and returns the status and, on success, the UTF-8 encoded passphrase.
+    /// Each call gets its own window. Pass option `id` to be able to cancel it.
+    #[zbus(out_args("status", "passphrase"))]
+    async fn prompt(
+        &self,
+        label: String,
+        options: HashMap<String, OwnedValue>,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(signal_context)] ctx: SignalContext<'_>,
+    ) -> fdo::Result<(u32, Secret)> {
+        let options = Options::parse(options)?;
+        let id = options
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("#{}", self.next_id.fetch_add(1, Ordering::Relaxed)));
+        let cancel = Arc::new(Notify::new());
+        let (labels_tx, labels) = mpsc::unbounded_channel();
+        {
+            let mut prompts = self.prompts.lock().unwrap();
+            if prompts.contains_key(&id) {
+                return Err(fdo::Error::InvalidArgs(format!(
+                    "prompt {id} already shown"
+                )));
+            }
+            prompts.insert(
+                id.clone(),
+                Prompt {
+                    cancel: Arc::clone(&cancel),
+                    labels: labels_tx,
+                },
+            );
+        }
+        let outcome = tokio::select! {
+            outcome = self.request(label, options, Arc::clone(&cancel), labels) => outcome,
+            () = caller_gone(conn, header.sender()) => {
+                debug!("the caller of prompt {} left the bus", id);
+                cancel.notify_one();
+                Err(fdo::Error::Failed("caller left the bus".into()))
+            }
+        };
+        self.prompts.lock().unwrap().remove(&id);
+        let outcome = outcome?;
+        let status = outcome.status();
+        if let Err(err) = Self::completed(&ctx, &id, status).await {
+            debug!("completed signal failed: {}", err);
+        }
+        match outcome {
+            Outcome::Ok(pass) => Ok((status, Secret(pass.encode_utf8(false)))),
+            Outcome::Cancelled => Ok((status, Secret(SecBuf::new(Vec::new())))),
+            Outcome::Error(err) => Err(fdo::Error::Failed(err)),
+        }
+    }
+
+    /// Cancels all the dialogs being shown.
+    fn cancel(&self) {
+        for prompt in self.prompts.lock().unwrap().values() {
+            prompt.cancel.notify_one();
+        }
+    }
+
+    /// Cancels the dialog of the prompt with the given id.
+    fn cancel_prompt(&self, id: &str) -> fdo::Result<()> {
+        self.prompts
+            .lock()
+            .unwrap()
+            .get(id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no prompt {id}")))?
+            .cancel
+            .notify_one();
+        Ok(())
+    }
+
+    /// Replaces the label of the dialog of the prompt with the given id.
+    fn update_label(&self, id: &str, label: String) -> fdo::Result<()> {
+        self.prompts
+            .lock()
+            .unwrap()
+            .get(id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no prompt {id}")))?
+            .labels
+            .send(label)
+            .map_err(|_| fdo::Error::Failed(format!("prompt {id} is closing")))
+    }
+
+    /// Emitted when a prompt finishes. Never carries the passphrase.
+    #[zbus(signal)]
+    async fn completed(ctx: &SignalContext<'_>, id: &str, status: u32) -> zbus::Result<()>;
+}
+
+impl Service {
+    async fn request(
+        &self,
+        label: String,
+        options: Options,
+        cancel: Arc<Notify>,
+        labels: mpsc::UnboundedReceiver<String>,
+    ) -> fdo::Result<Outcome> {
+        let (reply, outcome) = oneshot::channel();
+        self.requests
+            .send(Request {
+                label,
+                options,
+                cancel,
+                labels,
+                reply,
+            })
+            .await
+            .map_err(|_| fdo::Error::Failed("shutting down".into()))?;
+        outcome
+            .await
+            .map_err(|_| fdo::Error::Failed("shutting down".into()))
+    }
+}
+
+// Resolves when the caller's unique name leaves the bus, never if that cannot be watched
+async fn caller_gone(conn: &zbus::Connection, sender: Option<&zbus::names::UniqueName<'_>>) {
+    let watch = async {
+        let Some(sender) = sender else {
+            return Ok(false);
+        };
+        let proxy = fdo::DBusProxy::new(conn).await?;
+        let mut changes = proxy
+            .receive_name_owner_changed_with_args(&[(0, sender.as_str())])
+            .await?;
+        // Gone before the match rule was added
+        if !proxy.name_has_owner(sender.as_ref().into()).await? {
+            return Ok(true);
+        }
+        while let Some(change) = changes.next().await {
+            if change.args()?.new_owner().is_none() {
+                return Ok(true);
+            }
+        }
+        zbus::Result::Ok(false)
+    };
+    match watch.await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(err) => debug!("could not watch the caller: {}", err),
+    }
+    std::future::pending::<()>().await;
+}
+
+pub struct Daemon {
+    _conn: zbus::Connection,
+    pub requests: mpsc::Receiver<Request>,
+}
+
+impl Daemon {
+    pub async fn new() -> Result<Self> {
+        let (tx, requests) = mpsc::channel(8);
+        let service = Service {
+            requests: tx,
+            prompts: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        };
+        let conn = zbus::connection::Builder::session()
+            .context("D-Bus session")?
+            .name(BUS_NAME)
+            .context("D-Bus name")?
+            .serve_at(OBJECT_PATH, service)
+            .context("D-Bus object")?
+            .build()
+            .await
+            .context("D-Bus connect")?;
+        Ok(Self {
+            _conn: conn,
+            requests,
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use log::debug;
+
+use super::{Action, Button, Label, Pattern, TextLabel};
+use crate::config;
+
+/// How long the clipboard button must be held down to open the chooser
+pub const LONG_PRESS: Duration = Duration::from_millis(500);
+
+/// Buttons for picking the selection to paste, shown after a long press on the clipboard button.
+#[derive(Debug)]
+pub struct Chooser {
+    buttons: [Button; 2],
+    open: bool,
+    spacing: f64,
+}
+
+impl Chooser {
+    const ACTIONS: [Action; 2] = [Action::PasteClipboard, Action::PastePrimary];
+
+    pub fn new(
+        config: &config::ClipboardButton,
+        pango_context: &pango::Context,
+        text_height: f64,
+    ) -> Self {
+        let button = |text: &str| {
+            let layout = pango::Layout::new(pango_context);
+            layout.set_text(text);
+            let label = Label::TextLabel(TextLabel::new(config.foreground.into(), layout));
+            let mut button = Button::new(config.button.clone(), label, text_height);
+            button.calc_label_position();
+            button
+        };
+        Self {
+            buttons: [
+                button(&config.clipboard_label),
+                button(&config.primary_label),
+            ],
+            open: false,
+            spacing: (text_height / 3.0).round(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens below the anchor or above it if there is no room within `height`.
+    pub fn open(&mut self, anchor: &Button, height: f64) {
+        debug!("opening the selection chooser");
+        let [ref mut first, ref mut second] = self.buttons;
+        second.x = (anchor.x + anchor.width - second.width).max(0.0);
+        first.x = (second.x - self.spacing - first.width).max(0.0);
+        let buttons_height = first.height.max(second.height);
+        let mut y = anchor.y + anchor.height + self.spacing;
+        if y + buttons_height > height {
+            y = (anchor.y - self.spacing - buttons_height).max(0.0);
+        }
+        for b in &mut self.buttons {
+            b.y = y;
+            b.set_hover(false);
+            b.set_pressed(false);
+        }
+        self.open = true;
+    }
+
+    pub fn close(&mut self) {
+        debug!("closing the selection chooser");
+        self.open = false;
+    }
+
+    pub fn buttons(&self) -> &[Button] {
+        if self.open {
+            &self.buttons
+        } else {
+            &[]
+        }
+    }
+
+    pub fn buttons_mut(&mut self) -> &mut [Button] {
+        if self.open {
+            &mut self.buttons
+        } else {
+            &mut []
+        }
+    }
+
+    /// Returns the action of the button released over.
+    pub fn release(&mut self, x: f64, y: f64) -> Option<Action> {
+        let i = self
+            .buttons
+            .iter()
+            .position(|b| b.pressed && b.is_inside(x, y));
+        for b in &mut self.buttons {
+            b.set_pressed(false);
+        }
+        i.map(|i| Self::ACTIONS[i])
+    }
+
+    pub fn paint(&self, cr: &cairo::Context, bg: &Pattern) {
+        if !self.open {
+            return;
+        }
+        // A margin so that it stands apart from what is below
+        let [ref first, ref second] = self.buttons;
+        cr.rectangle(
+            first.x - self.spacing,
+            first.y - self.spacing,
+            second.x + second.width - first.x + 2.0 * self.spacing,
+            first.height.max(second.height) + 2.0 * self.spacing,
+        );
+        cr.save().unwrap();
+        cr.set_operator(cairo::Operator::Source);
+        cr.set_source(bg).unwrap();
+        cr.fill().unwrap();
+        cr.restore().unwrap();
+        for b in &self.buttons {
+            b.paint(cr);
+        }
+    }
+
+    pub fn cairo_context_changed(&mut self, cr: &cairo::Context) {
+        for b in &mut self.buttons {
+            b.label.cairo_context_changed(cr);
+        }
+    }
+}
@@ -11,7 +11,7 @@ use tokio::time::{sleep, Instant, Sleep};
 use super::Pattern;
 use crate::config;
 use crate::errors::Result;
-use crate::secret::{Passphrase, SecBuf};
+use crate::secret::{Passphrase, SecBuf, Sensitive};
 
 mod ffi {
     #![allow(non_upper_case_globals)]
@@ -50,12 +50,15 @@ pub struct Base {
     pub(super) width: f64,
     pub(super) height: f64,
     border_width: f64,
+    border_mode: config::BorderMode,
     has_focus: bool,
     foreground: Pattern,
     background: Pattern,
     border_pattern: Pattern,
     border_pattern_focused: Pattern,
     indicator_pattern: Pattern,
+    // For rendering the gradients again
+    config: config::IndicatorCommon,
     dirty: bool,
     dirty_blink: bool,
     blink_enabled: bool,
@@ -63,6 +66,8 @@ pub struct Base {
     show_selection_do: bool,
     blink_timeout: Pin<Box<Sleep>>,
     show_selection_timeout: Pin<Box<Sleep>>,
+    // Shown at the end, e.g. the name of the new keyboard layout
+    notice: Option<pango::Layout>,
     pub pass: SecBuf<char>,
 }
 
@@ -74,11 +79,13 @@ impl Base {
             width: 0.0,
             height,
             border_width: config.border_width,
+            border_mode: config.border_mode,
             foreground: config.foreground.into(),
             background: Pattern::get_pattern(
                 height - config.border_width,
                 config.background,
                 config.background_stop,
+                None,
             ),
             border_pattern: config.border_color.into(),
             border_pattern_focused: config.border_color_focused.into(),
@@ -86,7 +93,9 @@ impl Base {
                 height - config.border_width,
                 config.indicator_color,
                 config.indicator_color_stop,
+                None,
             ),
+            config,
             has_focus: false,
             dirty: false,
             dirty_blink: false,
@@ -95,6 +104,7 @@ impl Base {
             show_selection_do: false,
             blink_timeout: Box::pin(sleep(Duration::from_millis(800))),
             show_selection_timeout: Box::pin(sleep(Duration::from_millis(0))),
+            notice: None,
             pass: SecBuf::new(vec!['X'; 512]),
         }
     }
@@ -103,6 +113,74 @@ impl Base {
         self.dirty || self.dirty_blink
     }
 
+    /// Takes the colors of the config keeping the geometry. The gradients are rendered again by
+    /// `set_dither`.
+    pub fn set_colors(&mut self, config: &config::IndicatorCommon) {
+        self.config = config::IndicatorCommon {
+            border_width: self.config.border_width,
+            border_mode: self.config.border_mode,
+            blink: self.config.blink,
+            feedback: self.config.feedback,
+            ..*config
+        };
+        self.foreground = config.foreground.into();
+        self.border_pattern = config.border_color.into();
+        self.border_pattern_focused = config.border_color_focused.into();
+        self.dirty = true;
+    }
+
+    /// Whether typing shows, see `IndicatorCommon::feedback`.
+    pub fn has_feedback(&self) -> bool {
+        self.config.feedback
+    }
+
+    pub fn set_notice(&mut self, notice: Option<pango::Layout>) {
+        self.notice = notice;
+        self.dirty = true;
+    }
+
+    // Drawn over the indicator after it has been painted
+    pub fn paint_notice(&self, cr: &cairo::Context) {
+        let Some(ref notice) = self.notice else {
+            return;
+        };
+        let (width, height) = notice.pixel_size();
+        let padding = (self.height / 4.0).round();
+        cr.save().unwrap();
+        cr.move_to(
+            self.x + self.width - self.border_width - padding - f64::from(width),
+            self.y + ((self.height - f64::from(height)) / 2.0).round(),
+        );
+        cr.set_source(&self.foreground).unwrap();
+        pangocairo::functions::layout_path(cr, notice);
+        cr.clip();
+        cr.paint_with_alpha(0.6).unwrap();
+        cr.restore().unwrap();
+    }
+
+    pub fn repaint_notice(&self, cr: &cairo::Context) {
+        if self.dirty {
+            self.paint_notice(cr);
+        }
+    }
+
+    pub fn set_dither(&mut self, scale: Option<f64>) {
+        let fill_height = self.height - self.border_width;
+        self.background = Pattern::get_pattern(
+            fill_height,
+            self.config.background,
+            self.config.background_stop,
+            scale,
+        );
+        self.indicator_pattern = Pattern::get_pattern(
+            fill_height,
+            self.config.indicator_color,
+            self.config.indicator_color_stop,
+            scale,
+        );
+        self.dirty = true;
+    }
+
     pub fn pass_delete(&mut self) {
         self.key_pressed();
         if self.pass.len > 0 {
@@ -240,6 +318,10 @@ impl Base {
         }
     }
 
+    pub fn is_focused(&self) -> bool {
+        self.has_focus
+    }
+
     pub fn set_focused(&mut self, is_focused: bool) {
         self.dirty = self.dirty || is_focused != self.has_focus;
         self.has_focus = is_focused;
@@ -275,6 +357,7 @@ impl Base {
 }
 
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Circle {
     pub base: Base,
     indicator_count: u32,
@@ -285,6 +368,12 @@ pub struct Circle {
     frame_increment: f64,
     frame_increment_start: f64,
     frame_increment_gain: f64,
+    easing: config::Easing,
+    // Shortest time between frames
+    frame_interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    frame_timeout: Pin<Box<Sleep>>,
+    frame_waiting: bool,
     angle: f64,
     animation_distance: f64,
     rotation: f64,
@@ -326,6 +415,8 @@ impl Circle {
             cursor_visible: config.blink,
             ..Base::new(config, diameter)
         };
+        // Without feedback it is only the lock
+        let feedback = config.feedback;
 
         let indicator_count = circle.indicator_count;
         let spacing_angle = circle
@@ -337,12 +428,20 @@ impl Circle {
             indicator_count,
             inner_radius,
             spacing_angle,
-            light_up: circle.light_up,
-            rotate: circle.rotate,
+            light_up: circle.light_up && feedback,
+            rotate: circle.rotate && feedback,
             lock_color: circle.lock_color.into(),
             frame_increment: frame_increment_start,
             frame_increment_start,
             frame_increment_gain: circle.rotation_speed_gain,
+            easing: circle.rotation_easing,
+            frame_interval: circle
+                .max_fps
+                .filter(|&fps| fps > 0)
+                .map(|fps| Duration::from_secs(1) / fps),
+            last_frame: None,
+            frame_timeout: Box::pin(sleep(Duration::ZERO)),
+            frame_waiting: false,
             angle: 2.0 * std::f64::consts::PI / f64::from(indicator_count),
             animation_distance: 0.0,
             rotation: 0.0,
@@ -389,7 +488,7 @@ impl Circle {
                 self.animation_distance -= FULL_ROUND;
             }
         }
-        if !self.paint_pending && self.animation_distance != 0.0 {
+        if !self.paint_pending && !self.frame_waiting && self.animation_distance != 0.0 {
             self.animate_frame();
         }
     }
@@ -401,16 +500,49 @@ impl Circle {
             trace!("not animating");
             return;
         }
+        let next = self
+            .last_frame
+            .zip(self.frame_interval)
+            .and_then(|(last, interval)| last.checked_add(interval))
+            .filter(|&next| next > Instant::now());
+        if let Some(next) = next {
+            trace!("frame capped");
+            self.frame_timeout.as_mut().reset(next);
+            self.frame_waiting = true;
+            return;
+        }
         self.animate_frame();
     }
 
+    pub async fn handle_events(&mut self) {
+        tokio::select! {
+            () = &mut self.frame_timeout, if self.frame_waiting => {
+                self.frame_waiting = false;
+                self.animate_frame();
+            }
+            () = self.base.handle_events() => {}
+        }
+    }
+
+    fn frame_step(&self) -> f64 {
+        // Stop easing out when closer than this
+        const MIN_STEP: f64 = 0.01;
+        match self.easing {
+            config::Easing::EaseIn => self.frame_increment,
+            config::Easing::Linear => self.frame_increment_start,
+            config::Easing::EaseOut => (self.animation_distance.abs() / 4.0).max(MIN_STEP),
+        }
+    }
+
     fn animate_frame(&mut self) {
         assert!(!self.paint_pending);
         self.paint_pending = true;
+        self.last_frame = Some(Instant::now());
         let mut animation_running = true;
+        let step = self.frame_step();
         if self.animation_distance > 0.0 {
-            self.rotation += self.frame_increment.min(self.animation_distance);
-            self.animation_distance -= self.frame_increment;
+            self.rotation += step.min(self.animation_distance);
+            self.animation_distance -= step;
             if self.animation_distance <= 0.0 {
                 animation_running = false;
             }
@@ -420,8 +552,8 @@ impl Circle {
                 self.rotation
             );
         } else {
-            self.rotation -= self.frame_increment.min(-self.animation_distance);
-            self.animation_distance += self.frame_increment;
+            self.rotation -= step.min(-self.animation_distance);
+            self.animation_distance += step;
             if self.animation_distance >= 0.0 {
                 animation_running = false;
             }
@@ -594,6 +726,7 @@ pub struct Classic {
     horizontal_spacing: f64,
     radius_x: f64,
     radius_y: f64,
+    fill: config::ClassicFill,
     indicators: Vec<Element>,
     pub base: Base,
 }
@@ -640,6 +773,7 @@ impl Classic {
             element_height,
             radius_x: classic.radius_x,
             radius_y: classic.radius_y,
+            fill: classic.fill,
             horizontal_spacing: classic
                 .horizontal_spacing
                 .unwrap_or_else(|| (text_height / 3.0).round()),
@@ -660,6 +794,7 @@ impl Classic {
         self.width = f64::from(indicator_count) * (self.element_width + self.horizontal_spacing)
             - self.horizontal_spacing;
 
+        self.indicators.clear();
         let mut x = 0.0;
         for _ in 0..indicator_count {
             let e = Element { x, y: 0.0 };
@@ -682,18 +817,22 @@ impl Classic {
         assert!(self.width != 0.0);
         cr.save().unwrap();
         cr.translate(self.x, self.y);
-        cr.set_line_width(self.border_width);
         for (ix, i) in self.indicators.iter().enumerate() {
-            let is_lid = self.pass.len > 0
-                && (self.show_selection_do || self.pass.len - 1 % self.indicators.len() == ix);
-            super::Button::rounded_rectangle(
+            let is_lid = self.has_feedback()
+                && self.pass.len > 0
+                && (self.show_selection_do || self.is_lit(ix));
+            super::Button::stroked_rectangle(
                 cr,
                 self.radius_x,
                 self.radius_y,
-                i.x + self.border_width / 2.0,
-                i.y + self.border_width / 2.0,
-                self.element_width - self.border_width,
-                self.element_height - self.border_width,
+                (
+                    i.x + self.border_width / 2.0,
+                    i.y + self.border_width / 2.0,
+                    self.element_width - self.border_width,
+                    self.element_height - self.border_width,
+                ),
+                self.border_width,
+                self.border_mode,
             );
             let bg = if is_lid {
                 &self.indicator_pattern
@@ -713,6 +852,25 @@ impl Classic {
         cr.restore().unwrap();
         trace!("paint end");
     }
+
+    fn is_lit(&self, ix: usize) -> bool {
+        let count = self.indicators.len();
+        let last = (self.pass.len - 1) % count;
+        match self.fill {
+            config::ClassicFill::Single => ix == last,
+            config::ClassicFill::Progressive => ix <= last,
+            config::ClassicFill::CenterOut => {
+                // How many boxes are closer to the center
+                let distance = |i: usize| (2 * i + 1).abs_diff(count);
+                let closer = (0..count)
+                    .filter(|&i| {
+                        distance(i) < distance(ix) || (distance(i) == distance(ix) && i < ix)
+                    })
+                    .count();
+                closer <= last
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -745,9 +903,91 @@ impl StringType {
             Self::Asterisk(asterisk) => asterisk.set_text(layout, pass),
         }
     }
+
+    // Byte range in the layout text showing the chars start..end
+    fn paste_range(&self, start: usize, end: usize) -> (usize, usize) {
+        match self {
+            Self::Asterisk(asterisk) => (
+                start * asterisk.characters.len(),
+                end * asterisk.characters.len(),
+            ),
+            // No mapping from the chars so the whole text
+            Self::Disco(..) | Self::Custom(..) => (0, usize::MAX),
+        }
+    }
+
+    fn attributes(&self, pass_len: usize) -> Option<pango::AttrList> {
+        match self {
+            Self::Asterisk(asterisk) => asterisk.attributes(pass_len),
+            Self::Disco(..) | Self::Custom(..) => None,
+        }
+    }
+
+    fn dances_on_timer(&self) -> bool {
+        match self {
+            Self::Disco(disco) => disco.dances_on_timer(),
+            Self::Custom(..) | Self::Asterisk(..) => false,
+        }
+    }
+
+    async fn beat(&mut self) {
+        match self {
+            Self::Disco(disco) => disco.beat().await,
+            Self::Custom(..) | Self::Asterisk(..) => std::future::pending().await,
+        }
+    }
+
+    fn pass_changed(&mut self) {
+        if let Self::Disco(disco) = self {
+            disco.reset_beat();
+        }
+    }
+
+    fn animation(&self) -> Option<config::AsteriskAnimation> {
+        match self {
+            Self::Asterisk(asterisk) => asterisk.animation,
+            Self::Disco(..) | Self::Custom(..) => None,
+        }
+    }
+}
+
+/// A character being typed or erased.
+#[derive(Debug)]
+struct Animation {
+    kind: config::AsteriskAnimation,
+    added: bool,
+    // Char index of an added character
+    index: usize,
+    // Horizontal center of the character in the layout
+    center: f64,
+    start: Instant,
+    progress: f64,
+}
+
+impl Animation {
+    const DURATION: Duration = Duration::from_millis(150);
+
+    fn alpha(&self) -> f64 {
+        match (self.kind, self.added) {
+            (config::AsteriskAnimation::Pop, true) => 1.0,
+            (_, true) => self.progress,
+            (_, false) => 1.0 - self.progress,
+        }
+    }
+
+    fn scale(&self) -> f64 {
+        let p = self.progress;
+        match (self.kind, self.added) {
+            (config::AsteriskAnimation::Fade, _) => 1.0,
+            // Overshoots a bit before settling
+            (config::AsteriskAnimation::Pop, true) => p + 0.5 * (std::f64::consts::PI * p).sin(),
+            (config::AsteriskAnimation::Pop, false) => 1.0 + 0.5 * p,
+        }
+    }
 }
 
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Strings {
     pub base: Base,
     content: StringType,
@@ -761,8 +1001,27 @@ pub struct Strings {
     blink_spacing: f64,
     layout: pango::Layout,
     show_plain: bool,
+    // Plain text that cannot be hidden, for the username of --basic-auth
+    always_plain: bool,
     cursor: usize,
     hover: bool,
+    paste_foreground: config::Rgba,
+    paste_underline: bool,
+    caret_shape: config::CaretShape,
+    caret_width: f64,
+    caret_pattern: Pattern,
+    plaintext_overflow: config::PlaintextOverflow,
+    // Layout width of the hidden passphrase
+    layout_width: i32,
+    // How far the text is scrolled left
+    scroll: f64,
+    // The chars inserted by the latest paste
+    pasted: (usize, usize),
+    // Shown underlined at the cursor until the compose sequence is finished
+    preedit: SecBuf<char>,
+    animation: Option<Animation>,
+    // Draws the animated character
+    glyph_layout: pango::Layout,
 }
 
 impl Deref for Strings {
@@ -808,6 +1067,7 @@ impl Strings {
             vertical_spacing, horizontal_spacing, config.border_width
         );
         let height = text_height.ceil() + 2.0 * vertical_spacing + 2.0 * config.border_width;
+        let caret_pattern = strings_cfg.caret_color.unwrap_or(config.foreground).into();
         let base = Base {
             ..Base::new(config, height)
         };
@@ -816,6 +1076,10 @@ impl Strings {
         layout.set_single_paragraph_mode(true);
 
         let blink_spacing = if content.use_cursor() { 0.0 } else { 8.0 };
+        let glyph_layout = pango::Layout::new(&layout.context());
+        if let StringType::Asterisk(asterisk) = &content {
+            glyph_layout.set_text(&asterisk.characters);
+        }
         Self {
             base,
             content,
@@ -826,8 +1090,21 @@ impl Strings {
             blink_spacing,
             layout,
             show_plain: false,
+            always_plain: false,
             cursor: 0,
             hover: false,
+            paste_foreground: strings_cfg.paste_foreground,
+            paste_underline: strings_cfg.paste_underline,
+            caret_shape: strings_cfg.caret_shape,
+            caret_width: strings_cfg.caret_width,
+            caret_pattern,
+            plaintext_overflow: strings_cfg.plaintext_overflow,
+            layout_width: -1,
+            scroll: 0.0,
+            pasted: (0, 0),
+            preedit: SecBuf::new(vec!['\0'; 16]),
+            animation: None,
+            glyph_layout,
         }
     }
 
@@ -852,10 +1129,13 @@ impl Strings {
 
     pub fn pass_clear(&mut self) {
         self.key_pressed();
+        self.content.pass_changed();
         if self.pass.len != 0 {
             self.cursor = 0;
             self.pass.len = 0;
+            self.animation = None;
             self.set_text();
+            self.scroll_to_cursor();
             self.dirty = true;
         }
     }
@@ -863,19 +1143,52 @@ impl Strings {
     pub fn pass_insert(&mut self, s: &str, pasted: bool) {
         trace!("pass insert {}", self.cursor);
         self.base.key_pressed();
+        self.content.pass_changed();
         let cursor = self.cursor;
         let inserted = self.pass.insert_many(cursor, s.chars(), s.chars().count());
         if inserted > 0 {
             if pasted {
+                self.pasted = (cursor, cursor + inserted);
                 self.show_selection();
             }
+            self.animation = None;
             self.set_text();
+            if !pasted && inserted == 1 {
+                self.animate(cursor, true);
+            }
             self.cursor += inserted;
+            self.scroll_to_cursor();
             self.dirty = true;
             trace!("pass inserted");
         }
     }
 
+    pub fn preedit_push(&mut self, c: char) {
+        if self.preedit.push(c) {
+            self.animation = None;
+            self.set_text();
+        }
+    }
+
+    pub fn preedit_clear(&mut self) {
+        if self.preedit.len > 0 {
+            self.preedit.len = 0;
+            self.set_text();
+        }
+    }
+
+    // The passphrase with the preedit inserted at the cursor
+    fn with_preedit(&self) -> SecBuf<char> {
+        let mut pass = SecBuf::new(vec!['\0'; self.pass.len + self.preedit.len]);
+        pass.insert_many(0, self.pass.unsecure().iter().copied(), self.pass.len);
+        pass.insert_many(
+            self.cursor,
+            self.preedit.unsecure().iter().copied(),
+            self.preedit.len,
+        );
+        pass
+    }
+
     fn get_log_attrs(layout: &pango::Layout) -> &[ffi::PangoLogAttr] {
         unsafe {
             let mut n_attrs: libc::c_int = 0;
@@ -921,7 +1234,8 @@ impl Strings {
         if log_attrs[self.cursor].backspace_deletes_character() == 1 {
             debug!(
                 "cursor {}, log_attrs: {:?}",
-                self.cursor, log_attrs[self.cursor]
+                self.cursor,
+                Sensitive(log_attrs[self.cursor])
             );
             self.cursor - 1
         } else {
@@ -931,7 +1245,8 @@ impl Strings {
                 new_cursor -= 1;
                 debug!(
                     "new_cursor: {}, log_attrs: {:?}",
-                    new_cursor, log_attrs[new_cursor]
+                    new_cursor,
+                    Sensitive(log_attrs[new_cursor])
                 );
             }
             new_cursor
@@ -941,6 +1256,7 @@ impl Strings {
     pub fn pass_delete(&mut self, word: bool) {
         trace!("pass delete {}", self.cursor);
         self.base.key_pressed();
+        self.content.pass_changed();
         let new_cursor = if word {
             self.move_backward_word()
         } else {
@@ -951,10 +1267,15 @@ impl Strings {
         }
         assert!(new_cursor < self.cursor);
         let old_index = self.cursor - 1;
+        self.animation = None;
+        if new_cursor == old_index {
+            self.animate(old_index, false);
+        }
         self.pass.delete(new_cursor, old_index);
         self.cursor = new_cursor;
         self.dirty = true;
         self.set_text();
+        self.scroll_to_cursor();
     }
 
     pub fn move_visually(&mut self, direction: Direction, word: bool) {
@@ -994,6 +1315,7 @@ impl Strings {
         if new_cursor != self.cursor {
             self.dirty = true;
             self.cursor = new_cursor;
+            self.scroll_to_cursor();
         }
     }
 
@@ -1022,16 +1344,45 @@ impl Strings {
     }
 
     pub fn for_width(&mut self, for_width: f64) {
+        // Measured unwrapped in case the width was set before
+        self.layout.set_width(-1);
         self.width = f64::from(self.content.for_width(&self.layout, for_width))
             + 2.0 * self.horizontal_spacing
             + self.blink_spacing
             + 2.0 * self.border_width;
+        self.layout_width = self.layout.width();
+        if self.pass.len > 0 {
+            // Relayout on resize: restore the text
+            self.animation = None;
+            if self.scrolls() {
+                self.layout.set_width(-1);
+            }
+            self.set_text();
+            self.scroll_to_cursor();
+        }
+    }
+
+    pub fn set_always_plain(&mut self) {
+        if !self.show_plain {
+            self.toggle_plaintext();
+        }
+        self.always_plain = true;
+    }
+
+    pub fn always_plain(&self) -> bool {
+        self.always_plain
     }
 
     pub fn toggle_plaintext(&mut self) {
         self.show_plain = !self.show_plain;
-        if self.show_plain {
+        self.animation = None;
+        if self.scrolls() {
+            self.layout.set_ellipsize(pango::EllipsizeMode::None);
+            self.layout.set_width(-1);
+        } else if self.show_plain {
             self.layout.set_ellipsize(pango::EllipsizeMode::Middle);
+        } else {
+            self.layout.set_width(self.layout_width);
         }
 
         self.set_text();
@@ -1040,6 +1391,97 @@ impl Strings {
         while self.cursor < log_attrs.len() && log_attrs[self.cursor].is_cursor_position() == 0 {
             self.cursor += 1;
         }
+        self.scroll_to_cursor();
+    }
+
+    // Starts animating the char at index, set_text must be up to date
+    fn animate(&mut self, index: usize, added: bool) {
+        let Some(kind) = self.content.animation() else {
+            return;
+        };
+        if self.show_plain {
+            return;
+        }
+        let (start, end) = self.content.paste_range(index, index + 1);
+        let x = |i: usize| {
+            let pos = self.layout.cursor_pos(i32::try_from(i).unwrap()).0;
+            f64::from(pos.x()) / f64::from(pango::SCALE)
+        };
+        self.animation = Some(Animation {
+            kind,
+            added,
+            index,
+            center: f64::midpoint(x(start), x(end)),
+            start: Instant::now(),
+            progress: 0.0,
+        });
+        if added {
+            // Hide it in the layout while it is drawn separately
+            self.set_text();
+        }
+    }
+
+    pub fn set_next_frame(&mut self) {
+        let Some(animation) = &mut self.animation else {
+            return;
+        };
+        animation.progress =
+            animation.start.elapsed().as_secs_f64() / Animation::DURATION.as_secs_f64();
+        if animation.progress >= 1.0 {
+            trace!("animation done");
+            self.animation = None;
+            self.set_text();
+        }
+        self.dirty = true;
+    }
+
+    fn paint_animation(&self, cr: &cairo::Context) {
+        let Some(animation) = &self.animation else {
+            return;
+        };
+        let (width, _) = self.glyph_layout.pixel_size();
+        cr.save().unwrap();
+        cr.translate(
+            animation.center,
+            f64::from(self.layout.pixel_size().1) / 2.0,
+        );
+        let scale = animation.scale().max(0.01);
+        cr.scale(scale, scale);
+        cr.translate(
+            -f64::from(width) / 2.0,
+            -f64::from(self.layout.pixel_size().1) / 2.0,
+        );
+        cr.move_to(0.0, 0.0);
+        pangocairo::functions::layout_path(cr, &self.glyph_layout);
+        cr.clip();
+        cr.set_source(&self.foreground).unwrap();
+        cr.paint_with_alpha(animation.alpha()).unwrap();
+        cr.restore().unwrap();
+    }
+
+    fn scrolls(&self) -> bool {
+        self.show_plain && self.plaintext_overflow == config::PlaintextOverflow::Scroll
+    }
+
+    // Scrolls just enough for the caret to be visible
+    fn scroll_to_cursor(&mut self) {
+        if !self.scrolls() {
+            self.scroll = 0.0;
+            return;
+        }
+        let visible = self.width
+            - 2.0 * self.horizontal_spacing
+            - self.blink_spacing
+            - 2.0 * self.border_width;
+        let text = f64::from(self.layout.pixel_size().0) + self.caret_width;
+        let caret = f64::from(self.layout.cursor_pos(self.cursor_bytes(self.cursor)).0.x())
+            / f64::from(pango::SCALE);
+        if caret < self.scroll {
+            self.scroll = caret;
+        } else if caret + self.caret_width > self.scroll + visible {
+            self.scroll = caret + self.caret_width - visible;
+        }
+        self.scroll = self.scroll.min(text - visible).max(0.0).round();
     }
 
     pub fn paint(&self, cr: &cairo::Context) {
@@ -1047,17 +1489,20 @@ impl Strings {
         assert!(self.width != 0.0);
         cr.save().unwrap();
         cr.translate(self.x, self.y);
-        super::Button::rounded_rectangle(
+        super::Button::stroked_rectangle(
             cr,
             self.radius_x,
             self.radius_y,
-            self.border_width / 2.0,
-            self.border_width / 2.0,
-            self.width - self.border_width,
-            self.height - self.border_width,
+            (
+                self.border_width / 2.0,
+                self.border_width / 2.0,
+                self.width - self.border_width,
+                self.height - self.border_width,
+            ),
+            self.border_width,
+            self.border_mode,
         );
         cr.set_source(&self.background).unwrap();
-        cr.set_line_width(self.border_width);
         cr.fill_preserve().unwrap();
         let bp = if self.has_focus {
             &self.border_pattern_focused
@@ -1072,9 +1517,23 @@ impl Strings {
             self.blink_spacing + self.horizontal_spacing + self.border_width,
             self.vertical_spacing + self.border_width,
         );
+        if self.scrolls() {
+            cr.rectangle(
+                0.0,
+                0.0,
+                self.width
+                    - 2.0 * self.horizontal_spacing
+                    - self.blink_spacing
+                    - 2.0 * self.border_width,
+                self.height - 2.0 * self.vertical_spacing - 2.0 * self.border_width,
+            );
+            cr.clip();
+            cr.translate(-self.scroll, 0.0);
+        }
         cr.set_source(&self.foreground).unwrap();
         cr.move_to(0.0, 0.0);
         pangocairo::functions::show_layout(cr, &self.layout);
+        self.paint_animation(cr);
         // TODO text is drawn too high
         // pangocairo::show_layout_line(&cr, &self.layout.get_line_readonly(self.layout.get_line_count() - 1).unwrap());
         cr.restore().unwrap();
@@ -1101,10 +1560,18 @@ impl Strings {
             () = &mut self.base.show_selection_timeout, if self.base.show_selection_do => {
                 self.on_show_selection_timeout();
             }
+            () = self.content.beat(), if self.dances() => {
+                trace!("beat");
+                self.set_text();
+            }
             else => std::future::pending().await
         }
     }
 
+    fn dances(&self) -> bool {
+        self.content.dances_on_timer() && !self.show_plain && self.pass.len > 0
+    }
+
     // TODO
     pub fn repaint(&self, cr: &cairo::Context, background: &super::Pattern) {
         if self.dirty {
@@ -1128,7 +1595,7 @@ impl Strings {
             let (inside, idx, trailing) = self.layout.xy_to_index(
                 min(
                     max(
-                        ((x - self.x
+                        ((x - self.x + self.scroll
                             - self.blink_spacing
                             - self.horizontal_spacing
                             - self.border_width)
@@ -1149,6 +1616,7 @@ impl Strings {
             if inside {
                 self.key_pressed();
                 self.cursor = self.cursor_chars(idx, trailing);
+                self.scroll_to_cursor();
                 self.dirty = true;
                 return true;
             }
@@ -1163,9 +1631,16 @@ impl Strings {
     }
 
     fn set_text(&mut self) {
+        let spliced;
+        let pass = if self.preedit.len > 0 {
+            spliced = self.with_preedit();
+            &spliced
+        } else {
+            &self.base.pass
+        };
         if self.show_plain {
-            let mut buf: SecBuf<u8> = SecBuf::new(vec![0; 4 * self.pass.len]);
-            for c in self.pass.unsecure() {
+            let mut buf: SecBuf<u8> = SecBuf::new(vec![0; 4 * pass.len]);
+            for c in pass.unsecure() {
                 let ret = c.encode_utf8(&mut buf.buf.unsecure_mut()[buf.len..]);
                 buf.len += ret.len();
             }
@@ -1174,49 +1649,136 @@ impl Strings {
             self.layout.set_text(s);
         } else {
             self.content
-                .set_text(&self.layout, &self.base.pass, self.show_selection_do);
+                .set_text(&self.layout, pass, self.show_selection_do);
+        }
+        let mut attributes = if self.show_plain {
+            None
+        } else {
+            self.content.attributes(pass.len)
+        };
+        if self.show_selection_do {
+            self.paste_attributes(attributes.get_or_insert_with(pango::AttrList::new));
+        }
+        if self.preedit.len > 0 {
+            let (start, end) = self.char_range(pass, self.cursor, self.cursor + self.preedit.len);
+            let mut attr = pango::AttrInt::new_underline(pango::Underline::Single);
+            attr.set_start_index(u32::try_from(start).unwrap_or(u32::MAX));
+            attr.set_end_index(u32::try_from(end).unwrap_or(u32::MAX));
+            attributes
+                .get_or_insert_with(pango::AttrList::new)
+                .insert(attr);
+        }
+        if let Some(animation) = self.animation.as_ref().filter(|a| a.added) {
+            let (start, end) = self
+                .content
+                .paste_range(animation.index, animation.index + 1);
+            // Alpha 0 would mean opaque
+            let mut attr = pango::AttrInt::new_foreground_alpha(1);
+            attr.set_start_index(u32::try_from(start).unwrap());
+            attr.set_end_index(u32::try_from(end).unwrap());
+            attributes
+                .get_or_insert_with(pango::AttrList::new)
+                .insert(attr);
         }
+        self.layout.set_attributes(attributes.as_ref());
         self.dirty = true;
     }
 
+    // Byte range in the layout text of the chars start..end of pass
+    fn char_range(&self, pass: &SecBuf<char>, start: usize, end: usize) -> (usize, usize) {
+        if self.show_plain {
+            let bytes = |chars: &[char]| chars.iter().map(|c| c.len_utf8()).sum::<usize>();
+            let pass = pass.unsecure();
+            (bytes(&pass[..start]), bytes(&pass[..end]))
+        } else {
+            self.content.paste_range(start, end)
+        }
+    }
+
+    fn paste_attributes(&self, list: &pango::AttrList) {
+        // The passphrase might have been edited since
+        let start = self.pasted.0.min(self.pass.len);
+        let end = self.pasted.1.min(self.pass.len);
+        let (start, end) = self.char_range(&self.pass, start, end);
+        let index = |i: usize| u32::try_from(i).unwrap_or(u32::MAX);
+        let color = |c: u8| u16::from(c) * 257;
+        let fg = self.paste_foreground;
+        let mut attributes: Vec<pango::Attribute> = vec![
+            pango::AttrColor::new_foreground(color(fg.red), color(fg.green), color(fg.blue)).into(),
+            pango::AttrInt::new_foreground_alpha(color(fg.alpha)).into(),
+        ];
+        if self.paste_underline {
+            attributes.push(pango::AttrInt::new_underline(pango::Underline::Single).into());
+        }
+        for mut attr in attributes {
+            attr.set_start_index(index(start));
+            attr.set_end_index(index(end));
+            list.insert(attr);
+        }
+    }
+
     fn blink(&self, cr: &cairo::Context) {
-        if self.has_focus && self.cursor_visible {
-            let pos = if self.show_plain || self.content.use_cursor() {
-                let pos = self.layout.cursor_pos(self.cursor_bytes(self.cursor));
-                (pos.0.x(), pos.1.x())
-            } else {
-                (0, 0)
-            };
-            self.base.blink(
+        if !self.has_focus || !self.cursor_visible {
+            self.paint(cr);
+            return;
+        }
+        let uses_cursor = self.show_plain || self.content.use_cursor();
+        let pos = if uses_cursor {
+            let pos = self.layout.cursor_pos(self.cursor_bytes(self.cursor));
+            (pos.0.x(), pos.1.x())
+        } else {
+            (0, 0)
+        };
+        let x = |pos: i32| {
+            self.border_width
+                + self.horizontal_spacing
+                + (f64::from(pos) / f64::from(pango::SCALE)).round()
+                + self.blink_spacing
+                - self.scroll
+        };
+        let y = self.vertical_spacing + self.border_width;
+        let height = self.height - 2.0 * self.vertical_spacing - 2.0 * self.border_width;
+        // The width of the character after the cursor
+        let char_width = if uses_cursor {
+            let rect = self.layout.index_to_pos(self.cursor_bytes(self.cursor));
+            f64::from(rect.width().abs()) / f64::from(pango::SCALE)
+        } else {
+            0.0
+        };
+        let char_width = if char_width > 0.0 {
+            char_width
+        } else {
+            (height / 2.0).round()
+        };
+
+        cr.save().unwrap();
+        cr.translate(self.x, self.y);
+        cr.set_source(&self.caret_pattern).unwrap();
+        let x_strong = x(pos.0).floor();
+        match self.caret_shape {
+            config::CaretShape::Bar => {
+                super::snapped_rectangle(cr, x_strong, y, self.caret_width, height);
+            }
+            config::CaretShape::Underline => super::snapped_rectangle(
                 cr,
-                self.height - 2.0 * self.vertical_spacing - 2.0 * self.border_width,
-                self.border_width
-                    + self.horizontal_spacing
-                    + (f64::from(pos.0) / f64::from(pango::SCALE)).round()
-                    + self.blink_spacing,
-                self.vertical_spacing + self.border_width,
-                None,
-                true,
-                1.0,
-            );
-            if pos.0 != pos.1 {
-                debug!("strong cursor: {}, weak cursor: {}", pos.0, pos.1);
-                self.base.blink(
-                    cr,
-                    self.height - 2.0 * self.vertical_spacing - 2.0 * self.border_width,
-                    self.border_width
-                        + self.horizontal_spacing
-                        + (f64::from(pos.1) / f64::from(pango::SCALE)).round()
-                        + self.blink_spacing,
-                    self.vertical_spacing + self.border_width,
-                    None,
-                    false,
-                    0.5,
-                );
+                x_strong,
+                y + height - self.caret_width,
+                char_width,
+                self.caret_width,
+            ),
+            config::CaretShape::Block => {
+                cr.set_operator(cairo::Operator::Difference);
+                super::snapped_rectangle(cr, x_strong, y, char_width, height);
             }
-        } else {
-            self.paint(cr);
         }
+        cr.fill().unwrap();
+        if pos.0 != pos.1 {
+            debug!("strong cursor: {}, weak cursor: {}", pos.0, pos.1);
+            cr.set_operator(cairo::Operator::Over);
+            super::snapped_rectangle(cr, x(pos.1), y, self.caret_width / 2.0, height);
+            cr.fill().unwrap();
+        }
+        cr.restore().unwrap();
     }
 }
 
@@ -1268,32 +1830,57 @@ struct Disco {
     dancer_max_width: f64,
     separator_width: f64,
     dancer_count: u16,
+    // Moves made on the timer
+    beats: usize,
+    beat_interval: Option<Duration>,
+    beat_timeout: Pin<Box<Sleep>>,
     config: config::Disco,
 }
 
 impl Disco {
-    pub const DANCER: &'static [&'static str] = &["┗(･o･)┛", "┏(･o･)┛", "┗(･o･)┓", "┏(･o･)┓"];
-    pub const SEPARATOR: &'static str = " ♪ ";
-
     pub fn new(config: config::Disco, layout: &pango::Layout) -> Self {
         trace!("disco new start");
-        let strings = Self::DANCER;
-        let sizes = strings.iter().map(|s| {
+        let sizes = config.dancers.iter().map(|s| {
             layout.set_text(s);
             layout.pixel_size().0
         });
         // every string with the same font should have the same logical height
         let dancer_max_width = f64::from(sizes.max().unwrap());
+        layout.set_text(&config.separator);
+        let separator_width = f64::from(layout.pixel_size().0);
         layout.set_text("");
+        let beat_interval = config
+            .tempo
+            .filter(|&bpm| bpm > 0)
+            .map(|bpm| Duration::from_mins(1) / bpm);
         trace!("disco new end");
         Self {
             dancer_max_width,
-            separator_width: f64::from(layout.pixel_size().1),
+            separator_width,
             config,
             dancer_count: 0,
+            beats: 0,
+            beat_interval,
+            beat_timeout: Box::pin(sleep(Duration::ZERO)),
         }
     }
 
+    pub fn dances_on_timer(&self) -> bool {
+        self.beat_interval.is_some()
+    }
+
+    /// Resolves on the next beat.
+    pub async fn beat(&mut self) {
+        let Some(interval) = self.beat_interval else {
+            return std::future::pending().await;
+        };
+        self.beat_timeout.as_mut().await;
+        self.beats = self.beats.wrapping_add(1);
+        self.beat_timeout
+            .as_mut()
+            .reset(Instant::now().checked_add(interval).unwrap());
+    }
+
     pub fn for_width(&mut self, layout: &pango::Layout, for_width: f64) -> i32 {
         trace!("for_width start");
         self.dancer_count = min(
@@ -1305,8 +1892,7 @@ impl Disco {
             ),
             self.config.max_count,
         );
-        let last = if self.config.three_states { 4 } else { 3 };
-        let width = (0..last)
+        let width = (0..=self.states())
             .map(|l| {
                 self.set_text_do(layout, l, l == 0);
                 layout.pixel_size().0
@@ -1326,25 +1912,41 @@ impl Disco {
         self.set_text_do(layout, pass.len, show_paste);
     }
 
+    // Keystrokes move the dancers so the next beat waits a full interval
+    fn reset_beat(&mut self) {
+        if let Some(interval) = self.beat_interval {
+            self.beat_timeout
+                .as_mut()
+                .reset(Instant::now().checked_add(interval).unwrap());
+        }
+    }
+
+    fn states(&self) -> usize {
+        if self.config.three_states {
+            self.config.dancers.len() - 1
+        } else {
+            2
+        }
+    }
+
     fn set_text_do(&mut self, layout: &pango::Layout, pass_len: usize, show_paste: bool) {
         if pass_len == 0 && !show_paste {
             layout.set_text("");
             return;
         }
         let mut buf = String::with_capacity(
-            (Self::DANCER[0].len() + Self::SEPARATOR.len()) * usize::from(self.dancer_count),
+            (self.config.dancers[0].len() + self.config.separator.len())
+                * usize::from(self.dancer_count),
         );
         for i in 0..self.dancer_count {
-            let states = if self.config.three_states { 3 } else { 2 };
-            let idx: usize = if show_paste {
+            let idx = if show_paste {
                 0
             } else {
-                (pass_len % states) as u8 + 1
-            }
-            .into();
-            buf.push_str(Self::DANCER[idx]);
+                pass_len.wrapping_add(self.beats) % self.states() + 1
+            };
+            buf.push_str(&self.config.dancers[idx]);
             if i + 1 != self.dancer_count {
-                buf.push_str(Self::SEPARATOR);
+                buf.push_str(&self.config.separator);
             }
         }
         layout.set_text(&buf);
@@ -1355,6 +1957,9 @@ impl Disco {
 struct Asterisk {
     width: f64,
     characters: String,
+    // Letter spacing for each of the characters to fill a cell
+    cell_spacing: Option<Vec<i32>>,
+    animation: Option<config::AsteriskAnimation>,
     count: u16,
     min_count: u16,
     max_count: u16,
@@ -1362,14 +1967,30 @@ struct Asterisk {
 
 impl Asterisk {
     pub fn new(config: config::Asterisk, layout: &pango::Layout) -> Self {
-        let characters: String = config.asterisk;
+        let characters: String = config
+            .preset
+            .map_or(config.asterisk, |preset| preset.as_str().to_owned());
         layout.set_text(&characters);
-        let (asterisk_width, _) = layout.pixel_size();
+        let (mut asterisk_width, _) = layout.pixel_size();
+        let cell_spacing = config.cell_width.map(|cell_width| {
+            let n = u32::try_from(characters.chars().count()).unwrap_or(u32::MAX);
+            asterisk_width = (cell_width * f64::from(n)).ceil() as i32;
+            let cell = (cell_width * f64::from(pango::SCALE)).round() as i32;
+            characters
+                .chars()
+                .map(|c| {
+                    layout.set_text(c.encode_utf8(&mut [0; 4]));
+                    cell - layout.size().0
+                })
+                .collect()
+        });
         layout.set_alignment(config.alignment.into());
         layout.set_text("");
         Self {
             width: f64::from(asterisk_width),
             characters,
+            cell_spacing,
+            animation: config.animation,
             min_count: config.min_count,
             max_count: config.max_count,
             count: 0,
@@ -1382,12 +2003,32 @@ impl Asterisk {
             self.max_count,
         );
         layout.set_text(&self.characters.repeat(self.count.into()));
+        layout.set_attributes(self.attributes(self.count.into()).as_ref());
         let w = layout.pixel_size().0;
         layout.set_width(w * pango::SCALE);
         layout.set_text("");
+        layout.set_attributes(None);
         w
     }
 
+    /// Spacing that centers each character of `count` repetitions in its cell.
+    pub fn attributes(&self, count: usize) -> Option<pango::AttrList> {
+        let cell_spacing = self.cell_spacing.as_ref()?;
+        let list = pango::AttrList::new();
+        let mut start = 0;
+        for _ in 0..count {
+            for (c, &spacing) in self.characters.chars().zip(cell_spacing) {
+                let end = start + c.len_utf8() as u32;
+                let mut attr = pango::AttrInt::new_letter_spacing(spacing);
+                attr.set_start_index(start);
+                attr.set_end_index(end);
+                list.insert(attr);
+                start = end;
+            }
+        }
+        Some(list)
+    }
+
     pub fn set_text(&mut self, layout: &pango::Layout, pass: &SecBuf<char>) {
         layout.set_ellipsize(pango::EllipsizeMode::Start);
 
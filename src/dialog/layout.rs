@@ -1,10 +1,11 @@
 use log::{debug, trace};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use super::{Components, Indicator};
-use crate::config;
+use super::{Components, Indicator, Indicators};
+use crate::config::{self, LogoPlacement};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub enum Layout {
     BottomLeft,
     Center,
@@ -12,6 +13,27 @@ pub enum Layout {
     TopRight,
 }
 
+/// A row of components that can be moved as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Row {
+    Label,
+    /// The indicator and the buttons next to it
+    Indicator,
+    /// Ok and Cancel
+    Buttons,
+}
+
+impl Row {
+    pub const ALL: [Row; 3] = [Row::Label, Row::Indicator, Row::Buttons];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Side {
+    Left,
+    Right,
+    Above,
+}
+
 impl Layout {
     pub fn get_fn(self) -> fn(&config::Layout, &mut Components, &mut Indicator) -> (f64, f64) {
         match self {
@@ -23,6 +45,354 @@ impl Layout {
     }
 }
 
+/// Window size limits in user space.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub padding: f64,
+    pub min_width: Option<f64>,
+    pub max_width: Option<f64>,
+    pub min_height: Option<f64>,
+    pub max_height: Option<f64>,
+}
+
+/// Runs the configured layout within the limits, wrapping the label narrower if the dialog
+/// is too wide.
+pub fn arrange(
+    config: &config::Layout,
+    limits: &Limits,
+    components: &mut Components,
+    indicators: &mut Indicators,
+) -> (f64, f64) {
+    let (mut width, mut height) = arrange_do(config, components, indicators);
+    if let Some(max_width) = limits.max_width {
+        let max_width = max_width - 2.0 * limits.padding;
+        let mut config = config.clone();
+        // Wrapping does not always make it narrow enough on the first try
+        for _ in 0..4 {
+            if width <= max_width {
+                break;
+            }
+            let text_width = (components.label().width - (width - max_width)).floor();
+            if text_width < 1.0 {
+                break;
+            }
+            debug!("dialog too wide, wrapping the label to {}", text_width);
+            config.text_width = Some(text_width as u32);
+            (width, height) = arrange_do(&config, components, indicators);
+        }
+    }
+
+    let mut dx = limits.padding;
+    let mut dy = limits.padding;
+    width += 2.0 * limits.padding;
+    height += 2.0 * limits.padding;
+    if let Some(min_width) = limits.min_width.filter(|&w| w > width) {
+        dx += ((min_width - width) / 2.0).floor();
+        width = min_width;
+    }
+    if let Some(min_height) = limits.min_height.filter(|&h| h > height) {
+        dy += ((min_height - height) / 2.0).floor();
+        height = min_height;
+    }
+    translate(components, indicators, dx, dy);
+    width = limits.max_width.map_or(width, |w| width.min(w));
+    height = limits.max_height.map_or(height, |h| height.min(h));
+    (width, height)
+}
+
+fn arrange_do(
+    config: &config::Layout,
+    components: &mut Components,
+    indicators: &mut Indicators,
+) -> (f64, f64) {
+    let (width, height) = config.layout.get_fn()(config, components, &mut indicators.main);
+    let height = place_secondary(config, components, indicators, height);
+    let height = stack_rows(config, components, indicators, height);
+    place_logo(config, components, indicators, width, height)
+}
+
+// Moves everything else aside for the logo.
+fn place_logo(
+    config: &config::Layout,
+    components: &mut Components,
+    indicators: &mut Indicators,
+    width: f64,
+    height: f64,
+) -> (f64, f64) {
+    let Some(mut logo) = components.logo.take() else {
+        return (width, height);
+    };
+    let horizontal_spacing = config.horizontal_spacing(components.text_height);
+    let vertical_spacing = config.vertical_spacing(components.text_height);
+    let size = &mut logo.rectangle;
+    let (width, height) = match logo.placement {
+        LogoPlacement::Left | LogoPlacement::Right => {
+            let label_y = components.label().y;
+            let dx = size.width + horizontal_spacing;
+            if logo.placement == LogoPlacement::Left {
+                translate(components, indicators, dx, 0.0);
+                size.x = horizontal_spacing;
+            } else {
+                size.x = width;
+            }
+            size.y = label_y;
+            (
+                width + dx,
+                height.max(size.y + size.height + vertical_spacing),
+            )
+        }
+        LogoPlacement::Top => {
+            let new_width = width.max(size.width + 2.0 * horizontal_spacing);
+            let dx = ((new_width - width) / 2.0).floor();
+            translate(components, indicators, dx, size.height + vertical_spacing);
+            size.x = ((new_width - size.width) / 2.0).floor();
+            size.y = vertical_spacing;
+            (new_width, height + size.height + vertical_spacing)
+        }
+    };
+    components.logo = Some(logo);
+    (width, height)
+}
+
+fn translate(components: &mut Components, indicators: &mut Indicators, dx: f64, dy: f64) {
+    for b in &mut components.buttons {
+        b.x += dx;
+        b.y += dy;
+    }
+    for l in &mut components.labels {
+        l.x += dx;
+        l.y += dy;
+    }
+    if let Some(ref mut logo) = components.logo {
+        logo.rectangle.x += dx;
+        logo.rectangle.y += dy;
+    }
+    indicators.main.x += dx;
+    indicators.main.y += dy;
+    for i in &mut indicators.secondary {
+        i.x += dx;
+        i.y += dy;
+    }
+}
+
+// Stacks the secondary indicators under the main one and moves what is below them down.
+fn place_secondary(
+    config: &config::Layout,
+    components: &mut Components,
+    indicators: &mut Indicators,
+    height: f64,
+) -> f64 {
+    if indicators.secondary.is_empty() {
+        return height;
+    }
+    let bottom = |components: &Components, indicators: &Indicators| {
+        let buttons = components.buttons.iter().map(|b| b.y + b.height);
+        let labels = components.labels.iter().map(|l| l.y + l.height);
+        let indicators = indicators.all().map(|i| i.y + i.height);
+        buttons.chain(labels).chain(indicators).fold(0.0, f64::max)
+    };
+    let old_bottom = bottom(components, indicators);
+    let spacing = config.indicator_spacing(components.text_height);
+    let main = &indicators.main;
+    let (x, width, below) = (main.x, main.width, main.y + main.height);
+    let mut y = below;
+    for i in &mut indicators.secondary {
+        i.for_width(width);
+        i.x = (x + (width - i.width) / 2.0).floor();
+        i.y = y + spacing;
+        y = i.y + i.height;
+    }
+    let dy = y - below;
+    debug!("secondary indicators take {}", dy);
+    for b in &mut components.buttons {
+        if b.y >= below {
+            b.y += dy;
+        }
+    }
+    for l in &mut components.labels {
+        if l.y >= below {
+            l.y += dy;
+        }
+    }
+    height + bottom(components, indicators) - old_bottom
+}
+
+// Rows that overlap vertically, like the indicator and the buttons in MiddleCompact, form a
+// band that moves as one. The bands are stacked in the new order keeping the gaps between them
+// and adding the margins.
+fn stack_rows(
+    config: &config::Layout,
+    components: &mut Components,
+    indicators: &mut Indicators,
+    height: f64,
+) -> f64 {
+    let order = &config.rows;
+    let rank = |row: Row| order.iter().position(|&r| r == row).unwrap_or(order.len());
+    let margin = |row: Row| match row {
+        Row::Label => config.label_margin,
+        Row::Indicator => config.indicator_margin,
+        Row::Buttons => config.buttons_margin,
+    };
+    let mut rows: Vec<(Row, f64, f64)> = Row::ALL
+        .iter()
+        .filter_map(|&row| {
+            let (top, bottom) = row_extents(row, components, indicators)?;
+            Some((row, top, bottom))
+        })
+        .collect();
+    rows.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    // (rows, top, bottom)
+    let mut bands: Vec<(Vec<Row>, f64, f64)> = Vec::new();
+    for (row, top, bottom) in rows {
+        match bands.last_mut() {
+            Some(band) if top < band.2 => {
+                band.0.push(row);
+                band.2 = band.2.max(bottom);
+            }
+            _ => bands.push((vec![row], top, bottom)),
+        }
+    }
+    let mut gaps: Vec<f64> = bands.windows(2).map(|w| w[1].1 - w[0].2).collect();
+    let Some(first) = bands.first() else {
+        return height;
+    };
+    let mut y = first.1;
+    gaps.push(0.0);
+
+    let band_rank = |band: &(Vec<Row>, f64, f64)| band.0.iter().map(|&r| rank(r)).min();
+    let band_margin =
+        |band: &(Vec<Row>, f64, f64)| band.0.iter().map(|&r| margin(r)).fold(0.0, f64::max);
+    let margins: f64 = bands.iter().map(|b| 2.0 * band_margin(b)).sum();
+    bands.sort_by_key(band_rank);
+    for (band, gap) in bands.into_iter().zip(gaps) {
+        let band_margin = band_margin(&band);
+        let (band_rows, top, bottom) = band;
+        y += band_margin;
+        let dy = y - top;
+        debug!("moving rows {:?} by {}", band_rows, dy);
+        for row in band_rows {
+            shift_row(row, dy, components, indicators);
+        }
+        y = bottom + dy + band_margin + gap;
+    }
+    height + margins
+}
+
+fn row_extents(
+    row: Row,
+    components: &mut Components,
+    indicators: &Indicators,
+) -> Option<(f64, f64)> {
+    let extents = |r: &super::Rectangle| (r.y, r.y + r.height);
+    let mut all = match row {
+        Row::Label => vec![extents(components.label())],
+        Row::Indicator => {
+            let mut all: Vec<_> = indicators.all().map(|i| (i.y, i.y + i.height)).collect();
+            all.extend(
+                components
+                    .buttons
+                    .iter()
+                    .skip(2)
+                    .map(|b| (b.y, b.y + b.height)),
+            );
+            all.extend(components.labels.iter().skip(1).map(|l| extents(l)));
+            all
+        }
+        Row::Buttons => components
+            .buttons
+            .iter()
+            .take(2)
+            .map(|b| (b.y, b.y + b.height))
+            .collect(),
+    }
+    .into_iter();
+    let first = all.next()?;
+    Some(all.fold(first, |(top, bottom), (t, b)| (top.min(t), bottom.max(b))))
+}
+
+fn shift_row(row: Row, dy: f64, components: &mut Components, indicators: &mut Indicators) {
+    match row {
+        Row::Label => components.label().y += dy,
+        Row::Indicator => {
+            indicators.main.y += dy;
+            for i in &mut indicators.secondary {
+                i.y += dy;
+            }
+            for b in components.buttons.iter_mut().skip(2) {
+                b.y += dy;
+            }
+            for l in components.labels.iter_mut().skip(1) {
+                l.y += dy;
+            }
+        }
+        Row::Buttons => {
+            for b in components.buttons.iter_mut().take(2) {
+                b.y += dy;
+            }
+        }
+    }
+}
+
+// The size of the indicator together with its label.
+fn indicator_box(
+    config: &config::Layout,
+    components: &mut Components,
+    indicator: &Indicator,
+) -> (f64, f64) {
+    if !components.has_indicator_label() {
+        return (indicator.width, indicator.height);
+    }
+    let spacing = config.indicator_spacing(components.text_height);
+    let label = components.indicator_label();
+    label.calc_extents(None, false);
+    match config.indicator_label_side {
+        Side::Left | Side::Right => (
+            indicator.width + spacing + label.width,
+            indicator.height.max(label.height),
+        ),
+        Side::Above => (
+            indicator.width.max(label.width),
+            label.height + spacing + indicator.height,
+        ),
+    }
+}
+
+// Places the indicator and its label in the box at x, y sized by indicator_box.
+fn place_indicator_box(
+    config: &config::Layout,
+    components: &mut Components,
+    indicator: &mut Indicator,
+    (x, y, width, height): (f64, f64, f64, f64),
+) {
+    if !components.has_indicator_label() {
+        indicator.x = x;
+        indicator.y = y;
+        return;
+    }
+    let spacing = config.indicator_spacing(components.text_height);
+    let label = components.indicator_label();
+    match config.indicator_label_side {
+        Side::Left | Side::Right => {
+            label.y = y + ((height - label.height) / 2.0).floor();
+            indicator.y = y + ((height - indicator.height) / 2.0).floor();
+            if config.indicator_label_side == Side::Left {
+                label.x = x;
+                indicator.x = x + label.width + spacing;
+            } else {
+                indicator.x = x;
+                label.x = x + indicator.width + spacing;
+            }
+        }
+        Side::Above => {
+            label.x = x + ((width - label.width) / 2.0).floor();
+            label.y = y;
+            indicator.x = x + ((width - indicator.width) / 2.0).floor();
+            indicator.y = y + label.height + spacing;
+        }
+    }
+}
+
 pub fn bottom_left(
     config: &config::Layout,
     components: &mut Components,
@@ -30,16 +400,14 @@ pub fn bottom_left(
 ) -> (f64, f64) {
     let horizontal_spacing: f64 = config.horizontal_spacing(components.text_height);
     indicator.for_width(components.ok().width);
-    let buttonind_area_width = (4.0 * horizontal_spacing)
-        + components.ok().width
-        + components.cancel().width
-        + indicator.width;
+    let (box_width, box_height) = indicator_box(config, components, indicator);
+    let buttonind_area_width =
+        (4.0 * horizontal_spacing) + components.ok().width + components.cancel().width + box_width;
     components.label().calc_extents(config.text_width, true);
     let label_area_width = components.label().width + (2.0 * horizontal_spacing);
     let width = label_area_width.max(buttonind_area_width);
     // floor instead of round so these stay within the widths specified above
-    let inter_buttonind_space = ((width - components.ok().width - indicator.width) / 3.0).floor();
-    indicator.x = inter_buttonind_space;
+    let inter_buttonind_space = ((width - components.ok().width - box_width) / 3.0).floor();
     components.label().x = horizontal_spacing;
     components.ok().x = width - horizontal_spacing - components.ok().width;
     components.cancel().x = components.ok().x;
@@ -47,14 +415,20 @@ pub fn bottom_left(
     let vertical_spacing = config.vertical_spacing(components.text_height);
     let button_area_height: f64 =
         vertical_spacing + components.ok().height + components.cancel().height;
-    let buttonind_area_height = button_area_height.max(indicator.height);
+    let buttonind_area_height = button_area_height.max(box_height);
     let space = vertical_spacing;
     let height =
         (2.0 * vertical_spacing) + components.label().height + buttonind_area_height + space;
     components.label().y = vertical_spacing;
     components.ok().y = components.label().y + components.label().height + space;
-    indicator.y = components.ok().y
-        + (height - components.ok().y - indicator.height - vertical_spacing) / 2.0;
+    let box_y =
+        components.ok().y + (height - components.ok().y - box_height - vertical_spacing) / 2.0;
+    place_indicator_box(
+        config,
+        components,
+        indicator,
+        (inter_buttonind_space, box_y, box_width, box_height),
+    );
     components.cancel().y = components.ok().y + components.ok().height + vertical_spacing;
 
     (width, height)
@@ -67,32 +441,36 @@ pub fn middle_compact(
 ) -> (f64, f64) {
     let horizontal_spacing: f64 = config.horizontal_spacing(components.text_height);
     indicator.for_width(components.ok().width);
-    let buttonind_area_width = (8.0 * horizontal_spacing)
-        + components.ok().width
-        + components.cancel().width
-        + indicator.width;
+    let (box_width, box_height) = indicator_box(config, components, indicator);
+    let buttonind_area_width =
+        (8.0 * horizontal_spacing) + components.ok().width + components.cancel().width + box_width;
     components.label().calc_extents(config.text_width, true);
     let label_area_width = components.label().width + (2.0 * horizontal_spacing);
     let width = label_area_width.max(buttonind_area_width);
     components.label().x = (width - components.label().width) / 2.0;
-    let inter_space =
-        (width - components.ok().width - components.cancel().width - indicator.width) / 4.0;
+    let inter_space = (width - components.ok().width - components.cancel().width - box_width) / 4.0;
     components.ok().x = inter_space;
-    indicator.x = components.ok().x + components.ok().width + inter_space;
-    components.cancel().x = indicator.x + indicator.width + inter_space;
+    let box_x = components.ok().x + components.ok().width + inter_space;
+    components.cancel().x = box_x + box_width + inter_space;
 
     let vertical_spacing = config.vertical_spacing(components.text_height);
     let buttonind_area_height = components
         .ok()
         .height
         .max(components.cancel().height)
-        .max(indicator.height);
+        .max(box_height);
     let height = (3.0 * vertical_spacing) + components.label().height + buttonind_area_height;
     components.label().y = vertical_spacing;
     components.ok().y = height - vertical_spacing - components.ok().height;
     components.cancel().y = components.ok().y;
-    indicator.y = height - vertical_spacing - buttonind_area_height
-        + (buttonind_area_height - indicator.height) / 2.0;
+    let box_y = height - vertical_spacing - buttonind_area_height
+        + (buttonind_area_height - box_height) / 2.0;
+    place_indicator_box(
+        config,
+        components,
+        indicator,
+        (box_x, box_y, box_width, box_height),
+    );
     trace!(
         "buttonind_area_height: {}, indicator.height: {}, components.ok().height: {}",
         buttonind_area_height,
@@ -103,6 +481,7 @@ pub fn middle_compact(
 }
 
 // TODO mess
+#[allow(clippy::too_many_lines)]
 pub fn center(
     config: &config::Layout,
     components: &mut Components,
@@ -114,10 +493,15 @@ pub fn center(
     components.label().calc_extents(config.text_width, true);
     let label_area_width = components.label().width + (2.0 * horizontal_spacing);
     let w = label_area_width.max(button_area_width);
-    let indicator_spacing = (components.text_height / 4.0).round();
+    let indicator_spacing = config.indicator_spacing(components.text_height);
     debug!("layout indicator_spacing: {}", indicator_spacing);
-    let indicator_label_space = if matches!(indicator, Indicator::Circle(..)) {
+    let has_label = components.has_indicator_label();
+    let label_above = has_label && config.indicator_label_side == Side::Above;
+    let label_beside = has_label && !label_above;
+    if has_label {
         components.indicator_label().calc_extents(None, false);
+    }
+    let indicator_label_space = if label_beside {
         components.indicator_label().width + indicator_spacing
     } else {
         0.0
@@ -127,7 +511,7 @@ pub fn center(
         - components.clipboard().width
         - indicator_spacing
         - indicator_label_space;
-    if indicator.has_plaintext() {
+    if components.has_plaintext_button(indicator) {
         for_width -= components.plaintext().width + indicator_spacing;
     }
     indicator.for_width(for_width);
@@ -137,21 +521,39 @@ pub fn center(
         + indicator_spacing
         + indicator_label_space;
 
-    if indicator.has_plaintext() {
+    if components.has_plaintext_button(indicator) {
         indicator_area_width += components.plaintext().width + indicator_spacing;
     }
-    let width = w.max(indicator_area_width);
+    let mut width = w.max(indicator_area_width);
+    if label_above {
+        width = width.max(components.indicator_label().width + 2.0 * horizontal_spacing);
+    }
 
     let indicator_label_x =
         ((width - indicator_area_width + horizontal_spacing * 2.0) / 2.0).floor();
-    if matches!(indicator, Indicator::Circle(..)) {
-        components.indicator_label().x = indicator_label_x;
-    }
-    indicator.x = indicator_label_x + indicator_label_space;
+    let label_on_right = config.indicator_label_side == Side::Right;
+    indicator.x = if label_on_right {
+        indicator_label_x
+    } else {
+        indicator_label_x + indicator_label_space
+    };
     components.clipboard().x = indicator.x + indicator.width + indicator_spacing;
-    if indicator.has_plaintext() {
-        components.plaintext().x =
-            components.clipboard().x + components.clipboard().width + indicator_spacing;
+    let mut row_end = components.clipboard().x + components.clipboard().width;
+    if components.has_plaintext_button(indicator) {
+        components.plaintext().x = row_end + indicator_spacing;
+        row_end = components.plaintext().x + components.plaintext().width;
+    }
+    if label_beside {
+        components.indicator_label().x = if label_on_right {
+            row_end + indicator_spacing
+        } else {
+            indicator_label_x
+        };
+    } else if label_above {
+        let label_width = components.indicator_label().width;
+        components.indicator_label().x = ((indicator.x + (indicator.width - label_width) / 2.0)
+            .floor())
+        .clamp(horizontal_spacing, width - horizontal_spacing - label_width);
     }
     // floor instead of round so these stay within the widths specified above
     components.label().x = ((width - components.label().width) / 2.0).floor();
@@ -161,7 +563,7 @@ pub fn center(
     components.cancel().x = components.ok().x + components.ok().width + inter_button_space;
 
     let vertical_spacing = config.vertical_spacing(components.text_height);
-    let mut indicator_area_height = if matches!(indicator, Indicator::Circle(..)) {
+    let mut indicator_area_height = if label_beside {
         indicator
             .height
             .max(components.clipboard().height)
@@ -169,24 +571,33 @@ pub fn center(
     } else {
         indicator.height.max(components.clipboard().height)
     };
-    if indicator.has_plaintext() {
+    if components.has_plaintext_button(indicator) {
         indicator_area_height = indicator_area_height.max(components.plaintext().height);
     }
+    let label_above_height = if label_above {
+        components.indicator_label().height + indicator_spacing
+    } else {
+        0.0
+    };
     let height = (4.0 * vertical_spacing)
         + components.label().height
+        + label_above_height
         + indicator_area_height
         + components.ok().height;
 
     components.label().y = vertical_spacing;
-    let indicator_area_y = components.label().y + components.label().height + vertical_spacing;
-    if matches!(indicator, Indicator::Circle(..)) {
+    let indicator_area_y =
+        components.label().y + components.label().height + vertical_spacing + label_above_height;
+    if label_beside {
         components.indicator_label().y = indicator_area_y
             + ((indicator_area_height - components.indicator_label().height) / 2.0).floor();
+    } else if label_above {
+        components.indicator_label().y = indicator_area_y - label_above_height;
     }
     indicator.y = indicator_area_y + ((indicator_area_height - indicator.height) / 2.0).floor();
     components.clipboard().y =
         indicator_area_y + ((indicator_area_height - components.clipboard().height) / 2.0).floor();
-    if indicator.has_plaintext() {
+    if components.has_plaintext_button(indicator) {
         components.plaintext().y = indicator_area_y
             + ((indicator_area_height - components.plaintext().height) / 2.0).floor();
     }
@@ -205,24 +616,31 @@ pub fn top_right(
     // debug!("label() width {}", label().width);
     let h_space = 2.0 * horizontal_spacing;
     indicator.for_width(components.ok().width);
+    let (box_width, box_height) = indicator_box(config, components, indicator);
     components.label().calc_extents(config.text_width, true);
     let label_area_width =
-        components.label().width + (4.0 * horizontal_spacing) + indicator.width + h_space;
+        components.label().width + (4.0 * horizontal_spacing) + box_width + h_space;
     // debug!("label() area width {}", label());
     let button_area_width =
         (3.0 * horizontal_spacing) + components.ok().width + components.cancel().width;
     let width = label_area_width.max(button_area_width);
     components.label().x = horizontal_spacing * 2.0;
-    indicator.x = width - horizontal_spacing * 2.0 - indicator.width;
+    let box_x = width - horizontal_spacing * 2.0 - box_width;
     components.ok().x = width - horizontal_spacing - components.ok().width;
     components.cancel().x = components.ok().x - horizontal_spacing - components.cancel().width;
 
     let vertical_spacing = config.vertical_spacing(components.text_height);
-    let label_area_height = components.label().height.max(indicator.height);
+    let label_area_height = components.label().height.max(box_height);
     let v_space = 3.0 * vertical_spacing;
     let height = (2.0 * vertical_spacing) + label_area_height + components.ok().height + v_space;
     components.label().y = vertical_spacing;
-    indicator.y = components.label().y;
+    let box_y = components.label().y;
+    place_indicator_box(
+        config,
+        components,
+        indicator,
+        (box_x, box_y, box_width, box_height),
+    );
     components.ok().y = components.label().y + label_area_height + v_space;
     components.cancel().y = components.ok().y;
 
@@ -1,27 +1,31 @@
 use std::ffi::{CStr, OsStr, OsString};
+use std::fmt::Write as _;
 use std::ops::{Deref, DerefMut};
 use std::os::unix::ffi::OsStrExt as _;
 use std::pin::Pin;
 use std::time::Duration;
 
 use libc::{LC_ALL, LC_CTYPE};
-use log::{debug, info, log_enabled, trace};
+use log::{debug, info, log_enabled, trace, warn};
 use pango::prelude::FontExt as _;
-use tokio::time::{sleep, Instant, Sleep};
+use serde::Serialize;
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
 use x11rb::protocol::xproto;
 use zeroize::Zeroize;
 
 use crate::bail;
 use crate::config;
 use crate::config::{IndicatorType, Rgba};
-use crate::errors::Result;
+use crate::envelope::Method;
+use crate::errors::{Context as _, Result};
 use crate::event::XContext;
 use crate::keyboard::{
-    self, keysyms, xkb_compose_feed_result, xkb_compose_status, Keyboard, Keycode,
+    self, keysyms, xkb_compose_feed_result, xkb_compose_status, Keyboard, Keycode, Keysym,
 };
 use crate::secret::Passphrase;
-use crate::secret::SecBuf;
+use crate::secret::{SecBuf, Sensitive};
 
+mod chooser;
 pub mod indicator;
 pub mod layout;
 
@@ -33,17 +37,23 @@ pub enum Action {
     PastePrimary,
     PasteClipboard,
     PlainText,
+    // Drag the window
+    Move,
 }
 
 pub struct Components {
     clipboard_config: Option<config::ClipboardButton>,
     plaintext_config: Option<config::TextButton>,
+    icon_theme: Option<String>,
     labels: Vec<Label>,
     indicator_label_text: String,
     indicator_label_foreground: Option<Rgba>,
     pango_context: pango::Context,
     buttons: Vec<Button>,
+    logo: Option<Logo>,
     text_height: f64,
+    // The plaintext button even when the indicator cannot show the text, for `--demo`
+    force_plaintext_button: bool,
 }
 
 impl Components {
@@ -70,10 +80,12 @@ impl Components {
         if self.buttons.get_mut(2).is_none() {
             debug!("creating clipboard button");
             let config = self.clipboard_config.take().unwrap();
-            let clipboard_label = Label::ClipboardLabel(ClipboardLabel::new(
-                config.foreground.into(),
-                self.text_height,
-            ));
+            let mut clipboard_label =
+                ClipboardLabel::new(config.foreground.into(), self.text_height);
+            if let Some(ref path) = config.image {
+                clipboard_label.set_image(path);
+            }
+            let clipboard_label = Label::ClipboardLabel(clipboard_label);
             self.buttons.push(Button::new(
                 config.button,
                 clipboard_label,
@@ -90,12 +102,26 @@ impl Components {
             let layout = pango::Layout::new(&self.pango_context);
             layout.set_text(&config.label);
             let label = Label::TextLabel(TextLabel::new(config.foreground.into(), layout));
-            self.buttons
-                .push(Button::new(config.button, label, self.text_height));
+            let mut button = Button::new(config.button, label, self.text_height);
+            if let Some(ref icon) = config.icon {
+                button.set_icon(icon, self.icon_theme.as_deref());
+            }
+            // Also when created by a later layout
+            button.calc_label_position();
+            self.buttons.push(button);
         }
         &mut self.buttons[3]
     }
 
+    fn has_plaintext_button(&self, indicator: &Indicator) -> bool {
+        self.force_plaintext_button || indicator.has_plaintext()
+    }
+
+    // Not when `indicator_label` is empty
+    fn has_indicator_label(&self) -> bool {
+        self.labels.len() > 1 || !self.indicator_label_text.is_empty()
+    }
+
     fn indicator_label(&mut self) -> &mut Label {
         if self.labels.get_mut(1).is_none() {
             debug!("creating indicator label");
@@ -117,11 +143,20 @@ impl Components {
 pub enum Pattern {
     Solid(cairo::SolidPattern),
     Linear(cairo::LinearGradient),
+    Dithered(cairo::SurfacePattern),
 }
 
 impl Pattern {
-    pub fn get_pattern(fill_height: f64, start: Rgba, end: Option<Rgba>) -> Self {
-        if let Some(end) = end {
+    /// A gradient is dithered for the device `scale` if given.
+    pub fn get_pattern(
+        fill_height: f64,
+        start: Rgba,
+        end: Option<Rgba>,
+        dither: Option<f64>,
+    ) -> Self {
+        if let (Some(end), Some(scale)) = (end, dither) {
+            Self::Dithered(dithered_gradient(fill_height, start, end, scale))
+        } else if let Some(end) = end {
             let grad = cairo::LinearGradient::new(0.0, 0.0, 0.0, fill_height);
             grad.add_color_stop_rgba(
                 0.0,
@@ -160,10 +195,65 @@ impl AsRef<cairo::Pattern> for Pattern {
         match self {
             Self::Solid(ref p) => p,
             Self::Linear(ref p) => p,
+            Self::Dithered(ref p) => p,
         }
     }
 }
 
+// The thresholds of the 4x4 ordered dither, in sixteenths
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Renders a vertical gradient for the device pixels at `scale`, offsetting each pixel by its
+// threshold before rounding to 8 bits so the steps between the colors do not show as bands.
+fn dithered_gradient(
+    fill_height: f64,
+    start: Rgba,
+    end: Rgba,
+    scale: f64,
+) -> cairo::SurfacePattern {
+    let device_height = fill_height * scale;
+    // Rows past both ends for the antialiasing as the pattern repeats
+    let pad = 2 * scale.ceil() as i32;
+    let rows = device_height.ceil().max(1.0) as i32 + 2 * pad;
+    let mut surface =
+        cairo::ImageSurface::create(cairo::Format::ARgb32, 4, rows).expect("cairo image surface");
+    let stride = surface.stride() as usize;
+    {
+        let mut data = surface.data().expect("cairo image surface data");
+        for row in 0..rows {
+            let t = (f64::from(row - pad) + 0.5) / device_height;
+            let t = t.clamp(0.0, 1.0);
+            let mix = |a: u8, b: u8| f64::from(a) + (f64::from(b) - f64::from(a)) * t;
+            let alpha = mix(start.alpha, end.alpha);
+            for (col, threshold) in BAYER[row as usize % 4].iter().enumerate() {
+                let threshold = (f64::from(*threshold) + 0.5) / 16.0;
+                let dither = |v: f64| (v + threshold).floor().min(255.0) as u32;
+                let a = dither(alpha);
+                // Premultiplied
+                let channel = |s, e| dither(mix(s, e) * alpha / 255.0).min(a);
+                let pixel = a << 24
+                    | channel(start.red, end.red) << 16
+                    | channel(start.green, end.green) << 8
+                    | channel(start.blue, end.blue);
+                let i = row as usize * stride + col * 4;
+                data[i..i + 4].copy_from_slice(&pixel.to_ne_bytes());
+            }
+        }
+    }
+    let pattern = cairo::SurfacePattern::create(&surface);
+    pattern.set_extend(cairo::Extend::Repeat);
+    pattern.set_filter(cairo::Filter::Nearest);
+    pattern.set_matrix(cairo::Matrix::new(
+        scale,
+        0.0,
+        0.0,
+        scale,
+        0.0,
+        f64::from(pad),
+    ));
+    pattern
+}
+
 #[derive(Debug)]
 pub enum Indicator {
     Strings(indicator::Strings),
@@ -172,6 +262,26 @@ pub enum Indicator {
 }
 
 impl Indicator {
+    fn new(config: config::Indicator, pango_context: &pango::Context, text_height: f64) -> Self {
+        match config.indicator_type {
+            IndicatorType::Strings { strings } => {
+                let indicator_layout = pango::Layout::new(pango_context);
+                Indicator::Strings(indicator::Strings::new(
+                    config.common,
+                    strings,
+                    indicator_layout,
+                    text_height,
+                ))
+            }
+            IndicatorType::Classic { classic } => {
+                Indicator::Classic(indicator::Classic::new(config.common, classic, text_height))
+            }
+            IndicatorType::Circle { circle } => {
+                Indicator::Circle(indicator::Circle::new(config.common, circle, text_height))
+            }
+        }
+    }
+
     pub fn set_hover(&mut self, hover: bool, xcontext: &XContext) -> Result<()> {
         match self {
             Self::Strings(i) => i.set_hover(hover, xcontext),
@@ -232,14 +342,38 @@ impl Indicator {
         }
     }
 
+    /// Whether typing shows in the indicator, the Strings types always show it.
+    pub fn has_feedback(&self) -> bool {
+        match self {
+            Self::Strings(..) => true,
+            Self::Circle(i) => i.has_feedback(),
+            Self::Classic(i) => i.has_feedback(),
+        }
+    }
+
     // TODO
     pub fn has_plaintext(&self) -> bool {
         match self {
-            Self::Strings(..) => true,
+            Self::Strings(i) => !i.always_plain(),
             Self::Circle(..) | Self::Classic(..) => false,
         }
     }
 
+    /// Shows the uncommitted text of a compose sequence.
+    pub fn preedit_push(&mut self, c: char) {
+        match self {
+            Self::Strings(i) => i.preedit_push(c),
+            Self::Circle(..) | Self::Classic(..) => {}
+        }
+    }
+
+    pub fn preedit_clear(&mut self) {
+        match self {
+            Self::Strings(i) => i.preedit_clear(),
+            Self::Circle(..) | Self::Classic(..) => {}
+        }
+    }
+
     // TODO
     pub fn toggle_plaintext(&mut self) {
         match self {
@@ -256,12 +390,21 @@ impl Indicator {
         }
     }
 
+    pub fn set_dither(&mut self, scale: Option<f64>) {
+        match self {
+            Self::Strings(i) => i.set_dither(scale),
+            Self::Circle(i) => i.set_dither(scale),
+            Self::Classic(i) => i.set_dither(scale),
+        }
+    }
+
     pub fn paint(&self, cr: &cairo::Context) {
         match self {
             Self::Strings(i) => i.paint(cr),
             Self::Circle(i) => i.paint(cr),
             Self::Classic(i) => i.paint(cr),
         }
+        self.paint_notice(cr);
     }
 
     pub fn set_painted(&mut self) {
@@ -274,7 +417,8 @@ impl Indicator {
 
     pub fn set_next_frame(&mut self) {
         match self {
-            Self::Strings(..) | Self::Classic(..) => {}
+            Self::Strings(i) => i.set_next_frame(),
+            Self::Classic(..) => {}
             Self::Circle(i) => i.set_next_frame(),
         }
     }
@@ -285,6 +429,7 @@ impl Indicator {
             Self::Circle(i) => i.repaint(cr, bg),
             Self::Classic(i) => i.repaint(cr, bg),
         }
+        self.repaint_notice(cr);
     }
 
     pub fn for_width(&mut self, width: f64) {
@@ -296,6 +441,152 @@ impl Indicator {
     }
 }
 
+/// The indicator taking the input and the ones only following its length.
+#[derive(Debug)]
+pub struct Indicators {
+    main: Indicator,
+    secondary: Vec<Indicator>,
+    // Whether the input may have changed since `take_edited`
+    edited: bool,
+}
+
+impl Indicators {
+    pub fn pass_insert(&mut self, s: &str, pasted: bool) {
+        self.main.pass_insert(s, pasted);
+        self.sync(pasted);
+    }
+
+    pub fn pass_clear(&mut self) {
+        self.main.pass_clear();
+        self.main.preedit_clear();
+        self.sync(false);
+    }
+
+    pub fn pass_delete(&mut self, word: bool) {
+        self.main.pass_delete(word);
+        self.sync(false);
+    }
+
+    pub fn take_edited(&mut self) -> bool {
+        std::mem::take(&mut self.edited)
+    }
+
+    // Shows `other` in place of the main indicator, which is left in `other`
+    fn swap_main(&mut self, other: &mut Indicator) {
+        std::mem::swap(&mut self.main, other);
+        self.main.set_focused(other.is_focused());
+        self.sync(false);
+    }
+
+    // Brings the secondary indicators to the length of the passphrase
+    fn sync(&mut self, pasted: bool) {
+        self.edited = true;
+        let len = self.main.pass.len;
+        for i in &mut self.secondary {
+            if len == 0 {
+                i.pass_clear();
+            } else if i.pass.len < len {
+                i.pass_insert(&"*".repeat(len - i.pass.len), pasted);
+            }
+            while i.pass.len > len {
+                let before = i.pass.len;
+                i.pass_delete(false);
+                if i.pass.len == before {
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn into_pass(self) -> Passphrase {
+        self.main.into_pass()
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.all().any(|i| i.dirty())
+    }
+
+    pub fn paint(&self, cr: &cairo::Context) {
+        for i in self.all() {
+            i.paint(cr);
+        }
+    }
+
+    pub fn repaint(&self, cr: &cairo::Context, bg: &Pattern) {
+        for i in self.all() {
+            i.repaint(cr, bg);
+        }
+    }
+
+    pub fn set_painted(&mut self) {
+        self.main.set_painted();
+        for i in &mut self.secondary {
+            i.set_painted();
+        }
+    }
+
+    pub fn set_next_frame(&mut self) {
+        self.main.set_next_frame();
+        for i in &mut self.secondary {
+            i.set_next_frame();
+        }
+    }
+
+    pub fn set_colors(&mut self, main: &config::Indicator, secondary: &[config::Indicator]) {
+        self.main.set_colors(&main.common);
+        for (i, config) in self.secondary.iter_mut().zip(secondary) {
+            i.set_colors(&config.common);
+        }
+    }
+
+    pub fn set_dither(&mut self, scale: Option<f64>) {
+        self.main.set_dither(scale);
+        for i in &mut self.secondary {
+            i.set_dither(scale);
+        }
+    }
+
+    pub async fn handle_events(&mut self) {
+        let mut secondary: Vec<_> = self
+            .secondary
+            .iter_mut()
+            .map(|i| Box::pin(i.handle_events()))
+            .collect();
+        let secondary = std::future::poll_fn(|cx| {
+            if secondary
+                .iter_mut()
+                .any(|f| std::future::Future::poll(f.as_mut(), cx).is_ready())
+            {
+                std::task::Poll::Ready(())
+            } else {
+                std::task::Poll::Pending
+            }
+        });
+        tokio::select! {
+            () = self.main.handle_events() => {}
+            () = secondary => {}
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = &Indicator> {
+        std::iter::once(&self.main).chain(&self.secondary)
+    }
+}
+
+impl Deref for Indicators {
+    type Target = Indicator;
+
+    fn deref(&self) -> &Self::Target {
+        &self.main
+    }
+}
+
+impl DerefMut for Indicators {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.main
+    }
+}
+
 impl Deref for Indicator {
     type Target = indicator::Base;
 
@@ -318,7 +609,7 @@ impl DerefMut for Indicator {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Rectangle {
     x: f64,
     y: f64,
@@ -353,6 +644,13 @@ impl DerefMut for Label {
 }
 
 impl Label {
+    pub fn set_foreground(&mut self, foreground: Pattern) {
+        match self {
+            Self::TextLabel(l) => l.foreground = foreground,
+            Self::ClipboardLabel(l) => l.foreground = foreground,
+        }
+    }
+
     pub fn calc_extents(&mut self, textwidth_req: Option<u32>, compact: bool) {
         match self {
             Self::TextLabel(l) => l.calc_extents(textwidth_req, compact),
@@ -365,6 +663,12 @@ impl Label {
             Self::ClipboardLabel(l) => l.paint(cr),
         }
     }
+    pub fn paint_with(&self, cr: &cairo::Context, foreground: &Pattern) {
+        match self {
+            Self::TextLabel(l) => l.paint_with(cr, foreground),
+            Self::ClipboardLabel(l) => l.paint_with(cr, foreground),
+        }
+    }
     pub fn cairo_context_changed(&self, cr: &cairo::Context) {
         match self {
             Self::TextLabel(l) => l.cairo_context_changed(cr),
@@ -373,10 +677,66 @@ impl Label {
     }
 }
 
+/// A picture with the rest of the dialog moved aside for it.
+#[derive(Debug)]
+pub struct Logo {
+    rectangle: Rectangle,
+    image: crate::image::Image,
+    placement: config::LogoPlacement,
+    secret: bool,
+    hidden: bool,
+}
+
+impl Logo {
+    pub fn new(config: &config::Logo) -> Option<Self> {
+        let path = config.path.as_deref()?;
+        let image = match crate::image::Image::open(path) {
+            Ok(image) => image,
+            Err(err) => {
+                warn!("logo {}: {:#}", path, err);
+                return None;
+            }
+        };
+        let (width, height) = match image.size() {
+            Some((width, height)) => {
+                let scale = (config.max_width / width)
+                    .min(config.max_height / height)
+                    .min(1.0);
+                ((width * scale).round(), (height * scale).round())
+            }
+            None => (config.max_width, config.max_height),
+        };
+        Some(Self {
+            rectangle: Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+            },
+            image,
+            placement: config.placement,
+            secret: config.secret,
+            hidden: false,
+        })
+    }
+
+    pub fn paint(&self, cr: &cairo::Context) {
+        if self.hidden {
+            return;
+        }
+        let r = &self.rectangle;
+        if let Err(err) = self.image.draw(cr, r.x, r.y, r.width, r.height) {
+            warn!("logo: {:#}", err);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ClipboardLabel {
     rectangle: Rectangle,
     foreground: Pattern,
+    #[cfg(feature = "svg")]
+    image: Option<crate::svg::Image>,
 }
 
 impl ClipboardLabel {
@@ -389,9 +749,45 @@ impl ClipboardLabel {
                 width: text_height * 0.83,
             },
             foreground,
+            #[cfg(feature = "svg")]
+            image: None,
+        }
+    }
+
+    /// Draws the shape of the SVG file instead of the clipboard glyph.
+    pub fn set_image(&mut self, path: &str) {
+        #[cfg(feature = "svg")]
+        match crate::svg::Image::load(path) {
+            Ok(image) => self.image = Some(image),
+            Err(err) => warn!("clipboard image {}: {:#}", path, err),
         }
+        #[cfg(not(feature = "svg"))]
+        warn!("clipboard image {}: needs the svg feature", path);
     }
+
     pub fn paint(&self, cr: &cairo::Context) {
+        self.paint_with(cr, &self.foreground);
+    }
+
+    pub fn paint_with(&self, cr: &cairo::Context, foreground: &Pattern) {
+        #[cfg(feature = "svg")]
+        if let Some(ref image) = self.image {
+            // Only the shape so that the button colors apply
+            cr.push_group();
+            if let Err(err) = image.render(
+                cr,
+                self.rectangle.x,
+                self.rectangle.y,
+                self.rectangle.width,
+                self.rectangle.height,
+            ) {
+                warn!("clipboard image: {:#}", err);
+            }
+            let mask = cr.pop_group().unwrap();
+            cr.set_source(foreground).unwrap();
+            cr.mask(&mask).unwrap();
+            return;
+        }
         cr.save().unwrap();
         cr.translate(self.rectangle.x, self.rectangle.y);
 
@@ -419,7 +815,7 @@ impl ClipboardLabel {
             self.rectangle.width - line_width,
             self.rectangle.height - line_width - y_offset,
         );
-        cr.set_source(&self.foreground).unwrap();
+        cr.set_source(foreground).unwrap();
         cr.set_line_width(line_width);
         cr.stroke().unwrap();
 
@@ -504,9 +900,13 @@ impl TextLabel {
     }
 
     pub fn paint(&self, cr: &cairo::Context) {
+        self.paint_with(cr, &self.foreground);
+    }
+
+    pub fn paint_with(&self, cr: &cairo::Context, foreground: &Pattern) {
         cr.save().unwrap();
         cr.translate(self.rectangle.x, self.rectangle.y);
-        cr.set_source(&self.foreground).unwrap();
+        cr.set_source(foreground).unwrap();
         // TODO am I doin right?
         cr.move_to(-self.xoff, -self.yoff);
 
@@ -539,6 +939,8 @@ pub struct Button {
     dirty: bool,
     border_pattern: Pattern,
     border_pattern_pressed: Pattern,
+    border_pattern_disabled: Pattern,
+    foreground_disabled: Pattern,
     vertical_spacing: f64,
     horizontal_spacing: f64,
     interior_width: f64,
@@ -547,8 +949,14 @@ pub struct Button {
     background: Option<Pattern>,
     bg_pressed: Option<Pattern>,
     bg_hover: Option<Pattern>,
+    bg_disabled: Option<Pattern>,
     config: config::Button,
     toggled: bool,
+    disabled: bool,
+    // The device scale the gradients are dithered for
+    dither: Option<f64>,
+    // Before the label, as high as a line of it
+    icon: Option<(crate::image::Image, Rectangle)>,
 }
 
 impl Button {
@@ -576,6 +984,8 @@ impl Button {
             dirty: true,
             border_pattern: config.border_color.into(),
             border_pattern_pressed: config.border_color_pressed.into(),
+            border_pattern_disabled: config.border_color_disabled.into(),
+            foreground_disabled: config.foreground_disabled.into(),
             interior_width: 0.0,
             interior_height: 0.0,
             vertical_spacing,
@@ -584,18 +994,53 @@ impl Button {
             background: None,
             bg_pressed: None,
             bg_hover: None,
+            bg_disabled: None,
             config,
             toggled: false,
+            disabled: false,
+            dither: None,
+            icon: None,
         };
         me.calc_extents();
         me
     }
 
+    /// Puts the icon found by `crate::image::find_icon` before the label.
+    pub fn set_icon(&mut self, name: &str, theme: Option<&str>) {
+        let image = crate::image::find_icon(name, theme)
+            .with_context(|| format!("no icon {name}"))
+            .and_then(|path| Ok(crate::image::Image::open(&path.to_string_lossy())?));
+        match image {
+            Ok(image) => {
+                self.icon = Some((image, Rectangle::default()));
+                self.calc_extents();
+            }
+            Err(err) => warn!("button icon: {:#}", err),
+        }
+    }
+
+    // The icon and the space after it
+    fn icon_width(&self) -> f64 {
+        match self.icon {
+            Some(_) => self.label.height + (self.horizontal_spacing / 2.0).round(),
+            None => 0.0,
+        }
+    }
+
     pub fn toggle(&mut self) {
         self.toggled = !self.toggled;
         self.dirty = true;
     }
 
+    pub fn set_disabled(&mut self, disabled: bool) {
+        if self.disabled != disabled {
+            self.disabled = disabled;
+            self.hover = false;
+            self.pressed = false;
+            self.dirty = true;
+        }
+    }
+
     fn clear(&self, cr: &cairo::Context, bg: &Pattern) {
         cr.rectangle(
             self.x - 1.0,
@@ -612,7 +1057,8 @@ impl Button {
 
     fn calc_extents(&mut self) {
         self.label.calc_extents(None, false);
-        self.interior_width = self.label.width + (2.0 * self.horizontal_spacing);
+        self.interior_width =
+            self.label.width + self.icon_width() + (2.0 * self.horizontal_spacing);
         self.interior_height = self.label.height + (2.0 * self.vertical_spacing);
         self.calc_total_extents();
     }
@@ -620,37 +1066,83 @@ impl Button {
     fn calc_total_extents(&mut self) {
         self.width = self.interior_width + 2.0 * self.config.border_width;
         self.height = self.interior_height + 2.0 * self.config.border_width;
+        self.calc_patterns();
+    }
 
+    fn calc_patterns(&mut self) {
         // TODO placement
         let fill_height = self.height - self.config.border_width;
         self.background = Some(Pattern::get_pattern(
             fill_height,
             self.config.background,
             self.config.background_stop,
+            self.dither,
         ));
         self.bg_pressed = Some(Pattern::get_pattern(
             fill_height,
             self.config.background_pressed,
             self.config.background_pressed_stop,
+            self.dither,
         ));
         self.bg_hover = Some(Pattern::get_pattern(
             fill_height,
             self.config.background_hover,
             self.config.background_hover_stop,
+            self.dither,
         ));
+        self.bg_disabled = Some(self.config.background_disabled.into());
+    }
+
+    pub fn set_dither(&mut self, scale: Option<f64>) {
+        self.dither = scale;
+        self.calc_patterns();
+        self.dirty = true;
+    }
+
+    /// Takes the colors of the config keeping the geometry.
+    pub fn set_colors(&mut self, config: &config::Button, foreground: Rgba) {
+        self.config = config::Button {
+            horizontal_spacing: self.config.horizontal_spacing,
+            vertical_spacing: self.config.vertical_spacing,
+            border_width: self.config.border_width,
+            border_mode: self.config.border_mode,
+            radius_x: self.config.radius_x,
+            radius_y: self.config.radius_y,
+            pressed_adjustment_x: self.config.pressed_adjustment_x,
+            pressed_adjustment_y: self.config.pressed_adjustment_y,
+            ..config.clone()
+        };
+        self.border_pattern = config.border_color.into();
+        self.border_pattern_pressed = config.border_color_pressed.into();
+        self.border_pattern_disabled = config.border_color_disabled.into();
+        self.foreground_disabled = config.foreground_disabled.into();
+        self.label.set_foreground(foreground.into());
+        self.calc_patterns();
+        self.dirty = true;
     }
 
     fn calc_label_position(&mut self) {
-        self.label.x = (self.width - self.label.width) / 2.0;
+        let icon_width = self.icon_width();
+        self.label.x = icon_width + (self.width - icon_width - self.label.width) / 2.0;
         self.label.y = (self.height - self.label.height) / 2.0;
+        if let Some((_, ref mut rectangle)) = self.icon {
+            *rectangle = Rectangle {
+                x: self.label.x - icon_width,
+                y: self.label.y,
+                width: self.label.height,
+                height: self.label.height,
+            };
+        }
         debug!(
             "button/label: label.x: {}, label.y: {}",
             self.label.x, self.label.y
         );
     }
 
+    // Disabled buttons ignore the pointer
     pub fn is_inside(&self, x: f64, y: f64) -> bool {
-        x >= self.x + self.config.border_width
+        !self.disabled
+            && x >= self.x + self.config.border_width
             && x < self.x + self.width - self.config.border_width
             && y >= self.y + self.config.border_width
             && y < self.y + self.height - self.config.border_width
@@ -666,6 +1158,48 @@ impl Button {
         self.pressed = pressed;
     }
 
+    // rounded_rectangle to be stroked with line_width. Sets the line width.
+    fn stroked_rectangle(
+        cr: &cairo::Context,
+        radius_x: f64,
+        radius_y: f64,
+        (x, y, w, h): (f64, f64, f64, f64),
+        line_width: f64,
+        border_mode: config::BorderMode,
+    ) {
+        let scale = cr.matrix().xx();
+        let hairline = border_mode == config::BorderMode::Hairline;
+        let (x, y, w, h, line_width) = if scale.fract() == 0.0 && !hairline {
+            (x, y, w, h, line_width)
+        } else {
+            // Whole device pixels for the line and the edges of the outside of it
+            let device_width = if hairline {
+                line_width
+            } else {
+                line_width * scale
+            };
+            let snapped_width = if line_width > 0.0 {
+                device_width.round().max(1.0) / scale
+            } else {
+                0.0
+            };
+            let (x, y, w, h) = snap(
+                cr,
+                (x - line_width / 2.0, y - line_width / 2.0),
+                (w + line_width, h + line_width),
+            );
+            (
+                x + snapped_width / 2.0,
+                y + snapped_width / 2.0,
+                w - snapped_width,
+                h - snapped_width,
+                snapped_width,
+            )
+        };
+        cr.set_line_width(line_width);
+        Self::rounded_rectangle(cr, radius_x, radius_y, x, y, w, h);
+    }
+
     // from https://www.cairographics.org/cookbook/roundedrectangles/
     fn rounded_rectangle(
         cr: &cairo::Context,
@@ -721,17 +1255,18 @@ impl Button {
         let y = self.config.border_width / 2.0;
         let width = self.width - self.config.border_width;
         let height = self.height - self.config.border_width;
-        Self::rounded_rectangle(
+        Self::stroked_rectangle(
             cr,
             self.config.radius_x,
             self.config.radius_y,
-            x,
-            y,
-            width,
-            height,
+            (x, y, width, height),
+            self.config.border_width,
+            self.config.border_mode,
         );
 
-        let bg = if self.pressed && self.hover {
+        let bg = if self.disabled {
+            &self.bg_disabled
+        } else if self.pressed && self.hover {
             &self.bg_pressed
         } else if self.hover {
             &self.bg_hover
@@ -744,12 +1279,13 @@ impl Button {
         cr.fill_preserve().unwrap();
 
         if self.config.border_width > 0.0 {
-            if std::ptr::eq(bg, &self.bg_pressed) {
+            if self.disabled {
+                cr.set_source(&self.border_pattern_disabled).unwrap();
+            } else if std::ptr::eq(bg, &self.bg_pressed) {
                 cr.set_source(&self.border_pattern_pressed).unwrap();
             } else {
                 cr.set_source(&self.border_pattern).unwrap();
             }
-            cr.set_line_width(self.config.border_width);
             cr.stroke().unwrap();
         }
 
@@ -759,12 +1295,49 @@ impl Button {
                 self.config.pressed_adjustment_y,
             );
         }
-        self.label.paint(cr);
+        if self.disabled {
+            self.label.paint_with(cr, &self.foreground_disabled);
+        } else {
+            self.label.paint(cr);
+        }
+        if let Some((ref image, ref r)) = self.icon {
+            if self.disabled {
+                cr.push_group();
+            }
+            if let Err(err) = image.draw(cr, r.x, r.y, r.width, r.height) {
+                warn!("button icon: {:#}", err);
+            }
+            if self.disabled {
+                cr.pop_group_to_source().unwrap();
+                cr.paint_with_alpha(0.5).unwrap();
+            }
+        }
 
         cr.restore().unwrap();
     }
 }
 
+// Under a fractional scale the edges of a rectangle would fall between device pixels and be
+// blurred over two. Moves them to the nearest ones, keeping at least a pixel.
+fn snap(cr: &cairo::Context, (x, y): (f64, f64), (w, h): (f64, f64)) -> (f64, f64, f64, f64) {
+    if cr.matrix().xx().fract() == 0.0 {
+        return (x, y, w, h);
+    }
+    let (x0, y0) = cr.user_to_device(x, y);
+    let (x1, y1) = cr.user_to_device(x + w, y + h);
+    let (x0, y0) = (x0.round(), y0.round());
+    let (x1, y1) = (x1.round().max(x0 + 1.0), y1.round().max(y0 + 1.0));
+    let (x0, y0) = cr.device_to_user(x0, y0).expect("cairo device_to_user");
+    let (x1, y1) = cr.device_to_user(x1, y1).expect("cairo device_to_user");
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+/// Adds a rectangle with its edges on device pixels to the path.
+pub fn snapped_rectangle(cr: &cairo::Context, x: f64, y: f64, w: f64, h: f64) {
+    let (x, y, w, h) = snap(cr, (x, y), (w, h));
+    cr.rectangle(x, y, w, h);
+}
+
 fn balance_button_extents(button1: &mut Button, button2: &mut Button) {
     button1.interior_width = button1.interior_width.max(button2.interior_width);
     button2.interior_width = button1.interior_width;
@@ -774,6 +1347,36 @@ fn balance_button_extents(button1: &mut Button, button2: &mut Button) {
     button2.calc_total_extents();
 }
 
+// A key chord with the keysyms its key name resolves to
+type Binding = (config::KeyChord, Vec<Keysym>);
+
+fn binding(chord: config::KeyChord) -> Binding {
+    let syms = keyboard::keysyms_from_name(&chord.key);
+    (chord, syms)
+}
+
+fn is_bound(binding: Option<&Binding>, key_sym: Keysym, keyboard: &Keyboard) -> bool {
+    let Some((chord, syms)) = binding else {
+        return false;
+    };
+    let active = |name: &[u8]| {
+        keyboard.mod_name_is_active(
+            name,
+            keyboard::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+        )
+    };
+    syms.contains(&key_sym)
+        && chord.ctrl == keyboard.ctrl_active()
+        && chord.shift == active(keyboard::names::XKB_MOD_NAME_SHIFT)
+        && chord.alt == active(keyboard::names::XKB_MOD_NAME_ALT)
+}
+
+fn is_any_bound(bindings: &[Binding], key_sym: Keysym, keyboard: &Keyboard) -> bool {
+    bindings
+        .iter()
+        .any(|b| is_bound(Some(b), key_sym, keyboard))
+}
+
 pub fn set_locale_from_env() -> Result<()> {
     let locale = unsafe { libc::setlocale(LC_ALL, b"\0".as_ptr().cast()) };
     if locale.is_null() {
@@ -790,25 +1393,269 @@ pub fn get_character_locale() -> Result<OsString> {
     Ok(unsafe { OsStr::from_bytes(CStr::from_ptr(locale).to_bytes()).to_os_string() })
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Secret,
+    // Only OK and Cancel, OK prints "yes"
+    Confirm,
+    // Only Cancel, e.g. while waiting for a security key to be touched
+    Touch,
+    // Asks for a username and then a password
+    BasicAuth(config::BasicAuthOutput),
+}
+
+impl Mode {
+    /// Uses `SSH_ASKPASS_PROMPT` if set by ssh, otherwise guesses from the label if
+    /// `heuristics` is enabled.
+    pub fn detect(label: &str, heuristics: bool) -> Self {
+        match std::env::var("SSH_ASKPASS_PROMPT").as_deref() {
+            Ok("confirm") => return Self::Confirm,
+            Ok("none") => return Self::Touch,
+            _ => {}
+        }
+        if heuristics {
+            Self::guess(label)
+        } else {
+            Self::Secret
+        }
+    }
+
+    // ssh's host key prompt also takes a fingerprint, so it is typed in
+    fn guess(label: &str) -> Self {
+        let label = label.to_lowercase();
+        if label.contains("(yes/no") && !label.contains("[fingerprint]") {
+            Self::Confirm
+        } else if label.contains("confirm user presence") {
+            Self::Touch
+        } else {
+            Self::Secret
+        }
+    }
+
+    fn takes_input(self) -> bool {
+        matches!(self, Self::Secret | Self::BasicAuth(..))
+    }
+
+    fn shows_button(self, i: usize) -> bool {
+        match self {
+            Self::Secret | Self::BasicAuth(..) => true,
+            // ok and cancel
+            Self::Confirm => i < 2,
+            Self::Touch => i == 1,
+        }
+    }
+}
+
+// The lines of the main label
+#[derive(Debug)]
+struct LabelText {
+    // The user's anti-phishing phrase
+    phrase: Option<String>,
+    header: Option<String>,
+    header_foreground: Rgba,
+    label: String,
+    caller: Option<String>,
+    caller_foreground: Rgba,
+    countdown: Option<String>,
+    attempt: Option<String>,
+    // The text and whether it is visible, room is kept for it either way
+    focus_hint: Option<(String, bool)>,
+    error: Option<String>,
+    // Of a weak new passphrase, in the colors of the error
+    warning: Option<String>,
+    error_foreground: Rgba,
+    error_background: Rgba,
+}
+
+impl LabelText {
+    fn set_layout(&self, layout: &pango::Layout) {
+        if self.phrase.is_none()
+            && self.header.is_none()
+            && self.caller.is_none()
+            && self.countdown.is_none()
+            && self.attempt.is_none()
+            && self.focus_hint.is_none()
+            && self.error.is_none()
+            && self.warning.is_none()
+        {
+            layout.set_text(&self.label);
+        } else {
+            layout.set_markup(&self.markup());
+        }
+    }
+
+    fn markup(&self) -> String {
+        let mut markup = String::new();
+        if let Some(ref phrase) = self.phrase {
+            let _ = writeln!(markup, "<i>{}</i>", pango::glib::markup_escape_text(phrase));
+        }
+        if let Some(ref header) = self.header {
+            let _ = writeln!(
+                markup,
+                "<span foreground=\"{}\" weight=\"bold\">{}</span>",
+                self.header_foreground,
+                pango::glib::markup_escape_text(header)
+            );
+        }
+        markup.push_str(&pango::glib::markup_escape_text(&self.label));
+        if let Some(ref caller) = self.caller {
+            let _ = write!(
+                markup,
+                "\n<span foreground=\"{}\" size=\"small\">{}</span>",
+                self.caller_foreground,
+                pango::glib::markup_escape_text(caller)
+            );
+        }
+        if let Some(ref countdown) = self.countdown {
+            // Tabular digits keep the width from changing every second
+            let _ = write!(
+                markup,
+                "\n<span weight=\"bold\" size=\"x-large\" font_features=\"tnum\">{countdown}</span>"
+            );
+        }
+        if let Some(ref attempt) = self.attempt {
+            let _ = write!(
+                markup,
+                "\n<span foreground=\"{}\" size=\"small\">{attempt}</span>",
+                self.caller_foreground
+            );
+        }
+        if let Some((ref hint, shown)) = self.focus_hint {
+            let _ = write!(
+                markup,
+                "\n<span foreground=\"{}\" fgalpha=\"{}\" size=\"small\">{}</span>",
+                self.caller_foreground,
+                if shown { "100%" } else { "1" },
+                pango::glib::markup_escape_text(hint)
+            );
+        }
+        // Last so that they end up right above the indicator
+        for text in [&self.error, &self.warning].into_iter().flatten() {
+            let _ = write!(
+                markup,
+                "\n<span foreground=\"{}\" background=\"{}\" weight=\"bold\"> {} </span>",
+                self.error_foreground,
+                self.error_background,
+                pango::glib::markup_escape_text(text)
+            );
+        }
+        markup
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentGeometry {
+    pub name: &'static str,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Component positions are in user space, before the scale is applied.
+#[derive(Debug, Serialize)]
+pub struct Geometry {
+    pub scale: f64,
+    pub width: f64,
+    pub height: f64,
+    pub window_width: u16,
+    pub window_height: u16,
+    pub components: Vec<ComponentGeometry>,
+}
+
 #[derive(Debug)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct Dialog {
     background: Pattern,
     background_original: Rgba,
     buttons: Vec<Button>,
+    // For a plaintext button that a later layout adds
+    plaintext_config: Option<config::TextButton>,
+    icon_theme: Option<String>,
+    force_plaintext_button: bool,
     labels: Vec<Label>,
-    pub indicator: Indicator,
+    logo: Option<Logo>,
+    pub indicator: Indicators,
     width: f64,
     height: f64,
+    layout_opts: config::Layout,
+    limits: layout::Limits,
+    text_height: f64,
+    reflow: bool,
+    window_width: Option<u16>,
+    base_scale: f64,
+    scale: f64,
+    // The device scale the gradients are dithered for, None without dithering
+    dither: Option<f64>,
+    maximized_opts: config::Maximized,
+    maximized: bool,
+    laid_out_maximized: bool,
+    // The width the label was last wrapped to by a resize
+    reflow_width: Option<f64>,
     mouse_middle_pressed: bool,
     input_timeout_duration: Option<Duration>,
     input_timeout: Option<Pin<Box<Sleep>>>,
-    debug: bool,
+    timed_out: bool,
+    // Some of the input came from a selection
+    pasted: bool,
+    confirm_submit: Option<Duration>,
+    // Until when Enter submits
+    submit_armed_until: Option<Instant>,
+    timeout_action: config::TimeoutAction,
+    require_non_empty: bool,
+    submit_on_paste: config::SubmitOnPaste,
+    plaintext_key: Option<Binding>,
+    delete_word_key: Option<Binding>,
+    wipe_key: Option<Binding>,
+    cancel_keys: Vec<Binding>,
+    confirm_cancel_keys: Vec<Binding>,
+    cancel_clears: bool,
+    layout_switch_key: Option<Binding>,
+    show_layout_change: bool,
+    // Until when the layout name is shown
+    layout_notice_until: Option<Instant>,
+    deadline: Option<Instant>,
+    countdown_tick: Option<Instant>,
+    retry_backoff: Duration,
+    // Until when OK is held off after a failed attempt
+    backoff_until: Option<Instant>,
+    min_entropy: Option<u32>,
+    weak_warned: bool,
+    theme_key: Option<Binding>,
+    themes: Vec<config::Dialog>,
+    theme: usize,
+    delete_char_key: Option<Binding>,
+    submit_key: Option<Binding>,
+    keypad_submit_key: Option<Binding>,
+    paste_source: config::PasteSource,
+    chooser: Option<chooser::Chooser>,
+    clipboard_pressed_at: Option<Instant>,
     button_pressed: bool,
     transparency: bool,
+    reduce_transparency: bool,
+    corner_radius: f64,
+    // Whether there is a compositor to blend the window with what is below it
+    composited: bool,
+    draggable: bool,
+    focus_hint: String,
     dirty: bool,
     pango_context: pango::Context,
     config_direction: Option<pango::Direction>,
+    mode: Mode,
+    label_text: LabelText,
+    username_label: String,
+    password_label: String,
+    username: Option<SecBuf<char>>,
+    // The plaintext field for the username of --basic-auth, until the mode is set
+    username_indicator_config: Option<config::Indicator>,
+    // The username field while the password is typed and the other way round
+    stashed_indicator: Option<Indicator>,
+    #[cfg(feature = "keyring")]
+    remember: Button,
+    #[cfg(feature = "keyring")]
+    remember_spacing: f64,
+    #[cfg(feature = "keyring")]
+    keyring: Option<crate::keyring::Item>,
 }
 
 impl Dialog {
@@ -816,8 +1663,10 @@ impl Dialog {
     pub fn new(
         config: config::Dialog,
         cr: &cairo::Context,
+        px_per_mm: f64,
         label: Option<&str>,
-        debug: bool,
+        header: Option<&str>,
+        caller: Option<&str>,
     ) -> Result<Self> {
         if let Some(font_file) = config.font_file {
             debug!("using a specific font file");
@@ -836,6 +1685,7 @@ impl Dialog {
             }
         }
 
+        let dither = config.dither;
         if let Some(scale) = config.scale {
             debug!("config scale {}", scale);
             if scale <= 0.0 {
@@ -880,7 +1730,22 @@ impl Dialog {
         debug!("text height: {}", text_height);
 
         let label_layout = pango::Layout::new(&pango_context);
-        label_layout.set_text(label.unwrap_or(&config.label));
+        let label_text = LabelText {
+            phrase: config.secret_phrase,
+            header: header.map(str::to_owned),
+            header_foreground: config.header_foreground,
+            label: label.unwrap_or(&config.label).to_owned(),
+            caller: caller.map(str::to_owned),
+            caller_foreground: config.caller_foreground,
+            countdown: None,
+            attempt: None,
+            focus_hint: None,
+            error: None,
+            warning: None,
+            error_foreground: config.error_foreground,
+            error_background: config.error_background,
+        };
+        label_text.set_layout(&label_layout);
         label_layout.set_alignment(config.alignment.into());
         let label = Label::TextLabel(TextLabel::new(config.foreground.into(), label_layout));
 
@@ -899,31 +1764,61 @@ impl Dialog {
         ));
 
         let mut ok_button = Button::new(config.ok_button.button, ok_label, text_height);
+        if let Some(ref icon) = config.ok_button.icon {
+            ok_button.set_icon(icon, config.icon_theme.as_deref());
+        }
         let mut cancel_button = Button::new(config.cancel_button.button, cancel_label, text_height);
+        if let Some(ref icon) = config.cancel_button.icon {
+            cancel_button.set_icon(icon, config.icon_theme.as_deref());
+        }
         balance_button_extents(&mut ok_button, &mut cancel_button);
 
-        let mut indicator = match config.indicator.indicator_type {
-            IndicatorType::Strings { strings } => {
-                let indicator_layout = pango::Layout::new(&pango_context);
-                Indicator::Strings(indicator::Strings::new(
-                    config.indicator.common,
-                    strings,
-                    indicator_layout,
-                    text_height,
-                ))
+        let show_indicator_label = config.show_indicator_label.unwrap_or(matches!(
+            config.indicator.indicator_type,
+            config::IndicatorType::Circle { .. }
+        ));
+        let username_indicator_config = config::Indicator {
+            common: config.indicator.common,
+            indicator_type: IndicatorType::Strings {
+                strings: config::IndicatorStrings {
+                    plaintext_overflow: config::PlaintextOverflow::Scroll,
+                    ..match config.indicator.indicator_type {
+                        IndicatorType::Strings { ref strings } => strings.clone(),
+                        _ => config::IndicatorStrings::default(),
+                    }
+                },
+            },
+        };
+        let mut indicator = Indicators {
+            main: Indicator::new(config.indicator, &pango_context, text_height),
+            secondary: config
+                .secondary_indicators
+                .into_iter()
+                .map(|i| Indicator::new(i, &pango_context, text_height))
+                .collect(),
+            edited: false,
+        };
+
+        #[cfg(feature = "keyring")]
+        let remember = {
+            let layout = pango::Layout::new(&pango_context);
+            layout.set_text(&config.remember_button.label);
+            let label = Label::TextLabel(TextLabel::new(
+                config.remember_button.foreground.into(),
+                layout,
+            ));
+            let mut remember = Button::new(config.remember_button.button, label, text_height);
+            if let Some(ref icon) = config.remember_button.icon {
+                remember.set_icon(icon, config.icon_theme.as_deref());
             }
-            IndicatorType::Classic { classic } => Indicator::Classic(indicator::Classic::new(
-                config.indicator.common,
-                classic,
-                text_height,
-            )),
-            IndicatorType::Circle { circle } => Indicator::Circle(indicator::Circle::new(
-                config.indicator.common,
-                circle,
-                text_height,
-            )),
+            remember.calc_label_position();
+            remember
         };
 
+        let paste_source = config.clipboard_button.source;
+        let chooser = (paste_source == config::PasteSource::Choose)
+            .then(|| chooser::Chooser::new(&config.clipboard_button, &pango_context, text_height));
+
         let mut labels = Vec::with_capacity(2);
         labels.push(label);
         let mut buttons = Vec::with_capacity(3);
@@ -931,13 +1826,20 @@ impl Dialog {
         buttons.push(cancel_button);
         let mut components = Components {
             plaintext_config: Some(config.plaintext_button),
+            icon_theme: config.icon_theme.clone(),
             clipboard_config: Some(config.clipboard_button),
             indicator_label_foreground: Some(config.indicator_label_foreground),
-            indicator_label_text: config.indicator_label,
+            indicator_label_text: if show_indicator_label {
+                config.indicator_label
+            } else {
+                String::new()
+            },
             buttons,
+            logo: Logo::new(&config.logo),
             text_height,
             labels,
             pango_context,
+            force_plaintext_button: false,
         };
 
         debug!(
@@ -945,45 +1847,448 @@ impl Dialog {
             config.layout_opts.horizontal_spacing(text_height),
             config.layout_opts.vertical_spacing(text_height)
         );
-        let (width, height) = config.layout_opts.layout.get_fn()(
+        let limits = {
+            // The lengths are in window pixels
+            let scale = cr.matrix().xx();
+            let user = |length: config::Length| length.to_pixels(px_per_mm) / scale;
+            let opts = &config.layout_opts;
+            layout::Limits {
+                padding: user(opts.padding),
+                min_width: opts.min_width.map(user),
+                max_width: opts.max_width.map(user),
+                min_height: opts.min_height.map(user),
+                max_height: opts.max_height.map(user),
+            }
+        };
+        let (width, height) = layout::arrange(
             &config.layout_opts,
+            &limits,
             &mut components,
             &mut indicator,
         );
 
         let mut buttons = components.buttons;
+        let plaintext_config = components.plaintext_config;
+        let icon_theme = components.icon_theme;
 
         for b in &mut buttons {
             b.calc_label_position();
         }
 
+        #[cfg(feature = "keyring")]
+        let remember_spacing = config.layout_opts.vertical_spacing(text_height);
         debug!("input timeout: {:?}", config.input_timeout);
-        Ok(Self {
+        let mut me = Self {
             indicator,
             buttons,
+            plaintext_config,
+            icon_theme,
+            force_plaintext_button: false,
             labels: components.labels,
+            logo: components.logo,
             width,
             height,
+            layout_opts: config.layout_opts,
+            limits,
+            text_height,
+            reflow: false,
+            window_width: None,
+            base_scale: cr.matrix().xx(),
+            scale: cr.matrix().xx(),
+            dither: None,
+            maximized_opts: config.maximized,
+            maximized: false,
+            laid_out_maximized: false,
+            reflow_width: None,
             mouse_middle_pressed: false,
-            background: config.background.into(),
+            background: if config.reduce_transparency {
+                Rgba {
+                    alpha: u8::MAX,
+                    ..config.background
+                }
+            } else {
+                config.background
+            }
+            .into(),
             background_original: config.background,
             input_timeout_duration: config.input_timeout.map(Duration::from_secs),
             input_timeout: None,
-            debug,
+            timed_out: false,
+            pasted: false,
+            confirm_submit: config
+                .confirm_submit
+                .then(|| Duration::from_millis(config.confirm_submit_window)),
+            submit_armed_until: None,
+            timeout_action: config.timeout_action,
+            require_non_empty: config.require_non_empty,
+            submit_on_paste: config.submit_on_paste,
+            plaintext_key: config.plaintext_key.map(binding),
+            delete_word_key: config.delete_word_key.map(binding),
+            wipe_key: config.wipe_key.map(binding),
+            cancel_keys: config.cancel_keys.into_iter().map(binding).collect(),
+            confirm_cancel_keys: config
+                .confirm_cancel_keys
+                .into_iter()
+                .map(binding)
+                .collect(),
+            cancel_clears: config.cancel_clears,
+            layout_switch_key: config.layout_switch_key.map(binding),
+            show_layout_change: config.show_layout_change,
+            layout_notice_until: None,
+            deadline: None,
+            countdown_tick: None,
+            retry_backoff: Duration::from_millis(config.retry_backoff),
+            backoff_until: None,
+            min_entropy: None,
+            weak_warned: false,
+            theme_key: config.theme_key.map(binding),
+            themes: Vec::new(),
+            theme: 0,
+            delete_char_key: config.delete_char_key.map(binding),
+            submit_key: config.submit_key.map(binding),
+            keypad_submit_key: config.keypad_submit_key.map(binding),
+            paste_source,
+            chooser,
+            clipboard_pressed_at: None,
             button_pressed: false,
-            transparency: true,
+            transparency: !config.reduce_transparency,
+            reduce_transparency: config.reduce_transparency,
+            corner_radius: config.corner_radius,
+            composited: true,
+            draggable: false,
+            focus_hint: config.focus_hint,
             dirty: false,
             pango_context: components.pango_context,
             config_direction: config.direction.map(std::convert::Into::into),
-        })
+            mode: Mode::Secret,
+            label_text,
+            username_label: config.username_label,
+            password_label: config.password_label,
+            username: None,
+            username_indicator_config: Some(username_indicator_config),
+            stashed_indicator: None,
+            #[cfg(feature = "keyring")]
+            remember,
+            #[cfg(feature = "keyring")]
+            remember_spacing,
+            #[cfg(feature = "keyring")]
+            keyring: None,
+        };
+        if dither {
+            me.set_dither(cr.matrix().xx());
+        }
+        me.update_ok();
+        Ok(me)
+    }
+
+    /// `Mode::BasicAuth` shows the username in plain text until it is submitted.
+    pub fn set_mode(&mut self, mode: Mode) {
+        debug!("mode: {:?}", mode);
+        self.mode = mode;
+        if let (Mode::BasicAuth(..), Some(config)) = (mode, self.username_indicator_config.take()) {
+            let mut username = Indicator::new(config, &self.pango_context, self.text_height);
+            if let Indicator::Strings(ref mut i) = username {
+                i.set_always_plain();
+            }
+            if let Some(scale) = self.dither {
+                username.set_dither(Some(scale));
+            }
+            self.indicator.swap_main(&mut username);
+            self.stashed_indicator = Some(username);
+            self.relayout(self.reflow_width);
+        }
+        self.dirty = true;
+        self.update_ok();
+    }
+
+    /// Whether the dialog was closed by `input_timeout`.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub fn input_method(&self) -> Method {
+        if self.pasted {
+            Method::Pasted
+        } else {
+            Method::Typed
+        }
+    }
+
+    pub fn into_pass(self) -> Passphrase {
+        match (self.mode, self.username) {
+            (Mode::Confirm, _) => {
+                let mut pass = SecBuf::new(vec!['X'; 3]);
+                pass.insert_many(0, "yes".chars(), 3);
+                Passphrase(pass)
+            }
+            (Mode::BasicAuth(output), Some(username)) => {
+                let separator = match output {
+                    config::BasicAuthOutput::Colon => ':',
+                    config::BasicAuthOutput::Lines => '\n',
+                };
+                let password = self.indicator.into_pass();
+                let len = username.len + 1 + password.0.len;
+                let mut pass = SecBuf::new(vec!['X'; len]);
+                let chars = username
+                    .unsecure()
+                    .iter()
+                    .chain(std::iter::once(&separator))
+                    .chain(password.0.unsecure())
+                    .copied();
+                pass.insert_many(0, chars, len);
+                Passphrase(pass)
+            }
+            _ => self.indicator.into_pass(),
+        }
+    }
+
+    /// Shows the remember checkbox below the other components.
+    #[cfg(feature = "keyring")]
+    pub fn set_keyring(&mut self, item: crate::keyring::Item) {
+        self.keyring = Some(item);
+        self.place_remember();
+        self.dirty = true;
+    }
+
+    #[cfg(feature = "keyring")]
+    fn place_remember(&mut self) {
+        self.remember.x = self.labels[0].x;
+        self.remember.y = self.height;
+        self.height += self.remember.height + self.remember_spacing;
+    }
+
+    /// The item to store the passphrase as if remember was ticked.
+    #[cfg(feature = "keyring")]
+    pub fn take_keyring_item(&mut self) -> Option<crate::keyring::Item> {
+        if self.remember.toggled {
+            self.keyring.take()
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    fn remember(&self) -> Option<&Button> {
+        (self.keyring.is_some() && self.mode.takes_input()).then_some(&self.remember)
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    #[allow(clippy::unused_self)]
+    fn remember(&self) -> Option<&Button> {
+        None
+    }
+
+    #[cfg(feature = "keyring")]
+    fn remember_mut(&mut self) -> Option<&mut Button> {
+        (self.keyring.is_some() && self.mode.takes_input()).then_some(&mut self.remember)
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    #[allow(clippy::unused_self)]
+    fn remember_mut(&mut self) -> Option<&mut Button> {
+        None
+    }
+
+    fn asks_username(&self) -> bool {
+        matches!(self.mode, Mode::BasicAuth(..)) && self.username.is_none()
+    }
+
+    // The plaintext button stays with the password while the username is typed
+    fn password_indicator(&mut self) -> &mut Indicator {
+        match self.stashed_indicator {
+            Some(ref mut password) if self.username.is_none() => password,
+            _ => &mut self.indicator.main,
+        }
+    }
+
+    // The first OK of --basic-auth moves on to the password.
+    fn submit(&mut self) -> Action {
+        if self.buttons[0].disabled {
+            return Action::Nothing;
+        }
+        if !self.asks_username() {
+            return self.check_strength();
+        }
+        let len = self.indicator.pass.len;
+        let mut username = SecBuf::new(vec!['X'; len]);
+        username.insert_many(0, self.indicator.pass.unsecure().iter().copied(), len);
+        self.username = Some(username);
+        if let Some(ref mut password) = self.stashed_indicator {
+            self.indicator.swap_main(password);
+        } else {
+            self.indicator.pass_clear();
+        }
+        self.update_ok();
+        self.label_text.header = Some(self.password_label.clone());
+        self.label_text_changed();
+        Action::Nothing
+    }
+
+    // Back from the password of --basic-auth to the username, which keeps what was typed
+    fn edit_username(&mut self) {
+        let Some(mut username) = self.username.take() else {
+            return;
+        };
+        username.wipe();
+        if let Some(ref mut indicator) = self.stashed_indicator {
+            self.indicator.swap_main(indicator);
+        }
+        self.label_text.header = Some(self.username_label.clone());
+        self.label_text_changed();
+    }
+
+    // Cancels instead when OK would not go through, without the rest of submit
+    fn accept_on_timeout(&self) -> Action {
+        if self.buttons[0].disabled || self.asks_username() {
+            debug!("not accepting on timeout");
+            return Action::Cancel;
+        }
+        Action::Ok
+    }
+
+    // A weak new passphrase only goes through on the second OK
+    fn check_strength(&mut self) -> Action {
+        let Some(min) = self.min_entropy.filter(|_| !self.weak_warned) else {
+            return Action::Ok;
+        };
+        let bits = crate::secret::entropy_bits(self.indicator.pass.unsecure());
+        if bits >= f64::from(min) {
+            return Action::Ok;
+        }
+        debug!("weak passphrase");
+        self.weak_warned = true;
+        self.set_warning(Some(format!(
+            "Weak passphrase (about {bits:.0} bits), press OK again to use it"
+        )));
+        Action::Nothing
+    }
+
+    // An edit takes back the weak passphrase warning
+    fn check_edited(&mut self) {
+        if self.indicator.take_edited() && self.weak_warned {
+            self.weak_warned = false;
+            self.set_warning(None);
+        }
+    }
+
+    // With confirm_submit the first Enter or submitting paste only highlights OK. Slow and
+    // bounce keys hold back the second press by `delay`.
+    fn submit_key(&mut self, delay: Duration) -> Action {
+        if let Some(window) = self.confirm_submit {
+            if self.submit_armed_until.take().is_none() {
+                if !self.buttons[0].disabled {
+                    let window = window + delay;
+                    self.submit_armed_until = Some(Instant::now() + window);
+                    self.buttons[0].set_hover(true);
+                }
+                return Action::Nothing;
+            }
+            self.buttons[0].set_hover(false);
+        }
+        self.submit()
+    }
+
+    // With cancel_clears the first cancel key only clears the input
+    fn cancel_key(&mut self) -> Action {
+        if self.cancel_clears && self.indicator.pass.len > 0 {
+            self.indicator.pass_clear();
+            return Action::Nothing;
+        }
+        Action::Cancel
+    }
+
+    // Also what has been typed as the username
+    fn wipe(&mut self) -> Action {
+        debug!("wipe");
+        self.indicator.pass_clear();
+        self.indicator.pass.wipe();
+        if let Some(ref mut username) = self.username {
+            username.wipe();
+        }
+        if let Some(ref mut indicator) = self.stashed_indicator {
+            indicator.pass_clear();
+            indicator.pass.wipe();
+        }
+        Action::Cancel
+    }
+
+    // With require_non_empty OK is disabled until something is typed, and during a backoff
+    fn update_ok(&mut self) {
+        let disabled = self.backoff_until.is_some()
+            || self.require_non_empty && self.mode.takes_input() && self.indicator.pass.len == 0;
+        self.buttons[0].set_disabled(disabled);
     }
 
     // TODO
     pub fn uses_cursor(&self) -> bool {
-        matches!(self.indicator, Indicator::Strings(..))
+        matches!(*self.indicator, Indicator::Strings(..))
+    }
+
+    /// The original colors followed by the themes that the theme key cycles through.
+    pub fn set_themes(&mut self, themes: Vec<config::Dialog>) {
+        self.themes = themes;
+    }
+
+    fn next_theme(&mut self) {
+        if self.themes.len() < 2 {
+            return;
+        }
+        self.theme = (self.theme + 1) % self.themes.len();
+        debug!("theme {}", self.theme);
+        let themes = std::mem::take(&mut self.themes);
+        let theme = &themes[self.theme];
+
+        self.background_original = theme.background;
+        let mut background = theme.background;
+        if !self.transparency {
+            background.alpha = u8::MAX;
+        }
+        self.background = background.into();
+        self.labels[0].set_foreground(theme.foreground.into());
+        if let Some(label) = self.labels.get_mut(1) {
+            label.set_foreground(theme.indicator_label_foreground.into());
+        }
+        self.label_text.header_foreground = theme.header_foreground;
+        self.label_text.caller_foreground = theme.caller_foreground;
+        self.label_text.error_foreground = theme.error_foreground;
+        self.label_text.error_background = theme.error_background;
+        if let Label::TextLabel(ref label) = self.labels[0] {
+            self.label_text.set_layout(&label.layout);
+        }
+        let buttons = [
+            (&theme.ok_button.button, theme.ok_button.foreground),
+            (&theme.cancel_button.button, theme.cancel_button.foreground),
+            (
+                &theme.clipboard_button.button,
+                theme.clipboard_button.foreground,
+            ),
+            (
+                &theme.plaintext_button.button,
+                theme.plaintext_button.foreground,
+            ),
+        ];
+        for (b, (config, foreground)) in self.buttons.iter_mut().zip(buttons) {
+            b.set_colors(config, foreground);
+        }
+        if let Some(b) = self.remember_mut() {
+            b.set_colors(
+                &theme.remember_button.button,
+                theme.remember_button.foreground,
+            );
+        }
+        self.indicator
+            .set_colors(&theme.indicator, &theme.secondary_indicators);
+        self.indicator.set_dither(self.dither);
+        self.dirty = true;
+        self.themes = themes;
     }
 
     pub fn set_transparency(&mut self, enable: bool) {
+        if self.corner_radius > 0.0 && self.composited != enable {
+            self.dirty = true;
+        }
+        self.composited = enable;
+        let enable = enable && !self.reduce_transparency;
         if self.transparency == enable {
             debug!("set_transparency: status not changed");
             return;
@@ -1004,6 +2309,16 @@ impl Dialog {
         }
     }
 
+    /// Whether the background covering the window is painted opaque.
+    pub fn opaque(&self) -> bool {
+        (!self.transparency || self.background_original.alpha == u8::MAX) && !self.rounded()
+    }
+
+    // The corners need an alpha channel that is composited
+    fn rounded(&self) -> bool {
+        self.corner_radius > 0.0 && self.composited && !self.maximized
+    }
+
     pub fn set_next_frame(&mut self) {
         self.indicator.set_next_frame();
     }
@@ -1014,6 +2329,12 @@ impl Dialog {
         for b in &mut self.buttons {
             b.set_painted();
         }
+        if let Some(b) = self.remember_mut() {
+            b.set_painted();
+        }
+        for b in self.chooser_buttons_mut() {
+            b.set_painted();
+        }
         self.dirty = false;
     }
 
@@ -1021,7 +2342,12 @@ impl Dialog {
         if self.indicator.dirty() {
             return true;
         }
-        for b in &self.buttons {
+        for b in self
+            .buttons
+            .iter()
+            .chain(self.remember())
+            .chain(self.chooser_buttons())
+        {
             if b.dirty {
                 return true;
             }
@@ -1034,14 +2360,31 @@ impl Dialog {
             return self.init(cr);
         }
 
-        self.indicator.repaint(cr, &self.background);
+        if self.mode.takes_input() {
+            self.indicator.repaint(cr, &self.background);
+        }
         for (i, b) in self.buttons.iter().enumerate() {
-            if b.dirty {
+            if b.dirty && self.mode.shows_button(i) {
                 trace!("button {} dirty", i);
                 b.clear(cr, &self.background);
                 b.paint(cr);
             }
         }
+        if let Some(b) = self.remember().filter(|b| b.dirty) {
+            b.clear(cr, &self.background);
+            b.paint(cr);
+        }
+        // Always on top of the rest
+        if let Some(ref chooser) = self.chooser {
+            chooser.paint(cr, &self.background);
+        }
+    }
+
+    /// Leaves a secret logo blank, keeping its place.
+    pub fn hide_secret_logo(&mut self) {
+        if let Some(logo) = self.logo.as_mut().filter(|logo| logo.secret) {
+            logo.hidden = true;
+        }
     }
 
     pub fn window_size(&self, cr: &cairo::Context) -> (u16, u16) {
@@ -1051,8 +2394,79 @@ impl Dialog {
         (size.0.round() as u16, size.1.round() as u16)
     }
 
+    pub fn geometry(&self, cr: &cairo::Context) -> Geometry {
+        const LABELS: [&str; 2] = ["label", "indicator_label"];
+        const BUTTONS: [&str; 4] = ["ok", "cancel", "clipboard", "plaintext"];
+        const CHOOSER: [&str; 2] = ["chooser_clipboard", "chooser_primary"];
+        let rectangle = |name, r: &Rectangle| ComponentGeometry {
+            name,
+            x: r.x,
+            y: r.y,
+            width: r.width,
+            height: r.height,
+        };
+        let mut components = Vec::new();
+        for (name, l) in LABELS.into_iter().zip(&self.labels) {
+            components.push(rectangle(name, l));
+        }
+        let button = |name, b: &Button| ComponentGeometry {
+            name,
+            x: b.x,
+            y: b.y,
+            width: b.width,
+            height: b.height,
+        };
+        for (name, b) in BUTTONS.into_iter().zip(&self.buttons) {
+            components.push(button(name, b));
+        }
+        if let Some(b) = self.remember() {
+            components.push(button("remember", b));
+        }
+        // Only while it is open, like it is drawn
+        for (name, b) in CHOOSER
+            .into_iter()
+            .zip(self.chooser.iter().flat_map(chooser::Chooser::buttons))
+        {
+            components.push(button(name, b));
+        }
+        components.push(ComponentGeometry {
+            name: "indicator",
+            x: self.indicator.x,
+            y: self.indicator.y,
+            width: self.indicator.width,
+            height: self.indicator.height,
+        });
+        for i in &self.indicator.secondary {
+            components.push(ComponentGeometry {
+                name: "secondary_indicator",
+                x: i.x,
+                y: i.y,
+                width: i.width,
+                height: i.height,
+            });
+        }
+        if let Some(ref logo) = self.logo {
+            components.push(ComponentGeometry {
+                name: "logo",
+                x: logo.rectangle.x,
+                y: logo.rectangle.y,
+                width: logo.rectangle.width,
+                height: logo.rectangle.height,
+            });
+        }
+        let (window_width, window_height) = self.window_size(cr);
+        Geometry {
+            scale: cr.matrix().xx(),
+            width: self.width,
+            height: self.height,
+            window_width,
+            window_height,
+            components,
+        }
+    }
+
     pub fn set_keyboard(&self, keyboard: &Keyboard) {
-        if matches!(self.indicator, Indicator::Strings(..)) {
+        if matches!(*self.indicator, Indicator::Strings(..)) {
             let direction = self
                 .config_direction
                 .unwrap_or_else(|| keyboard.get_direction());
@@ -1065,8 +2479,19 @@ impl Dialog {
         debug!("dialog init");
         // TODO can I preserve antialiasing without clearing the image first?
         cr.set_operator(cairo::Operator::Source);
-        cr.set_source(&self.background).unwrap();
-        cr.paint().unwrap();
+        if self.rounded() {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
+            cr.paint().unwrap();
+            // The whole window in dialog coordinates
+            let (x1, y1, x2, y2) = cr.clip_extents().unwrap();
+            let radius = self.corner_radius;
+            Button::rounded_rectangle(cr, radius, radius, x1, y1, x2 - x1, y2 - y1);
+            cr.set_source(&self.background).unwrap();
+            cr.fill().unwrap();
+        } else {
+            cr.set_source(&self.background).unwrap();
+            cr.paint().unwrap();
+        }
         cr.set_operator(cairo::Operator::Over);
         debug!("dialog first paint");
         self.paint(cr);
@@ -1075,13 +2500,29 @@ impl Dialog {
 
     fn paint(&self, cr: &cairo::Context) {
         trace!("paint");
+        if let Some(ref logo) = self.logo {
+            logo.paint(cr);
+        }
+        if !self.mode.takes_input() {
+            // Only the main label
+            self.labels[0].paint(cr);
+            for (i, b) in self.buttons.iter().enumerate() {
+                if self.mode.shows_button(i) {
+                    b.paint(cr);
+                }
+            }
+            return;
+        }
         for l in &self.labels {
             l.paint(cr);
         }
         self.indicator.paint(cr);
-        for b in &self.buttons {
+        for b in self.buttons.iter().chain(self.remember()) {
             b.paint(cr);
         }
+        if let Some(ref chooser) = self.chooser {
+            chooser.paint(cr, &self.background);
+        }
     }
 
     pub fn init_events(&mut self) {
@@ -1096,19 +2537,201 @@ impl Dialog {
         tokio::select! {
             () = self.input_timeout.as_mut().unwrap(), if self.input_timeout_duration.is_some() => {
                 info!("input timeout");
-                Action::Cancel
+                self.timed_out = true;
+                match self.timeout_action {
+                    // Never confirm on behalf of the user
+                    _ if !self.mode.takes_input() => Action::Cancel,
+                    config::TimeoutAction::Cancel => Action::Cancel,
+                    config::TimeoutAction::Accept => self.accept_on_timeout(),
+                    config::TimeoutAction::AcceptEmpty => {
+                        self.indicator.pass_clear();
+                        self.update_ok();
+                        self.accept_on_timeout()
+                    }
+                }
             }
             () = self.indicator.handle_events() => {
                 Action::Nothing
             }
+            () = sleep_until(self.countdown_tick.unwrap_or_else(Instant::now)), if self.countdown_tick.is_some() => {
+                self.update_countdown();
+                Action::Nothing
+            }
+            () = sleep_until(self.layout_notice_until.unwrap_or_else(Instant::now)), if self.layout_notice_until.is_some() => {
+                self.layout_notice_until = None;
+                self.indicator.set_notice(None);
+                Action::Nothing
+            }
+            () = sleep_until(self.backoff_until.unwrap_or_else(Instant::now)), if self.backoff_until.is_some() => {
+                debug!("backoff over");
+                self.backoff_until = None;
+                self.update_ok();
+                self.dirty = true;
+                Action::Nothing
+            }
+            () = sleep_until(self.submit_armed_until.unwrap_or_else(Instant::now)), if self.submit_armed_until.is_some() => {
+                debug!("submit disarmed");
+                self.submit_armed_until = None;
+                self.buttons[0].set_hover(false);
+                Action::Nothing
+            }
             else => std::future::pending().await
         }
     }
 
+    /// Shows the time left until `deadline` under the label.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+        self.update_countdown();
+    }
+
+    /// Keeps room under the label for `focus_hint`.
+    pub fn reserve_focus_hint(&mut self) {
+        self.label_text.focus_hint = Some((self.focus_hint.clone(), false));
+        self.label_text_changed();
+    }
+
+    /// Shows or hides `focus_hint` if room has been kept for it.
+    pub fn show_focus_hint(&mut self, show: bool) {
+        let Some((_, ref mut shown)) = self.label_text.focus_hint else {
+            return;
+        };
+        if *shown == show {
+            return;
+        }
+        *shown = show;
+        if let Label::TextLabel(ref label) = self.labels[0] {
+            self.label_text.set_layout(&label.layout);
+        }
+        self.dirty = true;
+    }
+
+    /// Replaces the label text while the dialog is shown.
+    pub fn set_label(&mut self, label: &str) {
+        debug!("label updated");
+        self.label_text.label = label.into();
+        self.label_text_changed();
+    }
+
+    /// Shows why the dialog was brought up again in a strip above the indicator.
+    pub fn set_error(&mut self, error: &str) {
+        self.label_text.error = Some(error.into());
+        self.label_text_changed();
+    }
+
+    fn set_warning(&mut self, warning: Option<String>) {
+        self.label_text.warning = warning;
+        self.label_text_changed();
+    }
+
+    fn update_countdown(&mut self) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+        self.countdown_tick = secs
+            .checked_sub(1)
+            .map(|next| deadline - Duration::from_secs(next));
+        self.label_text.countdown = Some(format!("{}:{:02}", secs / 60, secs % 60));
+        self.label_text_changed();
+    }
+
+    // Lays out again for the new text, at the width the label was wrapped to
+    fn label_text_changed(&mut self) {
+        if let Label::TextLabel(ref mut label) = self.labels[0] {
+            self.label_text.set_layout(&label.layout);
+            label.calc_extents(None, false);
+        }
+        self.relayout(self.reflow_width);
+    }
+
+    /// Shows the name of the keyboard layout for a moment after it has changed.
+    pub fn layout_changed(&mut self, name: Option<&str>) {
+        let Some(name) = name.filter(|_| self.show_layout_change && self.mode.takes_input()) else {
+            return;
+        };
+        let layout = pango::Layout::new(&self.pango_context);
+        layout.set_markup(&format!(
+            "<span size=\"small\">{}</span>",
+            pango::glib::markup_escape_text(name)
+        ));
+        self.indicator.set_notice(Some(layout));
+        self.layout_notice_until = Some(Instant::now() + Duration::from_millis(1500));
+    }
+
+    /// Shows "attempt N of M" under the label. From the second attempt on OK is held off
+    /// for `retry_backoff`, doubled for each attempt after that.
+    pub fn set_attempt(&mut self, attempt: u32, attempts: Option<u32>) {
+        self.label_text.attempt = Some(match attempts {
+            Some(attempts) => format!("attempt {attempt} of {attempts}"),
+            None => format!("attempt {attempt}"),
+        });
+        self.label_text_changed();
+        if attempt > 1 && !self.retry_backoff.is_zero() {
+            let delay = self.retry_backoff * (1 << (attempt - 2).min(10));
+            debug!("holding off OK for {delay:?}");
+            self.backoff_until = Some(Instant::now() + delay);
+            self.update_ok();
+        }
+    }
+
+    /// Warns about a new passphrase with fewer than `min_bits` of estimated entropy.
+    pub fn set_min_entropy(&mut self, min_bits: u32) {
+        self.min_entropy = Some(min_bits);
+    }
+
+    /// Fills the indicator with dummy data for `--demo`.
+    pub fn fill_demo(&mut self) {
+        self.indicator
+            .pass_insert("correct horse battery staple", false);
+        self.update_ok();
+        if !self.indicator.has_plaintext() {
+            self.force_plaintext_button = true;
+            self.relayout(None);
+        }
+    }
+
+    /// Inserts the contents of a selection. The data comes from another client and is untrusted.
+    pub fn paste(&mut self, value: Vec<u8>) -> Action {
+        let was_empty = self.indicator.pass.len == 0;
+        let pasted = match String::from_utf8(value) {
+            Err(err) => {
+                warn!("selection is not valid utf8: {}", err);
+                err.into_bytes().zeroize();
+                false
+            }
+            Ok(mut val) => {
+                self.indicator.pass_insert(&val, true);
+                let pasted = !val.is_empty();
+                self.pasted |= pasted;
+                val.zeroize();
+                pasted
+            }
+        };
+        self.check_edited();
+        self.update_ok();
+        match self.submit_on_paste {
+            config::SubmitOnPaste::Always if pasted => self.submit_key(Duration::ZERO),
+            config::SubmitOnPaste::IfEmpty if pasted && was_empty => {
+                self.submit_key(Duration::ZERO)
+            }
+            _ => Action::Nothing,
+        }
+    }
+
     pub fn handle_motion(&mut self, x: f64, y: f64, xcontext: &XContext) -> Result<()> {
+        if self.chooser_is_open() {
+            let button_pressed = self.button_pressed;
+            for b in self.chooser_buttons_mut() {
+                let hover = b.is_inside(x, y);
+                b.set_hover(hover && button_pressed == b.pressed);
+            }
+            return self.indicator.set_hover(false, xcontext);
+        }
         let mut found = false;
-        for b in &mut self.buttons {
-            if found {
+        for (i, b) in self.buttons.iter_mut().enumerate() {
+            if found || !self.mode.shows_button(i) {
                 b.set_hover(false);
             } else if b.is_inside(x, y) {
                 b.set_hover(self.button_pressed == b.pressed);
@@ -1117,7 +2740,13 @@ impl Dialog {
                 b.set_hover(false);
             }
         }
-        if !found && self.indicator.is_inside(x, y) {
+        let button_pressed = self.button_pressed;
+        if let Some(b) = self.remember_mut() {
+            let hover = !found && b.is_inside(x, y);
+            b.set_hover(hover && button_pressed == b.pressed);
+            found |= hover;
+        }
+        if !found && self.mode.takes_input() && self.indicator.is_inside(x, y) {
             self.indicator.set_hover(true, xcontext)?;
         } else {
             self.indicator.set_hover(false, xcontext)?;
@@ -1132,6 +2761,120 @@ impl Dialog {
         for b in &mut self.buttons {
             b.label.cairo_context_changed(cr);
         }
+        #[cfg(feature = "keyring")]
+        self.remember.label.cairo_context_changed(cr);
+        if let Some(ref mut chooser) = self.chooser {
+            chooser.cairo_context_changed(cr);
+        }
+    }
+
+    // Renders the gradients again for the device scale if it changed.
+    fn set_dither(&mut self, scale: f64) {
+        if self.dither == Some(scale) {
+            return;
+        }
+        debug!("dithering gradients for scale {}", scale);
+        self.dither = Some(scale);
+        self.indicator.set_dither(Some(scale));
+        for button in &mut self.buttons {
+            button.set_dither(Some(scale));
+        }
+        #[cfg(feature = "keyring")]
+        self.remember.set_dither(Some(scale));
+    }
+
+    /// The middle of the dialog.
+    pub fn center(&self) -> (f64, f64) {
+        (self.width / 2.0, self.height / 2.0)
+    }
+
+    /// The middle of the OK button.
+    pub fn ok_center(&self) -> (f64, f64) {
+        let ok = &self.buttons[0];
+        (ok.x + ok.width / 2.0, ok.y + ok.height / 2.0)
+    }
+
+    /// Moves the window when dragged by an empty spot.
+    pub fn set_draggable(&mut self, draggable: bool) {
+        self.draggable = draggable;
+    }
+
+    /// Lays the label out again for the window width when the window is resized.
+    pub fn set_reflow(&mut self, reflow: bool) {
+        self.reflow = reflow;
+    }
+
+    /// Scales the dialog by `factor` relative to the configured scale, e.g. on a monitor with
+    /// a different pixel density. Returns whether the scale changed.
+    pub fn rescale(&mut self, cr: &cairo::Context, factor: f64) -> bool {
+        let scale = self.base_scale * factor;
+        if (scale - self.scale).abs() < 0.01 {
+            return false;
+        }
+        debug!("scale: {}", scale);
+        self.scale = scale;
+        let mut m = cr.matrix();
+        m.set_xx(scale);
+        m.set_yy(scale);
+        cr.set_matrix(m);
+        // Laid out again for the new size
+        self.window_width = None;
+        true
+    }
+
+    /// Spreads the dialog out when the window is maximized or fullscreen. Returns whether it
+    /// changed.
+    pub fn set_maximized(&mut self, maximized: bool) -> bool {
+        debug!("maximized: {}", maximized);
+        let changed = self.maximized != maximized;
+        self.maximized = maximized;
+        changed
+    }
+
+    // Runs the layout again, letting the label wrap at the available width if given.
+    fn relayout(&mut self, available: Option<f64>) {
+        debug!("relayout for width {:?}", available);
+        self.laid_out_maximized = self.maximized;
+        if let Label::TextLabel(ref label) = self.labels[0] {
+            label.layout.set_width(-1);
+        }
+        let mut components = Components {
+            clipboard_config: None,
+            plaintext_config: self.plaintext_config.take(),
+            icon_theme: self.icon_theme.take(),
+            labels: std::mem::take(&mut self.labels),
+            indicator_label_text: String::new(),
+            indicator_label_foreground: None,
+            pango_context: self.pango_context.clone(),
+            buttons: std::mem::take(&mut self.buttons),
+            logo: self.logo.take(),
+            text_height: self.text_height,
+            force_plaintext_button: self.force_plaintext_button
+                || self.password_indicator().has_plaintext(),
+        };
+        let mut layout_opts = if self.maximized {
+            self.layout_opts
+                .spaced(self.maximized_opts.spacing, self.text_height)
+        } else {
+            self.layout_opts.clone()
+        };
+        let mut limits = self.limits;
+        if let Some(available) = available {
+            layout_opts.text_width = None;
+            limits.max_width = Some(limits.max_width.map_or(available, |w| w.min(available)));
+        }
+        (self.width, self.height) =
+            layout::arrange(&layout_opts, &limits, &mut components, &mut self.indicator);
+        self.buttons = components.buttons;
+        self.plaintext_config = components.plaintext_config;
+        self.icon_theme = components.icon_theme;
+        self.labels = components.labels;
+        self.logo = components.logo;
+        #[cfg(feature = "keyring")]
+        if self.keyring.is_some() {
+            self.place_remember();
+        }
+        self.dirty = true;
     }
 
     pub fn resize(&mut self, cr: &cairo::Context, width: u16, height: u16, surface_cleared: bool) {
@@ -1151,7 +2894,34 @@ impl Dialog {
         }
         cr.set_operator(cairo::Operator::Over);
 
+        // The first size is the one the dialog was laid out for
+        let window_width = self.window_width.unwrap_or_else(|| self.window_size(cr).0);
+        let zoom = if self.maximized {
+            self.maximized_opts.scale.unwrap_or(1.0)
+        } else {
+            1.0
+        };
+        if (self.reflow && width != window_width) || self.maximized != self.laid_out_maximized {
+            let available = self
+                .reflow
+                .then(|| (f64::from(width) / (self.scale * zoom)).floor());
+            self.reflow_width = available;
+            self.relayout(available);
+        }
+        self.window_width = Some(width);
+
         let mut m = cr.matrix();
+        // As large as fits
+        let zoom = zoom
+            .min(f64::from(width) / (self.width * self.scale))
+            .min(f64::from(height) / (self.height * self.scale))
+            .max(1.0);
+        m.set_xx(self.scale * zoom);
+        m.set_yy(self.scale * zoom);
+        cr.set_matrix(m);
+        if self.dither.is_some() {
+            self.set_dither(self.scale * zoom);
+        }
 
         let (dialog_width, dialog_height) = self.window_size(cr);
         if width > dialog_width {
@@ -1215,51 +2985,161 @@ impl Dialog {
         };
 
         match action {
-            Action::Ok => return Ok(Action::Ok),
+            Action::Ok => return Ok(self.submit()),
             Action::Cancel => return Ok(Action::Cancel),
+            Action::PastePrimary | Action::PasteClipboard | Action::PlainText
+                if !self.mode.takes_input() => {}
             Action::PastePrimary => {
                 xcontext.paste_primary()?;
             }
             Action::PasteClipboard => {
                 xcontext.paste_clipboard()?;
             }
-            Action::PlainText => {
-                self.indicator.toggle_plaintext();
-                self.buttons[3].toggle();
-            }
+            Action::PlainText => self.toggle_plaintext(),
+            Action::Move => xcontext.start_move()?,
             Action::Nothing => {}
         }
 
         Ok(Action::Nothing)
     }
 
+    fn chooser_is_open(&self) -> bool {
+        self.chooser.as_ref().is_some_and(chooser::Chooser::is_open)
+    }
+
+    fn chooser_buttons(&self) -> &[Button] {
+        self.chooser.as_ref().map_or(&[], chooser::Chooser::buttons)
+    }
+
+    fn chooser_buttons_mut(&mut self) -> &mut [Button] {
+        self.chooser
+            .as_mut()
+            .map_or(&mut [], chooser::Chooser::buttons_mut)
+    }
+
+    fn open_chooser(&mut self) {
+        if let Some(ref mut chooser) = self.chooser {
+            for b in &mut self.buttons {
+                b.set_hover(false);
+            }
+            chooser.open(&self.buttons[2], self.height);
+            self.dirty = true;
+        }
+    }
+
+    fn close_chooser(&mut self) {
+        if let Some(ref mut chooser) = self.chooser {
+            chooser.close();
+            self.dirty = true;
+        }
+    }
+
+    fn clipboard_released(&mut self) -> Action {
+        let long_press = self
+            .clipboard_pressed_at
+            .take()
+            .is_some_and(|at| at.elapsed() >= chooser::LONG_PRESS);
+        match self.paste_source {
+            config::PasteSource::Primary => Action::PastePrimary,
+            config::PasteSource::Choose if long_press => {
+                self.open_chooser();
+                Action::Nothing
+            }
+            config::PasteSource::Clipboard | config::PasteSource::Choose => Action::PasteClipboard,
+        }
+    }
+
+    fn toggle_plaintext(&mut self) {
+        let indicator = self.password_indicator();
+        if indicator.has_plaintext() {
+            indicator.toggle_plaintext();
+        }
+        // Only some layouts have the button
+        if let Some(button) = self.buttons.get_mut(3) {
+            button.toggle();
+        }
+    }
+
+    // A submit key without modifiers works with any held, as Return always has
+    fn is_submit_key(&self, key_sym: Keysym, keyboard: &Keyboard) -> bool {
+        [&self.submit_key, &self.keypad_submit_key]
+            .into_iter()
+            .flatten()
+            .any(|b| {
+                let (chord, syms) = b;
+                let bare = !(chord.ctrl || chord.shift || chord.alt);
+                (bare && syms.contains(&key_sym)) || is_bound(Some(b), key_sym, keyboard)
+            })
+    }
+
+    fn is_plaintext_key(&mut self, key_sym: Keysym, keyboard: &Keyboard) -> bool {
+        self.password_indicator().has_plaintext()
+            && is_bound(self.plaintext_key.as_ref(), key_sym, keyboard)
+    }
+
     // Return true iff dialog should be repainted
     fn handle_mouse_left_button_press(&mut self, x: f64, y: f64, release: bool) -> Action {
+        if let Some(chooser) = self.chooser.as_mut().filter(|c| c.is_open()) {
+            if release {
+                self.button_pressed = false;
+                if let Some(action) = chooser.release(x, y) {
+                    self.close_chooser();
+                    return action;
+                }
+            } else if let Some(b) = chooser.buttons_mut().iter_mut().find(|b| b.is_inside(x, y)) {
+                b.set_pressed(true);
+                self.button_pressed = true;
+            } else {
+                // Clicking anywhere else dismisses it
+                self.close_chooser();
+            }
+            return Action::Nothing;
+        }
         if release {
             self.button_pressed = false;
+            if let Some(b) = self.remember_mut().filter(|b| b.pressed) {
+                b.set_pressed(false);
+                if b.is_inside(x, y) {
+                    b.toggle();
+                }
+                return Action::Nothing;
+            }
             for (i, b) in self.buttons.iter_mut().enumerate() {
                 if b.pressed {
                     b.set_pressed(false);
                     if b.is_inside(x, y) {
                         trace!("release inside button {}", i);
+                        if i == 2 {
+                            return self.clipboard_released();
+                        }
                         return Components::ACTIONS[i];
                     }
                     return Action::Nothing;
                 }
             }
         } else {
-            let inside = self.indicator.set_cursor(x, y);
-            if inside {
+            if self.mode.takes_input() && self.indicator.set_cursor(x, y) {
                 return Action::Nothing;
             }
             for (i, b) in self.buttons.iter_mut().enumerate() {
-                if b.is_inside(x, y) {
+                if self.mode.shows_button(i) && b.is_inside(x, y) {
                     trace!("inside button {}", i);
+                    if i == 2 {
+                        self.clipboard_pressed_at = Some(Instant::now());
+                    }
                     b.set_pressed(true);
                     self.button_pressed = true;
                     return Action::Nothing;
                 }
             }
+            if let Some(b) = self.remember_mut().filter(|b| b.is_inside(x, y)) {
+                b.set_pressed(true);
+                self.button_pressed = true;
+                return Action::Nothing;
+            }
+            if self.draggable {
+                return Action::Move;
+            }
         }
         Action::Nothing
     }
@@ -1290,7 +3170,47 @@ impl Dialog {
         buf
     }
 
+    // Feeds the key to the compose state. Returns whether the key_sym was replaced by a composed
+    // one or None if the key was consumed.
+    fn compose(&mut self, keyboard: &Keyboard, key_sym: &mut Keysym) -> Option<bool> {
+        let Some(ref compose) = keyboard.compose else {
+            return Some(false);
+        };
+        if compose.state_feed(*key_sym) != xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED {
+            return Some(false);
+        }
+        match compose.state_get_status() {
+            xkb_compose_status::XKB_COMPOSE_NOTHING => Some(false),
+            xkb_compose_status::XKB_COMPOSE_COMPOSING => {
+                if let Some(c) =
+                    keyboard::keysym_to_char(*key_sym).or_else(|| keyboard::dead_key_char(*key_sym))
+                {
+                    self.indicator.preedit_push(c);
+                }
+                None
+            }
+            xkb_compose_status::XKB_COMPOSE_COMPOSED => {
+                self.indicator.preedit_clear();
+                *key_sym = compose.state_get_one_sym();
+                Some(true)
+            }
+            xkb_compose_status::XKB_COMPOSE_CANCELLED => {
+                self.indicator.preedit_clear();
+                compose.state_reset();
+                None
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub fn handle_key_press(&mut self, key: Keycode, xcontext: &mut XContext) -> Result<Action> {
+        let action = self.handle_key_press_do(key, xcontext)?;
+        self.check_edited();
+        self.update_ok();
+        Ok(action)
+    }
+
+    fn handle_key_press_do(&mut self, key: Keycode, xcontext: &mut XContext) -> Result<Action> {
         if let Some(timeout) = self.input_timeout_duration {
             self.input_timeout
                 .as_mut()
@@ -1301,50 +3221,64 @@ impl Dialog {
 
         let keyboard = xcontext.keyboard();
         let mut key_sym = keyboard.key_get_one_sym(key);
-        if self.debug {
-            debug!("key: {:#x}, key_sym {:#x}", key, key_sym);
-        }
+        debug!(
+            "key: {:?}, key_sym {:?}",
+            Sensitive(key),
+            Sensitive(key_sym)
+        );
 
-        let mut composed = false;
-        if let Some(ref compose) = keyboard.compose {
-            if compose.state_feed(key_sym) == xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED {
-                match compose.state_get_status() {
-                    xkb_compose_status::XKB_COMPOSE_NOTHING => {}
-                    xkb_compose_status::XKB_COMPOSE_COMPOSING => {
-                        return Ok(Action::Nothing);
-                    }
-                    xkb_compose_status::XKB_COMPOSE_COMPOSED => {
-                        key_sym = compose.state_get_one_sym();
-                        composed = true;
-                    }
-                    xkb_compose_status::XKB_COMPOSE_CANCELLED => {
-                        compose.state_reset();
-                        return Ok(Action::Nothing);
-                    }
-                    _ => unreachable!(),
+        let Some(composed) = self.compose(keyboard, &mut key_sym) else {
+            return Ok(Action::Nothing);
+        };
+
+        if !self.mode.takes_input() {
+            let action = match key_sym {
+                keysyms::XKB_KEY_Return | keysyms::XKB_KEY_KP_Enter
+                    if self.mode == Mode::Confirm =>
+                {
+                    Action::Ok
                 }
-            }
+                _ if is_any_bound(&self.cancel_keys, key_sym, keyboard)
+                    || is_any_bound(&self.confirm_cancel_keys, key_sym, keyboard) =>
+                {
+                    Action::Cancel
+                }
+                _ => Action::Nothing,
+            };
+            key_sym.zeroize();
+            return Ok(action);
         }
 
-        let ctrl = xcontext.keyboard().mod_name_is_active(
-            keyboard::names::XKB_MOD_NAME_CTRL,
-            keyboard::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-        );
+        let ctrl = keyboard.ctrl_active();
 
         let mut matched = true;
         let mut action = Action::Nothing;
         match key_sym {
-            keysyms::XKB_KEY_Return | keysyms::XKB_KEY_KP_Enter => {
-                action = Action::Ok;
+            _ if self.is_plaintext_key(key_sym, keyboard) => self.toggle_plaintext(),
+            _ if is_bound(self.wipe_key.as_ref(), key_sym, keyboard) => action = self.wipe(),
+            keysyms::XKB_KEY_Escape if self.chooser_is_open() => self.close_chooser(),
+            _ if self.is_submit_key(key_sym, keyboard) => {
+                action = self.submit_key(keyboard.access_x_delay());
             }
             keysyms::XKB_KEY_j | keysyms::XKB_KEY_m if ctrl => {
-                action = Action::Ok;
+                action = self.submit_key(keyboard.access_x_delay());
+            }
+            _ if is_any_bound(&self.cancel_keys, key_sym, keyboard) => {
+                action = self.cancel_key();
+            }
+            _ if is_bound(self.delete_word_key.as_ref(), key_sym, keyboard) => {
+                self.indicator.pass_delete(true);
+            }
+            _ if is_bound(self.delete_char_key.as_ref(), key_sym, keyboard) => {
+                self.indicator.pass_delete(false);
             }
-            keysyms::XKB_KEY_Escape => {
-                action = Action::Cancel;
+            _ if is_bound(self.theme_key.as_ref(), key_sym, keyboard) => self.next_theme(),
+            _ if is_bound(self.layout_switch_key.as_ref(), key_sym, keyboard) => {
+                keyboard.lock_next_layout()?;
             }
-            keysyms::XKB_KEY_BackSpace => self.indicator.pass_delete(ctrl),
-            keysyms::XKB_KEY_h if ctrl => self.indicator.pass_delete(false),
+            keysyms::XKB_KEY_Tab if self.asks_username() => action = self.submit(),
+            keysyms::XKB_KEY_ISO_Left_Tab if self.username.is_some() => self.edit_username(),
+            keysyms::XKB_KEY_BackSpace => self.indicator.pass_delete(false),
             keysyms::XKB_KEY_u if ctrl => self.indicator.pass_clear(),
             keysyms::XKB_KEY_v if ctrl => {
                 xcontext.paste_clipboard()?;
@@ -1356,7 +3290,7 @@ impl Dialog {
                 .indicator
                 .move_visually(indicator::Direction::Right, ctrl),
             keysyms::XKB_KEY_Insert
-                if xcontext.keyboard().mod_name_is_active(
+                if keyboard.mod_name_is_active(
                     keyboard::names::XKB_MOD_NAME_SHIFT,
                     keyboard::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
                 ) =>
@@ -1374,10 +3308,43 @@ impl Dialog {
 
         let buf = Self::get_secure_utf8_do(xcontext.keyboard(), key, composed);
         let s = unsafe { std::str::from_utf8_unchecked(buf.unsecure()) };
-        if !s.is_empty() {
+        // Control characters from unbound Ctrl keys, but a tab still goes in
+        if !(s.is_empty() || (ctrl && s.chars().all(char::is_control))) {
             self.indicator.pass_insert(s, false);
-            return Ok(Action::Nothing);
+            if !self.indicator.has_feedback() {
+                xcontext.flash_led()?;
+            }
         }
         Ok(Action::Nothing)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guess_ssh_prompts() {
+        assert_eq!(
+            Mode::guess(
+                "The authenticity of host 'example.org (192.0.2.1)' can't be established.\n\
+                 ED25519 key fingerprint is SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU.\n\
+                 Are you sure you want to continue connecting (yes/no/[fingerprint])?"
+            ),
+            Mode::Secret
+        );
+        assert_eq!(
+            Mode::guess("Are you sure you want to continue connecting (yes/no)?"),
+            Mode::Confirm
+        );
+        assert_eq!(
+            Mode::guess("Confirm user presence for key ED25519-SK SHA256:47DEQpj8HBSa"),
+            Mode::Touch
+        );
+        assert_eq!(
+            Mode::guess("Enter passphrase for key '/home/user/.ssh/id_ed25519': "),
+            Mode::Secret
+        );
+        assert_eq!(Mode::guess("user@example.org's password: "), Mode::Secret);
+    }
+}
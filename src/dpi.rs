@@ -0,0 +1,301 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use log::{debug, warn};
+
+use x11rb::connection::{Connection as _, RequestConnection as _};
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+
+use crate::errors::{Context as _, Result};
+use crate::{monitor, Connection};
+
+#[derive(Debug, PartialEq)]
+pub enum XSetting {
+    Int(i32),
+    String(String),
+    Color,
+}
+
+/// Prints what is known about the screens and their resolution for bug reports about scaling.
+pub fn print_info(conn: &Connection, screen_num: usize, scale: Option<f64>) -> Result<()> {
+    let mut out = String::new();
+    let setup = conn.setup();
+    for (i, s) in setup.roots.iter().enumerate() {
+        let current = if i == screen_num { " (used)" } else { "" };
+        writeln!(
+            out,
+            "screen {i}{current}: {}x{} px, {}x{} mm",
+            s.width_in_pixels, s.height_in_pixels, s.width_in_millimeters, s.height_in_millimeters,
+        )
+        .unwrap();
+    }
+    let Some(screen) = setup.roots.get(screen_num) else {
+        crate::bail!("no screen {}", screen_num);
+    };
+
+    randr_outputs(conn, screen.root, &mut out)?;
+    let monitors = monitor::monitors(conn, screen)?;
+    for m in &monitors {
+        let primary = if m.primary { " primary" } else { "" };
+        writeln!(
+            out,
+            "monitor{primary}: {}x{}+{}+{} px, {}x{} mm",
+            m.width, m.height, m.x, m.y, m.width_mm, m.height_mm
+        )
+        .unwrap();
+    }
+
+    let db = x11rb::resource_manager::new_from_default(conn)?;
+    writeln!(
+        out,
+        "Xft.dpi: {}",
+        db.get_string("Xft.dpi", "").unwrap_or("unset")
+    )
+    .unwrap();
+
+    match xsettings(conn, screen_num)? {
+        None => writeln!(out, "XSETTINGS: no manager").unwrap(),
+        Some(settings) => {
+            for (name, value) in settings {
+                let value = match value {
+                    XSetting::Int(dpi) if name.ends_with("DPI") => {
+                        format!("{}", f64::from(dpi) / 1024.0)
+                    }
+                    XSetting::Int(value) => value.to_string(),
+                    _ => continue,
+                };
+                if [
+                    "Gdk/WindowScalingFactor",
+                    "Gdk/UnscaledDPI",
+                    "Xft/DPI",
+                    "Xaskpass/ReduceTransparency",
+                ]
+                .contains(&&*name)
+                {
+                    writeln!(out, "XSETTINGS {name}: {value}").unwrap();
+                }
+            }
+        }
+    }
+
+    let px_per_mm = monitor::px_per_mm(&monitors, screen);
+    writeln!(
+        out,
+        "xaskpass: scale {}, {:.2} px/mm ({:.0} dpi)",
+        self::scale(conn, screen_num, scale).unwrap_or(1.0),
+        px_per_mm,
+        px_per_mm * 25.4
+    )
+    .unwrap();
+    std::io::stdout()
+        .write_all(out.as_bytes())
+        .context("write dpi info")?;
+    Ok(())
+}
+
+fn randr_outputs(conn: &Connection, root: xproto::Window, out: &mut String) -> Result<()> {
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        writeln!(out, "RANDR: not available").unwrap();
+        return Ok(());
+    }
+    let version = conn.randr_query_version(1, 3)?.reply()?;
+    writeln!(
+        out,
+        "RANDR {}.{}",
+        version.major_version, version.minor_version
+    )
+    .unwrap();
+    let (outputs, timestamp) = if version.minor_version >= 3 {
+        let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+        (resources.outputs, resources.config_timestamp)
+    } else {
+        let resources = conn.randr_get_screen_resources(root)?.reply()?;
+        (resources.outputs, resources.config_timestamp)
+    };
+    for output in outputs {
+        let info = conn.randr_get_output_info(output, timestamp)?.reply()?;
+        if info.connection != randr::Connection::CONNECTED {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&info.name);
+        write!(
+            out,
+            "output {name}: {}x{} mm",
+            info.mm_width, info.mm_height
+        )
+        .unwrap();
+        if info.crtc != x11rb::NONE {
+            let crtc = conn.randr_get_crtc_info(info.crtc, timestamp)?.reply()?;
+            write!(
+                out,
+                ", {}x{}+{}+{} px",
+                crtc.width, crtc.height, crtc.x, crtc.y
+            )
+            .unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    Ok(())
+}
+
+/// The configured scale multiplied by the `Gdk/WindowScalingFactor` of the XSETTINGS manager.
+pub fn scale(conn: &Connection, screen_num: usize, scale: Option<f64>) -> Option<f64> {
+    let factor = match xsettings(conn, screen_num) {
+        Ok(settings) => settings
+            .into_iter()
+            .flatten()
+            .find_map(|(name, value)| match value {
+                XSetting::Int(factor) if name == "Gdk/WindowScalingFactor" && factor > 1 => {
+                    Some(f64::from(factor))
+                }
+                _ => None,
+            }),
+        Err(err) => {
+            warn!("XSETTINGS: {}", err);
+            None
+        }
+    };
+    debug!("window scaling factor {:?}", factor);
+    match factor {
+        Some(factor) => Some(scale.unwrap_or(1.0) * factor),
+        None => scale,
+    }
+}
+
+/// The integer XSETTINGS setting `name` if there is one.
+pub fn xsetting_int(conn: &Connection, screen_num: usize, name: &str) -> Option<i32> {
+    let settings = xsettings(conn, screen_num)
+        .map_err(|err| warn!("XSETTINGS: {}", err))
+        .ok()??;
+    settings.into_iter().find_map(|(n, value)| match value {
+        XSetting::Int(value) if n == name => Some(value),
+        _ => None,
+    })
+}
+
+/// The string XSETTINGS setting `name` if there is one.
+pub fn xsetting_string(conn: &Connection, screen_num: usize, name: &str) -> Option<String> {
+    let settings = xsettings(conn, screen_num)
+        .map_err(|err| warn!("XSETTINGS: {}", err))
+        .ok()??;
+    settings.into_iter().find_map(|(n, value)| match value {
+        XSetting::String(value) if n == name => Some(value),
+        _ => None,
+    })
+}
+
+/// The settings of the XSETTINGS manager of the screen if there is one.
+pub fn xsettings(conn: &Connection, screen_num: usize) -> Result<Option<Vec<(String, XSetting)>>> {
+    let selection = format!("_XSETTINGS_S{screen_num}");
+    let selection = conn.intern_atom(false, selection.as_bytes())?;
+    let settings = conn.intern_atom(false, b"_XSETTINGS_SETTINGS")?;
+    let owner = conn
+        .get_selection_owner(selection.reply()?.atom)?
+        .reply()?
+        .owner;
+    let settings = settings.reply()?.atom;
+    if owner == x11rb::NONE {
+        return Ok(None);
+    }
+    let reply = conn
+        .get_property(false, owner, settings, settings, 0, u32::MAX / 4)?
+        .reply()?;
+    Ok(parse_xsettings(&reply.value))
+}
+
+fn parse_xsettings(data: &[u8]) -> Option<Vec<(String, XSetting)>> {
+    let big_endian = match data.first()? {
+        0 => false,
+        1 => true,
+        _ => return None,
+    };
+    let u16_at = |i: usize| -> Option<u16> {
+        let bytes = data.get(i..i + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |i: usize| -> Option<u32> {
+        let bytes = data.get(i..i + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let string_at = |i: usize, len: usize| -> Option<String> {
+        Some(String::from_utf8_lossy(data.get(i..i + len)?).into_owned())
+    };
+    let padded = |len: usize| len.div_ceil(4) * 4;
+
+    let count = u32_at(8)?;
+    let mut pos = 12;
+    let mut settings = Vec::new();
+    for _ in 0..count {
+        let kind = *data.get(pos)?;
+        let name_len = usize::from(u16_at(pos + 2)?);
+        let name = string_at(pos + 4, name_len)?;
+        // the name and the serial of the last change
+        pos += 4 + padded(name_len) + 4;
+        let value = match kind {
+            0 => {
+                let value = i32::from_ne_bytes(u32_at(pos)?.to_ne_bytes());
+                pos += 4;
+                XSetting::Int(value)
+            }
+            1 => {
+                let len = usize::try_from(u32_at(pos)?).ok()?;
+                let value = string_at(pos + 4, len)?;
+                pos += 4 + padded(len);
+                XSetting::String(value)
+            }
+            2 => {
+                pos += 8;
+                XSetting::Color
+            }
+            _ => return None,
+        };
+        settings.push((name, value));
+    }
+    Some(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xsettings() {
+        let mut data = vec![0, 0, 0, 0];
+        data.extend(1u32.to_le_bytes()); // serial
+        data.extend(2u32.to_le_bytes());
+        // Xft/DPI = 96 * 1024
+        data.extend([0, 0]);
+        data.extend(7u16.to_le_bytes());
+        data.extend(b"Xft/DPI\0");
+        data.extend(0u32.to_le_bytes());
+        data.extend((96 * 1024i32).to_le_bytes());
+        // Net/ThemeName = "Adwaita"
+        data.extend([1, 0]);
+        data.extend(13u16.to_le_bytes());
+        data.extend(b"Net/ThemeName\0\0\0");
+        data.extend(0u32.to_le_bytes());
+        data.extend(7u32.to_le_bytes());
+        data.extend(b"Adwaita\0");
+
+        assert_eq!(
+            parse_xsettings(&data),
+            Some(vec![
+                ("Xft/DPI".into(), XSetting::Int(96 * 1024)),
+                ("Net/ThemeName".into(), XSetting::String("Adwaita".into())),
+            ])
+        );
+        assert_eq!(parse_xsettings(&data[..data.len() - 8]), None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::Write as _;
+use std::os::unix::io::FromRawFd as _;
+
+use serde::Serialize;
+
+use crate::errors::{Context as _, Result};
+
+/// How the passphrase got into the dialog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Typed,
+    /// At least part of it came from a selection
+    Pasted,
+}
+
+/// What `--result-fd` gets besides the secret on stdout. Never has the secret.
+#[derive(Debug, Serialize)]
+pub struct Envelope<'a> {
+    /// ok, cancel, timeout or error
+    pub outcome: &'a str,
+    pub duration_ms: u64,
+    /// Only when there is a passphrase
+    pub method: Option<Method>,
+    /// From `--attempt`
+    pub retries: u32,
+}
+
+/// Fails unless `fd` is open, and keeps it from the hooks.
+pub fn check_fd(fd: i32) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        Err(std::io::Error::last_os_error()).with_context(|| format!("result fd {fd}"))?;
+    }
+    Ok(())
+}
+
+impl Envelope<'_> {
+    /// Writes the envelope as one JSON line and closes `fd`.
+    pub fn write_fd(&self, fd: i32) -> Result<()> {
+        let mut line = serde_json::to_string(self).context("result envelope")?;
+        line.push('\n');
+        // The fd was handed to us for this alone
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("result fd {fd}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+    use std::os::unix::io::FromRawFd as _;
+
+    use super::*;
+
+    fn pipe() -> (i32, std::fs::File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (fds[1], unsafe { std::fs::File::from_raw_fd(fds[0]) })
+    }
+
+    fn written(envelope: &Envelope<'_>) -> String {
+        let (fd, mut reader) = pipe();
+        check_fd(fd).unwrap();
+        envelope.write_fd(fd).unwrap();
+        let mut line = String::new();
+        // Closed by write_fd, so this reads to the end
+        reader.read_to_string(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn shape() {
+        let line = written(&Envelope {
+            outcome: "ok",
+            duration_ms: 1500,
+            method: Some(Method::Pasted),
+            retries: 2,
+        });
+        assert_eq!(
+            line,
+            "{\"outcome\":\"ok\",\"duration_ms\":1500,\"method\":\"pasted\",\"retries\":2}\n"
+        );
+        let line = written(&Envelope {
+            outcome: "cancel",
+            duration_ms: 0,
+            method: None,
+            retries: 0,
+        });
+        assert_eq!(
+            line,
+            "{\"outcome\":\"cancel\",\"duration_ms\":0,\"method\":null,\"retries\":0}\n"
+        );
+    }
+
+    #[test]
+    fn no_secret() {
+        let value = serde_json::to_value(Envelope {
+            outcome: "ok",
+            duration_ms: 1,
+            method: Some(Method::Typed),
+            retries: 0,
+        })
+        .unwrap();
+        let mut keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        // Nothing else can carry the passphrase
+        assert_eq!(keys, ["duration_ms", "method", "outcome", "retries"]);
+    }
+
+    #[test]
+    fn closed_fd() {
+        // Not one that another test may be opening meanwhile
+        assert!(check_fd(999_999).is_err());
+        assert!(check_fd(-1).is_err());
+    }
+
+    #[test]
+    fn kept_from_hooks() {
+        let (fd, _reader) = pipe();
+        check_fd(fd).unwrap();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        unsafe { libc::close(fd) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+    }
+}
@@ -30,6 +30,8 @@ pub enum Error {
     Unsupported(#[from] Unsupported),
     #[error(transparent)]
     Generic(#[from] anyhow::Error),
+    /// The X connection failed while the dialog was shown
+    Lost(Box<Error>),
 }
 
 impl Display for Error {
@@ -45,6 +47,42 @@ impl Display for Error {
             Self::ReplyOrId(err) => write!(f, "{err}"),
             Self::X11(err) => write!(f, "{err:?}"),
             Self::Unsupported(err) => write!(f, "{err}"),
+            Self::Lost(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error {
+    /// Could not connect to the display at all, e.g. DISPLAY is unset.
+    pub fn is_connect_failure(&self) -> bool {
+        matches!(self, Self::Generic(err) if err.downcast_ref::<x11rb::errors::ConnectError>().is_some())
+    }
+
+    /// The X connection died or the server reported an error.
+    pub fn is_x11_failure(&self) -> bool {
+        match self {
+            Self::X11(_) | Self::Connection(_) | Self::Lost(_) => true,
+            Self::Reply(err) => matches!(
+                err,
+                x11rb::errors::ReplyError::ConnectionError(_)
+                    | x11rb::errors::ReplyError::X11Error(_)
+            ),
+            Self::ReplyOrId(err) => matches!(
+                err,
+                x11rb::errors::ReplyOrIdError::ConnectionError(_)
+                    | x11rb::errors::ReplyOrIdError::X11Error(_)
+            ),
+            Self::Unsupported(_) | Self::Generic(_) => false,
+        }
+    }
+
+    /// Marks an X failure as happening once the dialog was up.
+    #[must_use]
+    pub fn lost(self) -> Self {
+        match self {
+            Self::Lost(_) => self,
+            _ if self.is_x11_failure() => Self::Lost(Box::new(self)),
+            _ => self,
         }
     }
 }
@@ -1,28 +1,78 @@
 use anyhow::Context;
-use log::{debug, trace, warn};
+use std::time::Duration;
+
+use log::{debug, info, trace, warn};
 use tokio::io::unix::AsyncFd;
-use tokio::time::Instant;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
 use x11rb::connection::Connection as _;
 use x11rb::connection::RequestConnection;
+use x11rb::properties;
 use x11rb::protocol::xfixes::{self, ConnectionExt as _};
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::EventMask;
 use x11rb::protocol::xproto::{self, ConnectionExt as _, CursorWrapper, WindowWrapper};
 use x11rb::protocol::Event;
-use zeroize::Zeroize;
+use x11rb::wrapper::ConnectionExt as _;
 
 use crate::backbuffer::Backbuffer;
+use crate::config;
 use crate::dialog::{Action, Dialog};
+use crate::envelope::Method;
 use crate::errors::{Error, Result, Unsupported};
 use crate::keyboard::Keyboard;
-use crate::secret::Passphrase;
+use crate::monitor::Monitor;
+use crate::secret::{Passphrase, Sensitive};
 use crate::Connection;
 
+// Against window managers that refuse it every time
+const MAX_ABOVE_REASSERTS: u32 = 3;
+
+/// The keyring item to store the passphrase as when remember was ticked. Stored only once the
+/// dialog is gone, so that an unlock prompt of the keyring can have the keyboard.
+#[cfg(feature = "keyring")]
+pub type Remember = Option<crate::keyring::Item>;
+#[cfg(not(feature = "keyring"))]
+pub type Remember = Option<std::convert::Infallible>;
+
+/// How the dialog was closed.
+pub enum Outcome {
+    Ok(Passphrase, Method, Remember),
+    Cancelled,
+    /// By `input_timeout`, with the passphrase if the timeout accepts it
+    TimedOut(Option<(Passphrase, Method, Remember)>),
+}
+
 enum State {
     Continue,
     Ready,
     Cancelled,
 }
 
+// How the dialog was closed once the event loop is done with it
+#[cfg_attr(not(feature = "keyring"), allow(unused_mut))]
+fn finish(mut dialog: Dialog, state: &State) -> Outcome {
+    match state {
+        State::Continue => unreachable!(),
+        State::Cancelled if dialog.timed_out() => Outcome::TimedOut(None),
+        State::Cancelled => Outcome::Cancelled,
+        State::Ready => {
+            let timed_out = dialog.timed_out();
+            let method = dialog.input_method();
+            #[cfg(feature = "keyring")]
+            let remember = dialog.take_keyring_item();
+            #[cfg(not(feature = "keyring"))]
+            let remember = None;
+            let pass = dialog.into_pass();
+            if timed_out {
+                Outcome::TimedOut(Some((pass, method, remember)))
+            } else {
+                Outcome::Ok(pass, method, remember)
+            }
+        }
+    }
+}
+
 pub struct Config<'a> {
     pub xfd: &'a AsyncFd<Connection>,
     pub backbuffer: Backbuffer<'a>,
@@ -32,24 +82,80 @@ pub struct Config<'a> {
     pub width: u16,
     pub height: u16,
     pub grab_keyboard: bool,
+    /// Cancel when the focus has been away this long
+    pub focus_loss_grace: Option<Duration>,
     pub startup_time: Instant,
     pub input_cursor: Option<CursorWrapper<&'a Connection>>,
     pub compositor_atom: Option<xproto::Atom>,
-    pub debug: bool,
     pub cycle_deadline: u128,
     pub root: xproto::Window,
+    pub resizable: bool,
+    /// Of the user action that led to the prompt, for the focus stealing prevention
+    pub user_time: Option<u32>,
+    /// What to do if the above state is removed, None if it was not set
+    pub above_removed: Option<config::AboveRemoved>,
+    /// Centered again on this monitor once the frame extents are known
+    pub center: Option<Monitor>,
+    /// Moved back to the center of this monitor if the window manager places it elsewhere
+    pub recenter: Option<Monitor>,
+    pub warp_pointer: config::WarpPointer,
+    pub early_focus_loss: config::EarlyFocusLoss,
+    /// Redraws after key presses wait this long plus a random part of it
+    pub redraw_batch: Option<Duration>,
+    /// The dialog is rescaled when moved between these, empty to never rescale
+    pub monitors: Vec<Monitor>,
+    /// The keyboard LED flashed on each typed character
+    pub feedback_led: Option<xproto::Atom>,
+    /// Index of the keyboard layout locked while focused
+    pub force_layout: Option<u32>,
+    /// New label texts sent while the dialog is shown
+    pub labels: Option<&'a mut mpsc::UnboundedReceiver<String>>,
 }
 
+const LED_FLASH: Duration = Duration::from_millis(50);
+// How long after mapping the window manager may still be placing the window
+const RECENTER_WINDOW: Duration = Duration::from_secs(1);
+// How long after mapping losing the focus is put down to focus-follows-mouse
+const EARLY_FOCUS_WINDOW: Duration = Duration::from_secs(1);
+
 #[allow(clippy::struct_excessive_bools)]
 pub struct XContext<'a> {
     config: Config<'a>,
     keyboard_grabbed: bool,
+    focus_received: bool,
+    focus_lost_at: Option<Instant>,
     first_expose_received: bool,
     xsel_in_progress: bool,
     xfd_eagain: bool,
     xcb_events_queued_maybe: bool,
     x_unflushed_count: u32,
     max_work_time: u128,
+    monitor: Option<usize>,
+    // Whether _NET_WM_STATE has had the above state
+    above: bool,
+    // Maximized or fullscreen, as last told by _NET_WM_STATE
+    maximized: bool,
+    above_reasserted: u32,
+    // Of the monitor the window appeared on
+    monitor_px_per_mm: Option<f64>,
+    // The size last set as _NET_WM_OPAQUE_REGION
+    opaque_region: Option<(u16, u16)>,
+    // When the feedback LED is to be put back
+    led_off_at: Option<Instant>,
+    // Of the feedback LED before the flash
+    led_was_on: bool,
+    // The locked layout before force_layout
+    layout_before_force: Option<u32>,
+    frame_extents: [u32; 4],
+    // Until when a placement by the window manager is corrected
+    recenter_until: Option<Instant>,
+    // Until when not having the focus is taken for focus-follows-mouse
+    early_focus_until: Option<Instant>,
+    // Redraws are held back until then with redraw_batch
+    redraw_at: Option<Instant>,
+    // Sent with the batched redraw so that the key timing does not show
+    deferred_user_time: Option<xproto::Timestamp>,
+    deferred_flash: bool,
 }
 
 impl<'a> Config<'a> {
@@ -67,6 +173,349 @@ impl<'a> XContext<'a> {
         self.config.conn()
     }
 
+    pub fn window_size(&self) -> (u16, u16) {
+        (self.config.width, self.config.height)
+    }
+
+    /// Whether the window is maximized or fullscreen, when its size is not its own.
+    pub fn is_maximized(&self) -> bool {
+        self.maximized
+    }
+
+    // Rescales the dialog if it was moved to a monitor with a different pixel density.
+    fn follow_monitor(&mut self, dialog: &mut Dialog) -> Result<()> {
+        let window = self.config.window.window();
+        let position = self
+            .conn()
+            .translate_coordinates(window, self.config.root, 0, 0)?
+            .reply()?;
+        let x = i32::from(position.dst_x) + i32::from(self.config.width) / 2;
+        let y = i32::from(position.dst_y) + i32::from(self.config.height) / 2;
+        let Some(ix) = self.config.monitors.iter().position(|m| {
+            (i32::from(m.x)..i32::from(m.x) + i32::from(m.width)).contains(&x)
+                && (i32::from(m.y)..i32::from(m.y) + i32::from(m.height)).contains(&y)
+        }) else {
+            return Ok(());
+        };
+        if self.monitor == Some(ix) {
+            return Ok(());
+        }
+        debug!("moved to monitor {:?}", self.config.monitors[ix]);
+        let first = self.monitor.is_none();
+        self.monitor = Some(ix);
+        let px_per_mm = self.config.monitors[ix].px_per_mm();
+        if first {
+            self.monitor_px_per_mm = px_per_mm;
+            return Ok(());
+        }
+        let (Some(base), Some(px_per_mm)) = (self.monitor_px_per_mm, px_per_mm) else {
+            return Ok(());
+        };
+        let cr = &self.config.backbuffer.cr;
+        if !dialog.rescale(cr, px_per_mm / base) {
+            return Ok(());
+        }
+        self.fit_window(dialog, false)
+    }
+
+    // Sizes the window for the dialog after it has been laid out again, only ever making it
+    // larger with `grow_only`.
+    fn fit_window(&mut self, dialog: &Dialog, grow_only: bool) -> Result<()> {
+        let (min_width, min_height) = dialog.window_size(&self.config.backbuffer.cr);
+        let (width, height) = if grow_only {
+            (
+                min_width.max(self.config.width),
+                min_height.max(self.config.height),
+            )
+        } else {
+            (min_width, min_height)
+        };
+        debug!("fitting window width: {}, height: {}", width, height);
+        let mut size_hints = properties::WmSizeHints {
+            min_size: Some((min_width.into(), min_height.into())),
+            ..properties::WmSizeHints::default()
+        };
+        if !self.config.resizable {
+            size_hints.max_size = Some((width.into(), height.into()));
+        }
+        let window = self.config.window.window();
+        size_hints.set_normal_hints(self.conn(), window)?;
+        self.conn().configure_window(
+            window,
+            &xproto::ConfigureWindowAux::new()
+                .width(u32::from(width))
+                .height(u32::from(height)),
+        )?;
+        // Repainted at the new scale even if the size is not changed
+        self.config.backbuffer.resize_requested = Some((self.config.width, self.config.height));
+        Ok(())
+    }
+
+    // Tells the compositor it does not need to blend the window when it is painted opaque.
+    fn update_opaque_region(&mut self, dialog: &Dialog) -> Result<()> {
+        // Windows without an alpha channel are opaque anyway
+        if self.config.compositor_atom.is_none() {
+            return Ok(());
+        }
+        let region = dialog
+            .opaque()
+            .then_some((self.config.width, self.config.height));
+        if region == self.opaque_region {
+            return Ok(());
+        }
+        debug!("opaque region: {:?}", region);
+        self.opaque_region = region;
+        let window = self.config.window.window();
+        let atom = self.config.atoms._NET_WM_OPAQUE_REGION;
+        if let Some((width, height)) = region {
+            self.conn().change_property32(
+                xproto::PropMode::REPLACE,
+                window,
+                atom,
+                xproto::AtomEnum::CARDINAL,
+                &[0, 0, width.into(), height.into()],
+            )?;
+        } else {
+            self.conn().delete_property(window, atom)?;
+        }
+        Ok(())
+    }
+
+    fn set_user_time(&mut self, time: xproto::Timestamp) -> Result<()> {
+        // Once the user acts the window stays where it is
+        self.recenter_until = None;
+        if time == x11rb::CURRENT_TIME || self.config.user_time == Some(time) {
+            return Ok(());
+        }
+        if self.redraw_at.is_some() {
+            self.deferred_user_time = Some(time);
+            return Ok(());
+        }
+        self.config.user_time = Some(time);
+        self.conn().change_property32(
+            xproto::PropMode::REPLACE,
+            self.config.window.window(),
+            self.config.atoms._NET_WM_USER_TIME,
+            xproto::AtomEnum::CARDINAL,
+            &[time],
+        )?;
+        Ok(())
+    }
+
+    // Asks the window manager for the focus, which it may refuse if the time of the user
+    // action is older than that of the active window.
+    fn request_activation(&self, time: xproto::Timestamp) -> Result<()> {
+        debug!("requesting activation at {}", time);
+        // Source 1 is an application
+        let event = xproto::ClientMessageEvent::new(
+            32,
+            self.config.window.window(),
+            self.config.atoms._NET_ACTIVE_WINDOW,
+            [1, time, x11rb::NONE, 0, 0],
+        );
+        self.conn().send_event(
+            false,
+            self.config.root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+            event,
+        )?;
+        Ok(())
+    }
+
+    /// Lets the window manager move the window with the pointer from where the button was
+    /// pressed until it is released.
+    pub fn start_move(&mut self) -> Result<()> {
+        let pointer = self.conn().query_pointer(self.config.root)?.reply()?;
+        debug!("moving from {},{}", pointer.root_x, pointer.root_y);
+        // The window manager needs the pointer that our press grabbed
+        self.conn().ungrab_pointer(x11rb::CURRENT_TIME)?;
+        // Move with button 1 from an application
+        let event = xproto::ClientMessageEvent::new(
+            32,
+            self.config.window.window(),
+            self.config.atoms._NET_WM_MOVERESIZE,
+            [
+                u32::try_from(pointer.root_x).unwrap_or(0),
+                u32::try_from(pointer.root_y).unwrap_or(0),
+                8,
+                1,
+                1,
+            ],
+        );
+        self.conn().send_event(
+            false,
+            self.config.root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+            event,
+        )?;
+        Ok(())
+    }
+
+    // Once, when the focus is lost or never came right after mapping
+    fn early_focus_loss(&mut self, dialog: &mut Dialog) -> Result<()> {
+        self.early_focus_until = None;
+        match self.config.early_focus_loss {
+            config::EarlyFocusLoss::Ignore => {}
+            config::EarlyFocusLoss::Refocus => {
+                debug!("no focus right after mapping, asking again");
+                self.request_activation(self.config.user_time.unwrap_or(x11rb::CURRENT_TIME))?;
+            }
+            config::EarlyFocusLoss::Hint => {
+                debug!("no focus right after mapping, showing the hint");
+                dialog.show_focus_hint(true);
+            }
+        }
+        Ok(())
+    }
+
+    // Only the first time it is shown
+    fn warp_pointer(&mut self, dialog: &Dialog) -> Result<()> {
+        let (x, y) =
+            match std::mem::replace(&mut self.config.warp_pointer, config::WarpPointer::Never) {
+                config::WarpPointer::Never => return Ok(()),
+                config::WarpPointer::Center => dialog.center(),
+                config::WarpPointer::Ok => dialog.ok_center(),
+            };
+        let (x, y) = self.config.backbuffer.cr.user_to_device(x, y);
+        debug!("warping the pointer to {}, {}", x, y);
+        self.conn().warp_pointer(
+            x11rb::NONE,
+            self.config.window.window(),
+            0,
+            0,
+            0,
+            0,
+            x.round() as i16,
+            y.round() as i16,
+        )?;
+        Ok(())
+    }
+
+    // Moves the window to the center once if the window manager has put it elsewhere
+    fn recenter(&mut self) -> Result<()> {
+        let Some(monitor) = self.config.recenter else {
+            return Ok(());
+        };
+        let window = self.config.window.window();
+        let position = self
+            .conn()
+            .translate_coordinates(window, self.config.root, 0, 0)?
+            .reply()?;
+        let size = (self.config.width, self.config.height);
+        let (x, y) = monitor.centered(size, self.frame_extents);
+        let [left, _, top, _] = self.frame_extents.map(|e| i32::try_from(e).unwrap_or(0));
+        let (dx, dy) = (
+            i32::from(position.dst_x) - left - x,
+            i32::from(position.dst_y) - top - y,
+        );
+        if dx.abs() <= 1 && dy.abs() <= 1 {
+            return Ok(());
+        }
+        debug!(
+            "placed {},{} off the center, moving to {}, {}",
+            dx, dy, x, y
+        );
+        self.recenter_until = None;
+        self.conn()
+            .configure_window(window, &xproto::ConfigureWindowAux::new().x(x).y(y))?;
+        Ok(())
+    }
+
+    fn above_removed(&mut self) -> Result<()> {
+        match self.config.above_removed {
+            Some(config::AboveRemoved::Reassert) if self.above_reasserted < MAX_ABOVE_REASSERTS => {
+                debug!("above state removed, asking for it again");
+                self.above_reasserted += 1;
+                // Add from an application
+                let event = xproto::ClientMessageEvent::new(
+                    32,
+                    self.config.window.window(),
+                    self.config.atoms._NET_WM_STATE,
+                    [1, self.config.atoms._NET_WM_STATE_ABOVE, x11rb::NONE, 1, 0],
+                );
+                self.conn().send_event(
+                    false,
+                    self.config.root,
+                    EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+                    event,
+                )?;
+            }
+            Some(_) => info!("no longer above the other windows"),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Flashes the feedback LED if there is one.
+    pub fn flash_led(&mut self) -> Result<()> {
+        let Some(led) = self.config.feedback_led else {
+            return Ok(());
+        };
+        if self.redraw_at.is_some() {
+            self.deferred_flash = true;
+            return Ok(());
+        }
+        if self.led_off_at.is_none() {
+            self.led_was_on = self.led_state(led)?;
+            self.set_led(led, !self.led_was_on)?;
+        }
+        self.led_off_at = Some(Instant::now() + LED_FLASH);
+        Ok(())
+    }
+
+    fn led_state(&self, led: xproto::Atom) -> Result<bool> {
+        Ok(self
+            .conn()
+            .xkb_get_named_indicator(
+                xkb::ID::USE_CORE_KBD.into(),
+                xkb::LedClass::DFLT_XI_CLASS,
+                xkb::ID::DFLT_XI_ID,
+                led,
+            )?
+            .reply()?
+            .on)
+    }
+
+    fn set_led(&self, led: xproto::Atom, on: bool) -> Result<()> {
+        self.conn().xkb_set_named_indicator(
+            xkb::ID::USE_CORE_KBD.into(),
+            xkb::LedClass::DFLT_XI_CLASS,
+            xkb::ID::DFLT_XI_ID,
+            led,
+            true,
+            on,
+            false,
+            false,
+            0_u8.into(),
+            0_u8.into(),
+            0_u8.into(),
+            0_u8.into(),
+            0_u16.into(),
+            0_u16.into(),
+            0_u32.into(),
+        )?;
+        Ok(())
+    }
+
+    // Locks the forced layout when focused and the earlier one back when not.
+    fn force_layout(&mut self, focused: bool) -> Result<()> {
+        let Some(index) = self.config.force_layout else {
+            return Ok(());
+        };
+        if focused {
+            let locked = self.config.keyboard.locked_layout();
+            if self.layout_before_force.is_none() && locked != index {
+                debug!("forcing layout {} over {}", index, locked);
+                self.layout_before_force = Some(locked);
+                self.config.keyboard.lock_layout(index)?;
+            }
+        } else if let Some(locked) = self.layout_before_force.take() {
+            debug!("restoring layout {}", locked);
+            self.config.keyboard.lock_layout(locked)?;
+        }
+        Ok(())
+    }
+
     pub fn new(config: Config<'a>) -> Result<Self> {
         if let Some(compositor_atom) = config.compositor_atom {
             config
@@ -94,12 +543,29 @@ impl<'a> XContext<'a> {
         Ok(Self {
             config,
             keyboard_grabbed: false,
+            focus_received: false,
+            focus_lost_at: None,
             first_expose_received: false,
             xsel_in_progress: false,
             xfd_eagain: false,
             xcb_events_queued_maybe: true, // assume there are to be safe
             x_unflushed_count: 0,
             max_work_time: 0,
+            monitor: None,
+            above: true,
+            maximized: false,
+            above_reasserted: 0,
+            monitor_px_per_mm: None,
+            opaque_region: None,
+            led_off_at: None,
+            led_was_on: false,
+            layout_before_force: None,
+            frame_extents: [0; 4],
+            recenter_until: None,
+            early_focus_until: None,
+            redraw_at: None,
+            deferred_user_time: None,
+            deferred_flash: false,
         })
     }
 
@@ -112,7 +578,9 @@ impl<'a> XContext<'a> {
             // poll_for_event might not read from the fd until EAGAIN if there were pending errors
             if let Some(event) = self.conn().poll_for_event()? {
                 // TODO after poll_for_event there might be pending errors queued by the xcb
-                if self.config.debug {
+                if is_sensitive(&event) {
+                    trace!("event {:?}", Sensitive(&event));
+                } else {
                     trace!("event {:?}", event);
                 }
                 state = Some(self.handle_event(dialog, event)?);
@@ -142,7 +610,9 @@ impl<'a> XContext<'a> {
         // Xcb might queue something on flush and other commands
         self.xcb_events_queued_maybe = true;
         // TODO do not draw if the window is not exposed at all
-        self.config.backbuffer.commit(dialog)?;
+        if self.redraw_at.is_none() {
+            self.config.backbuffer.commit(dialog)?;
+        }
         self.conn().flush()?;
         self.x_unflushed_count = 0;
         Ok(())
@@ -165,20 +635,64 @@ impl<'a> XContext<'a> {
         }
     }
 
-    pub async fn run_events(&mut self, mut dialog: Dialog) -> Result<Option<Passphrase>> {
+    pub async fn run_events(&mut self, mut dialog: Dialog) -> Result<Outcome> {
         dialog.init_events();
+        self.update_opaque_region(&dialog)?;
         self.flush(&mut dialog)?;
         tokio::pin! { let events_ready = self.config.xfd.readable(); }
         let mut xcb_fd_guard = None;
         let mut state = State::Continue;
         while matches!(state, State::Continue) {
             trace!("event loop cycle start: xcb_dirty: {}", self.xcb_dirty());
+            let focus_deadline = self
+                .focus_lost_at
+                .zip(self.config.focus_loss_grace)
+                .map(|(at, grace)| at + grace);
             tokio::select! {
+                () = sleep_until(focus_deadline.unwrap_or_else(Instant::now)), if focus_deadline.is_some() => {
+                    info!("focus lost");
+                    state = State::Cancelled;
+                }
+                () = sleep_until(self.early_focus_until.unwrap_or_else(Instant::now)), if self.early_focus_until.is_some() => {
+                    self.early_focus_until = None;
+                    if !self.focus_received {
+                        self.early_focus_loss(&mut dialog)?;
+                        self.flush(&mut dialog)?;
+                    }
+                }
+                label = async { self.config.labels.as_mut().unwrap().recv().await }, if self.config.labels.is_some() => {
+                    if let Some(label) = label {
+                        dialog.set_label(&label);
+                        self.fit_window(&dialog, true)?;
+                    } else {
+                        self.config.labels = None;
+                    }
+                }
+                () = sleep_until(self.redraw_at.unwrap_or_else(Instant::now)), if self.redraw_at.is_some() => {
+                    trace!("batched redraw");
+                    self.redraw_at = None;
+                    if let Some(time) = self.deferred_user_time.take() {
+                        self.set_user_time(time)?;
+                    }
+                    if std::mem::take(&mut self.deferred_flash) {
+                        self.flash_led()?;
+                    }
+                    self.flush(&mut dialog)?;
+                }
+                () = sleep_until(self.led_off_at.unwrap_or_else(Instant::now)), if self.led_off_at.is_some() => {
+                    self.led_off_at = None;
+                    if let Some(led) = self.config.feedback_led {
+                        self.set_led(led, self.led_was_on)?;
+                    }
+                    self.conn().flush()?;
+                }
                 action = dialog.handle_events() => {
                     let timestamp = Instant::now();
                     self.flush(&mut dialog)?;
-                    if matches!(action, Action::Cancel) {
-                        state = State::Cancelled;
+                    match action {
+                        Action::Cancel => state = State::Cancelled,
+                        Action::Ok => state = State::Ready,
+                        _ => {}
                     }
                     self.stopwatch_stop(timestamp);
                 }
@@ -211,11 +725,7 @@ impl<'a> XContext<'a> {
             }
             tokio::task::yield_now().await;
         }
-        match state {
-            State::Continue => unreachable!(),
-            State::Ready => Ok(Some(dialog.indicator.into_pass())),
-            State::Cancelled => Ok(None),
-        }
+        Ok(finish(dialog, &state))
     }
 
     pub fn set_default_cursor(&self) -> Result<()> {
@@ -319,7 +829,79 @@ impl<'a> XContext<'a> {
                     self.config.width = ev.width;
                     self.config.height = ev.height;
                     self.config.backbuffer.resize_requested = Some((ev.width, ev.height));
+                    self.update_opaque_region(dialog)?;
+                }
+                if self.config.monitors.len() > 1 {
+                    self.follow_monitor(dialog)?;
                 }
+                if let Some(until) = self.recenter_until {
+                    if Instant::now() < until {
+                        self.recenter()?;
+                    } else {
+                        self.recenter_until = None;
+                    }
+                }
+            }
+            Event::PropertyNotify(ev)
+                if ev.window == self.config.window.window()
+                    && ev.atom == self.config.atoms._NET_FRAME_EXTENTS =>
+            {
+                if let Some(monitor) = self.config.center.take() {
+                    let reply = self
+                        .conn()
+                        .get_property(
+                            false,
+                            ev.window,
+                            self.config.atoms._NET_FRAME_EXTENTS,
+                            xproto::AtomEnum::CARDINAL,
+                            0,
+                            4,
+                        )?
+                        .reply()?;
+                    let extents: Vec<u32> =
+                        reply.value32().map(Iterator::collect).unwrap_or_default();
+                    if let Ok(extents) = <[u32; 4]>::try_from(extents) {
+                        self.frame_extents = extents;
+                        let (x, y) =
+                            monitor.centered((self.config.width, self.config.height), extents);
+                        debug!("frame extents {:?}, centering at {}, {}", extents, x, y);
+                        self.conn().configure_window(
+                            ev.window,
+                            &xproto::ConfigureWindowAux::new().x(x).y(y),
+                        )?;
+                    }
+                }
+            }
+            Event::PropertyNotify(ev)
+                if ev.window == self.config.window.window()
+                    && ev.atom == self.config.atoms._NET_WM_STATE =>
+            {
+                let reply = self
+                    .conn()
+                    .get_property(
+                        false,
+                        ev.window,
+                        self.config.atoms._NET_WM_STATE,
+                        xproto::AtomEnum::ATOM,
+                        0,
+                        32,
+                    )?
+                    .reply()?;
+                let states: Vec<u32> = reply.value32().map(Iterator::collect).unwrap_or_default();
+                let atoms = &self.config.atoms;
+                let maximized = states.contains(&atoms._NET_WM_STATE_FULLSCREEN)
+                    || (states.contains(&atoms._NET_WM_STATE_MAXIMIZED_HORZ)
+                        && states.contains(&atoms._NET_WM_STATE_MAXIMIZED_VERT));
+                self.maximized = maximized;
+                if dialog.set_maximized(maximized) {
+                    self.config.backbuffer.resize_requested =
+                        Some((self.config.width, self.config.height));
+                }
+                let above = states.contains(&atoms._NET_WM_STATE_ABOVE);
+                if self.above && !above {
+                    self.above_removed()?;
+                }
+                self.above = above;
             }
             Event::MotionNotify(me) => {
                 if me.same_screen {
@@ -337,6 +919,9 @@ impl<'a> XContext<'a> {
             // both events have the same structure
             Event::ButtonPress(bp) | Event::ButtonRelease(bp) => {
                 let isrelease = matches!(event, Event::ButtonRelease(_));
+                if !isrelease {
+                    self.set_user_time(bp.time)?;
+                }
                 trace!(
                     "button {}: {:?}",
                     if isrelease { "release" } else { "press" },
@@ -361,8 +946,27 @@ impl<'a> XContext<'a> {
                 }
             }
             Event::KeyPress(key_press) => {
+                if let (Some(batch), None) = (self.config.redraw_batch, self.redraw_at) {
+                    let jitter = batch.mul_f64(rand::random::<f64>());
+                    self.redraw_at = Some(Instant::now() + batch + jitter);
+                }
+                // Deferred with redraw_batch
+                self.set_user_time(key_press.time)?;
+                if !self
+                    .config
+                    .keyboard
+                    .key_pressed(key_press.detail.into(), key_press.state)
+                {
+                    debug!("bounce key ignored");
+                    return Ok(State::Continue);
+                }
+                let size = dialog.window_size(&self.config.backbuffer.cr);
                 let action = dialog.handle_key_press(key_press.detail.into(), self)?;
                 trace!("action {:?}", action);
+                // The fields of --basic-auth are laid out again
+                if dialog.window_size(&self.config.backbuffer.cr) != size {
+                    self.fit_window(dialog, true)?;
+                }
                 match action {
                     Action::Ok => return Ok(State::Ready),
                     Action::Cancel => return Ok(State::Cancelled),
@@ -400,15 +1004,8 @@ impl<'a> XContext<'a> {
                     warn!("Selection too big and INCR selection not implemented");
                     return Ok(State::Continue);
                 }
-                match String::from_utf8(selection.value) {
-                    Err(err) => {
-                        warn!("selection is not valid utf8: {}", err);
-                        err.into_bytes().zeroize();
-                    }
-                    Ok(mut val) => {
-                        dialog.indicator.pass_insert(&val, true);
-                        val.zeroize();
-                    }
+                if let Action::Ok = dialog.paste(selection.value) {
+                    return Ok(State::Ready);
                 }
             }
             Event::FocusIn(fe) => {
@@ -418,6 +1015,10 @@ impl<'a> XContext<'a> {
                     self.keyboard_grabbed = false;
                 }
                 dialog.indicator.set_focused(true);
+                dialog.show_focus_hint(false);
+                self.focus_received = true;
+                self.focus_lost_at = None;
+                self.force_layout(true)?;
             }
             Event::FocusOut(fe) => {
                 if fe.mode == xproto::NotifyMode::GRAB {
@@ -429,6 +1030,18 @@ impl<'a> XContext<'a> {
                     && fe.mode != xproto::NotifyMode::WHILE_GRABBED
                 {
                     dialog.indicator.set_focused(false);
+                    self.force_layout(false)?;
+                    // Not counted as lost right after mapping, and only once the window manager
+                    // has given us the focus
+                    if self
+                        .early_focus_until
+                        .is_some_and(|until| Instant::now() < until)
+                    {
+                        self.early_focus_loss(dialog)?;
+                    } else if self.focus_received && self.focus_lost_at.is_none() {
+                        debug!("focus out");
+                        self.focus_lost_at = Some(Instant::now());
+                    }
                 }
             }
             Event::ClientMessage(mut client_message) => {
@@ -461,7 +1074,13 @@ impl<'a> XContext<'a> {
                 self.config.backbuffer.on_vsync_completed(ev);
             }
             Event::XkbStateNotify(key) => {
+                let layout = self.config.keyboard.layout_index();
                 self.config.keyboard.update_mask(&key);
+                if self.config.keyboard.layout_index() != layout {
+                    let name = self.config.keyboard.layout_name();
+                    debug!("layout changed to {:?}", name);
+                    dialog.layout_changed(name.as_deref());
+                }
             }
             // TODO needs more testing
             Event::XkbNewKeyboardNotify(..) => {
@@ -473,20 +1092,39 @@ impl<'a> XContext<'a> {
                 debug!("xkb map notify");
                 self.config.keyboard.reload_keymap();
             }
+            Event::XkbControlsNotify(..) => {
+                debug!("xkb controls notify");
+                self.config.keyboard.update_controls()?;
+            }
             Event::XfixesSelectionNotify(sn) => {
                 debug!("selection notify: {:?}", sn);
                 dialog.set_transparency(sn.subtype == xfixes::SelectionEvent::SET_SELECTION_OWNER);
+                self.update_opaque_region(dialog)?;
             }
             // minimized
             Event::UnmapNotify(..) => {
                 debug!("set invisible");
                 self.config.backbuffer.visible = false;
             }
+            Event::MapNotify(..) => {
+                self.warp_pointer(dialog)?;
+                if self.config.early_focus_loss != config::EarlyFocusLoss::Ignore {
+                    self.early_focus_until = Some(Instant::now() + EARLY_FOCUS_WINDOW);
+                }
+                if self.config.recenter.is_some() {
+                    self.recenter_until = Some(Instant::now() + RECENTER_WINDOW);
+                }
+                if let (false, Some(time)) = (self.focus_received, self.config.user_time) {
+                    self.request_activation(time)?;
+                }
+            }
             // Ignored events:
-            // unminimized
-            Event::MapNotify(..) | Event::ReparentNotify(..) | Event::KeyRelease(..) => {
+            Event::ReparentNotify(..) => {
                 trace!("ignored event {:?}", event);
             }
+            Event::KeyRelease(..) => {
+                trace!("ignored event {:?}", Sensitive(&event));
+            }
             event => {
                 debug!("unexpected event {:?}", event);
             }
@@ -495,6 +1133,14 @@ impl<'a> XContext<'a> {
     }
 }
 
+// Key events and the xkb state changes they cause reveal what is being typed.
+fn is_sensitive(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyPress(..) | Event::KeyRelease(..) | Event::XkbStateNotify(..)
+    )
+}
+
 impl<'a> Drop for XContext<'a> {
     fn drop(&mut self) {
         if self.keyboard_grabbed {
@@ -502,6 +1148,18 @@ impl<'a> Drop for XContext<'a> {
                 debug!("ungrab keyboard failed: {}", err);
             }
         }
+        if let Err(err) = self.force_layout(false) {
+            debug!("restoring the layout failed: {}", err);
+        }
+        if let Some(led) = self
+            .config
+            .feedback_led
+            .filter(|_| self.led_off_at.is_some())
+        {
+            if let Err(err) = self.set_led(led, self.led_was_on) {
+                debug!("restoring the feedback led failed: {}", err);
+            }
+        }
         if let Some(compositor_atom) = self.config.compositor_atom {
             if let Err(err) = xfixes::select_selection_input(
                 self.conn(),
@@ -0,0 +1,61 @@
+use std::process::{Command, Stdio};
+
+use log::{debug, warn};
+
+/// Starts `command`, a program followed by its arguments, with `XASKPASS_EVENT` set to
+/// `event`. It is not waited for so that the caller gets the secret right away.
+pub fn run(command: &[String], event: &str) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    debug!("running the {} hook", event);
+    // Nothing must end up on stdout next to the secret
+    let child = Command::new(program)
+        .args(args)
+        .env("XASKPASS_EVENT", event)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn();
+    if let Err(err) = child {
+        warn!("could not run the {} hook {}: {}", event, program, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.into()).collect()
+    }
+
+    #[test]
+    fn event_in_env() {
+        let path = std::env::temp_dir().join(format!("xaskpass-hook-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        run(
+            &command(&[
+                "sh",
+                "-c",
+                "printf %s \"$XASKPASS_EVENT\" > \"$0.tmp\" && mv \"$0.tmp\" \"$0\"",
+                path.to_str().unwrap(),
+            ]),
+            "success",
+        );
+        // Not waited for by run
+        let start = Instant::now();
+        while !path.exists() && start.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "success");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn nothing_to_run() {
+        run(&[], "success");
+        run(&command(&["/nonexistent/xaskpass-hook"]), "cancel");
+    }
+}
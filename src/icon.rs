@@ -0,0 +1,40 @@
+use crate::errors::{Context as _, Result};
+use crate::image::Image;
+
+/// Icon sizes for the window manager to choose from.
+const SIZES: [i32; 4] = [16, 32, 48, 64];
+
+/// `_NET_WM_ICON` data of the PNG or SVG file scaled to fit each of the `SIZES`.
+pub fn load(path: &str) -> Result<Vec<u32>> {
+    let image = Image::open(path)?;
+    let mut data = vec![];
+    for size in SIZES {
+        let mut scaled = cairo::ImageSurface::create(cairo::Format::ARgb32, size, size)
+            .context("image surface")?;
+        {
+            let cr = cairo::Context::new(&scaled).context("cairo context")?;
+            image.draw(&cr, 0.0, 0.0, f64::from(size), f64::from(size))?;
+        }
+        let stride = scaled.stride() as usize;
+        let pixels = scaled.data().context("icon data")?;
+        data.extend([size as u32, size as u32]);
+        for row in pixels.chunks(stride) {
+            data.extend(
+                row[..size as usize * 4]
+                    .chunks_exact(4)
+                    .map(|px| unpremultiply(u32::from_ne_bytes(px.try_into().unwrap()))),
+            );
+        }
+    }
+    Ok(data)
+}
+
+// cairo premultiplies the colors by alpha but _NET_WM_ICON does not
+fn unpremultiply(argb: u32) -> u32 {
+    let alpha = argb >> 24;
+    if alpha == 0 {
+        return 0;
+    }
+    let channel = |shift: u32| ((argb >> shift & 0xff) * 255 / alpha).min(255) << shift;
+    alpha << 24 | channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::errors::{Context as _, Result};
+
+// Scalable icons go first
+#[cfg(feature = "svg")]
+const ICON_EXTENSIONS: [&str; 2] = ["svg", "png"];
+#[cfg(not(feature = "svg"))]
+const ICON_EXTENSIONS: [&str; 1] = ["png"];
+
+/// Looks `name` up in `theme`, the themes it inherits and then hicolor in the XDG data
+/// directories, preferring scalable and then the largest icons of the first theme that has
+/// it. A name with a slash is a path already.
+pub fn find_icon(name: &str, theme: Option<&str>) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(name.into());
+    }
+    let mut bases: Vec<PathBuf> = Vec::new();
+    if let Some(data_home) = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+    {
+        bases.push(data_home.join("icons"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    bases.extend(data_dirs.split(':').map(|dir| Path::new(dir).join("icons")));
+
+    let mut pending: Vec<String> = theme.into_iter().map(str::to_owned).collect();
+    let mut searched: Vec<String> = Vec::new();
+    while let Some(theme) = pending.pop().or_else(|| {
+        // The fallback of every theme
+        (!searched.iter().any(|t| t == "hicolor")).then(|| "hicolor".to_owned())
+    }) {
+        if searched.contains(&theme) {
+            continue;
+        }
+        let dirs: Vec<PathBuf> = bases.iter().map(|base| base.join(&theme)).collect();
+        if let Some(path) = find_in_theme(name, &dirs) {
+            debug!("icon {} from theme {}", name, theme);
+            return Some(path);
+        }
+        // Depth first in the order listed
+        pending.extend(inherits(&dirs).into_iter().rev());
+        searched.push(theme);
+    }
+    let path = Path::new("/usr/share/pixmaps").join(format!("{name}.png"));
+    path.is_file().then_some(path)
+}
+
+// The `Inherits` of the first index.theme of the theme
+fn inherits(dirs: &[PathBuf]) -> Vec<String> {
+    let Some(index) = dirs
+        .iter()
+        .find_map(|dir| std::fs::read_to_string(dir.join("index.theme")).ok())
+    else {
+        return Vec::new();
+    };
+    index
+        .lines()
+        .find_map(|line| line.strip_prefix("Inherits="))
+        .map(|themes| {
+            themes
+                .split(',')
+                .map(str::trim)
+                .filter(|theme| !theme.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn find_in_theme(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let sorted_dirs = |dir: &Path| {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+        dirs
+    };
+    // Themes are laid out as size/context or context/size
+    let size = |dir: &Path| {
+        let name = dir.file_name()?.to_str()?;
+        let name = name.split(['x', '@']).next()?;
+        name.parse::<u32>().ok()
+    };
+    let mut best: Option<(u32, PathBuf)> = None;
+    for theme in dirs {
+        for outer in sorted_dirs(theme) {
+            for inner in sorted_dirs(&outer) {
+                for ext in ICON_EXTENSIONS {
+                    let path = inner.join(format!("{name}.{ext}"));
+                    if !path.is_file() {
+                        continue;
+                    }
+                    let rank = if ext == "svg" {
+                        u32::MAX
+                    } else {
+                        size(&outer).or_else(|| size(&inner)).unwrap_or(0)
+                    };
+                    if best.as_ref().is_none_or(|(best, _)| rank > *best) {
+                        best = Some((rank, path));
+                    }
+                }
+            }
+        }
+    }
+    best.map(|(_, path)| path)
+}
+
+/// A PNG file, or an SVG file with the svg feature.
+#[derive(Debug)]
+pub enum Image {
+    Png(cairo::ImageSurface),
+    #[cfg(feature = "svg")]
+    Svg(crate::svg::Image),
+}
+
+impl Image {
+    pub fn open(path: &str) -> Result<Self> {
+        let svg = std::path::Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        if svg {
+            #[cfg(feature = "svg")]
+            return Ok(Self::Svg(crate::svg::Image::load(path)?));
+            #[cfg(not(feature = "svg"))]
+            crate::bail!("SVG images need the svg feature");
+        }
+        let mut file = std::fs::File::open(path).context("image file")?;
+        let image = cairo::ImageSurface::create_from_png(&mut file).context("png")?;
+        Ok(Self::Png(image))
+    }
+
+    /// The size in pixels if the image has one.
+    pub fn size(&self) -> Option<(f64, f64)> {
+        match self {
+            Self::Png(image) => Some((f64::from(image.width()), f64::from(image.height()))),
+            #[cfg(feature = "svg")]
+            Self::Svg(..) => None,
+        }
+    }
+
+    /// Draws the image centered in the rectangle and scaled to fit it.
+    pub fn draw(&self, cr: &cairo::Context, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        match self {
+            Self::Png(image) => {
+                let (image_width, image_height) =
+                    (f64::from(image.width()), f64::from(image.height()));
+                let scale = (width / image_width).min(height / image_height);
+                cr.save().context("cairo save")?;
+                cr.translate(
+                    x + (width - image_width * scale) / 2.0,
+                    y + (height - image_height * scale) / 2.0,
+                );
+                cr.scale(scale, scale);
+                cr.set_source_surface(image, 0.0, 0.0)
+                    .context("image source")?;
+                cr.paint().context("image paint")?;
+                cr.restore().context("cairo restore")?;
+            }
+            #[cfg(feature = "svg")]
+            Self::Svg(image) => image.render(cr, x, y, width, height)?,
+        }
+        Ok(())
+    }
+}
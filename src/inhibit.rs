@@ -0,0 +1,81 @@
+use log::{debug, warn};
+use x11rb::connection::{Connection as _, RequestConnection as _};
+use x11rb::protocol::dpms::{self, ConnectionExt as _, DPMSMode};
+use x11rb::protocol::screensaver::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{ConnectionExt as _, ScreenSaver};
+
+use crate::errors::Result;
+use crate::Connection;
+
+/// Turns the monitor back on and resets the screen saver so that the dialog is visible.
+pub fn wake(conn: &Connection) -> Result<()> {
+    if conn
+        .extension_information(dpms::X11_EXTENSION_NAME)?
+        .is_some()
+    {
+        let info = conn.dpms_info()?.reply()?;
+        if info.state && info.power_level != DPMSMode::ON {
+            debug!("waking the display from DPMS {:?}", info.power_level);
+            conn.dpms_force_level(DPMSMode::ON)?;
+        }
+    }
+    conn.force_screen_saver(ScreenSaver::RESET)?;
+    Ok(())
+}
+
+/// Keeps the screen saver and DPMS from blanking the screen until dropped.
+pub struct Inhibitor<'a> {
+    conn: &'a Connection,
+    suspended: bool,
+    // DPMS is a server-wide setting and stays off if we crash
+    dpms_disabled: bool,
+}
+
+impl<'a> Inhibitor<'a> {
+    pub fn new(conn: &'a Connection) -> Result<Self> {
+        // The server undoes the suspension when the client goes away
+        let suspended = conn
+            .extension_information(screensaver::X11_EXTENSION_NAME)?
+            .is_some();
+        if suspended {
+            debug!("suspending the screen saver");
+            conn.screensaver_suspend(1)?;
+        } else {
+            debug!("no screen saver extension");
+        }
+        let mut dpms_disabled = false;
+        if conn
+            .extension_information(dpms::X11_EXTENSION_NAME)?
+            .is_some()
+            && conn.dpms_info()?.reply()?.state
+        {
+            debug!("disabling DPMS");
+            conn.dpms_disable()?;
+            dpms_disabled = true;
+        }
+        Ok(Self {
+            conn,
+            suspended,
+            dpms_disabled,
+        })
+    }
+}
+
+impl Drop for Inhibitor<'_> {
+    fn drop(&mut self) {
+        let ret = (|| -> Result<()> {
+            if self.suspended {
+                self.conn.screensaver_suspend(0)?;
+            }
+            if self.dpms_disabled {
+                debug!("enabling DPMS again");
+                self.conn.dpms_enable()?;
+            }
+            self.conn.flush()?;
+            Ok(())
+        })();
+        if let Err(err) = ret {
+            warn!("could not let the screen blank again: {}", err);
+        }
+    }
+}
@@ -1,13 +1,14 @@
 use std::ffi::{CStr, CString};
 use std::os::unix::ffi::OsStrExt as _;
+use std::time::{Duration, Instant};
 
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use x11rb::connection::RequestConnection;
 use x11rb::protocol::xkb::{self as xkb_x11, ConnectionExt as _};
 use x11rb::xcb_ffi::XCBConnection;
 
 use crate::bail;
-use crate::errors::Unsupported;
+use crate::errors::{Context as _, Unsupported};
 
 mod ffi;
 pub mod ffi_keysyms;
@@ -24,6 +25,53 @@ pub type Keysym = ffi::xkb_keysym_t;
 
 use crate::errors::Result;
 
+/// Keysyms with the name in any case, e.g. both `p` and `P` for "p". Empty if there is none.
+pub fn keysyms_from_name(name: &str) -> Vec<Keysym> {
+    let mut syms = Vec::with_capacity(3);
+    let names = [name.to_owned(), name.to_lowercase(), name.to_uppercase()];
+    for (i, name) in names.into_iter().enumerate() {
+        let Ok(name) = CString::new(name) else {
+            return Vec::new();
+        };
+        let flags = if i == 0 {
+            ffi::xkb_keysym_flags::XKB_KEYSYM_CASE_INSENSITIVE
+        } else {
+            ffi::xkb_keysym_flags::XKB_KEYSYM_NO_FLAGS
+        };
+        let sym = unsafe { ffi::xkb_keysym_from_name(name.as_ptr(), flags) };
+        if sym != keysyms::XKB_KEY_NoSymbol && !syms.contains(&sym) {
+            syms.push(sym);
+        }
+    }
+    syms
+}
+
+/// The character of a keysym, None for e.g. dead keys.
+pub fn keysym_to_char(key_sym: Keysym) -> Option<char> {
+    char::from_u32(unsafe { ffi::xkb_keysym_to_utf32(key_sym) }).filter(|c| *c != '\0')
+}
+
+/// What a dead key shows while composing, the spacing form of its accent.
+pub fn dead_key_char(key_sym: Keysym) -> Option<char> {
+    let c = match key_sym {
+        keysyms::XKB_KEY_dead_grave => '`',
+        keysyms::XKB_KEY_dead_acute => '\u{b4}',
+        keysyms::XKB_KEY_dead_circumflex => '^',
+        keysyms::XKB_KEY_dead_tilde => '~',
+        keysyms::XKB_KEY_dead_macron => '\u{af}',
+        keysyms::XKB_KEY_dead_breve => '\u{2d8}',
+        keysyms::XKB_KEY_dead_abovedot => '\u{2d9}',
+        keysyms::XKB_KEY_dead_diaeresis => '\u{a8}',
+        keysyms::XKB_KEY_dead_abovering => '\u{2da}',
+        keysyms::XKB_KEY_dead_doubleacute => '\u{2dd}',
+        keysyms::XKB_KEY_dead_caron => '\u{2c7}',
+        keysyms::XKB_KEY_dead_cedilla => '\u{b8}',
+        keysyms::XKB_KEY_dead_ogonek => '\u{2db}',
+        _ => return None,
+    };
+    Some(c)
+}
+
 pub struct Keyboard<'a> {
     state: *mut ffi::xkb_state,
     context: *mut ffi::xkb_context,
@@ -31,6 +79,13 @@ pub struct Keyboard<'a> {
     map_parts: u16,
     events: u16,
     conn: &'a XCBConnection,
+    // How much longer the AccessX slow and bounce keys make typing a key twice
+    access_x_delay: Duration,
+    // Of the bounce keys, zero when they are off
+    debounce_delay: Duration,
+    last_press: Option<(Keycode, Instant)>,
+    // Ctrl in the state of the last key press event
+    event_ctrl: bool,
 }
 
 impl<'a> Keyboard<'a> {
@@ -58,7 +113,8 @@ impl<'a> Keyboard<'a> {
 
         let events = xkb_x11::EventType::NEW_KEYBOARD_NOTIFY
             | xkb_x11::EventType::MAP_NOTIFY
-            | xkb_x11::EventType::STATE_NOTIFY;
+            | xkb_x11::EventType::STATE_NOTIFY
+            | xkb_x11::EventType::CONTROLS_NOTIFY;
         //let events = 0xFFF; //XkbAllEventsMask
 
         conn.xkb_select_events(
@@ -81,27 +137,35 @@ impl<'a> Keyboard<'a> {
             Ok(compose) => Some(compose),
         };
 
-        let state = Self::create_xkb_state(conn, context);
+        let state = Self::create_xkb_state(conn, context).context("xkb state creation failed")?;
 
-        let me = Self {
+        let mut me = Self {
             state,
             context,
             map_parts: map_parts.into(),
             events: events.into(),
             compose,
             conn,
+            access_x_delay: Duration::ZERO,
+            debounce_delay: Duration::ZERO,
+            last_press: None,
+            event_ctrl: false,
         };
+        me.update_controls()?;
         Ok(me)
     }
 
     fn create_xkb_state(
         conn: &XCBConnection,
         context: *mut ffi::xkb_context,
-    ) -> *mut ffi::xkb_state {
+    ) -> Option<*mut ffi::xkb_state> {
         let device_id = unsafe {
             ffi::xkb_x11_get_core_keyboard_device_id(conn.get_raw_xcb_connection().cast())
         };
-        assert!(device_id != -1, "xkb get core keyboard device id failed");
+        if device_id == -1 {
+            warn!("xkb get core keyboard device id failed");
+            return None;
+        }
         let keymap = unsafe {
             ffi::xkb_x11_keymap_new_from_device(
                 context,
@@ -110,7 +174,10 @@ impl<'a> Keyboard<'a> {
                 ffi::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
             )
         };
-        assert!(!keymap.is_null(), "xkb keymap creation failed");
+        if keymap.is_null() {
+            warn!("xkb keymap creation failed");
+            return None;
+        }
         let state = unsafe {
             ffi::xkb_x11_state_new_from_device(
                 keymap,
@@ -122,14 +189,55 @@ impl<'a> Keyboard<'a> {
         // xkb_keymap is no longer referenced directly
         unsafe { ffi::xkb_keymap_unref(keymap) }
 
-        assert!(!state.is_null(), "xkb state creation failed");
-
-        state
+        (!state.is_null()).then_some(state)
     }
 
+    /// Loads the keymap again keeping the modifiers and the layout of the old state so that
+    /// e.g. a latched shift is not lost when a keyboard is plugged in. Compose sequences are
+    /// kept as their state is separate.
     pub fn reload_keymap(&mut self) {
-        unsafe { ffi::xkb_state_unref(self.state) }
-        self.state = Self::create_xkb_state(self.conn, self.context);
+        let Some(state) = Self::create_xkb_state(self.conn, self.context) else {
+            warn!("keeping the old keymap");
+            return;
+        };
+        unsafe {
+            let old_keymap = ffi::xkb_state_get_keymap(self.state);
+            let keymap = ffi::xkb_state_get_keymap(state);
+            // By name as the indices may differ between the keymaps
+            let mods = |component| {
+                let old = ffi::xkb_state_serialize_mods(self.state, component);
+                let mut mods = 0;
+                for i in 0..ffi::xkb_keymap_num_mods(old_keymap).min(u32::BITS) {
+                    if old & (1 << i) != 0 {
+                        let name = ffi::xkb_keymap_mod_get_name(old_keymap, i);
+                        let index = ffi::xkb_keymap_mod_get_index(keymap, name);
+                        if index < u32::BITS {
+                            mods |= 1 << index;
+                        }
+                    }
+                }
+                mods
+            };
+            let layout = |component| {
+                let layout = ffi::xkb_state_serialize_layout(self.state, component);
+                if layout < ffi::xkb_keymap_num_layouts(keymap) {
+                    layout
+                } else {
+                    0
+                }
+            };
+            ffi::xkb_state_update_mask(
+                state,
+                mods(xkb_state_component::XKB_STATE_MODS_DEPRESSED),
+                mods(xkb_state_component::XKB_STATE_MODS_LATCHED),
+                mods(xkb_state_component::XKB_STATE_MODS_LOCKED),
+                layout(xkb_state_component::XKB_STATE_LAYOUT_DEPRESSED),
+                layout(xkb_state_component::XKB_STATE_LAYOUT_LATCHED),
+                layout(xkb_state_component::XKB_STATE_LAYOUT_LOCKED),
+            );
+            ffi::xkb_state_unref(self.state);
+        }
+        self.state = state;
     }
 
     pub fn key_get_utf8(&self, key: Keycode, buf: &mut [u8]) -> usize {
@@ -140,6 +248,140 @@ impl<'a> Keyboard<'a> {
         }
     }
 
+    /// Queries the keyboard accessibility controls again. The server filters the key events for
+    /// them, `key_pressed` and `ctrl_active` catch what gets past it.
+    pub fn update_controls(&mut self) -> Result<()> {
+        let controls = self
+            .conn
+            .xkb_get_controls(xkb_x11::ID::USE_CORE_KBD.into())?
+            .reply()?;
+        let enabled = |ctrl| controls.enabled_controls.contains(ctrl);
+        let slow = if enabled(xkb_x11::BoolCtrl::SLOW_KEYS) {
+            controls.slow_keys_delay
+        } else {
+            0
+        };
+        let bounce = if enabled(xkb_x11::BoolCtrl::BOUNCE_KEYS) {
+            controls.debounce_delay
+        } else {
+            0
+        };
+        debug!(
+            "accessx: sticky keys {}, slow keys {}ms, bounce keys {}ms",
+            enabled(xkb_x11::BoolCtrl::STICKY_KEYS),
+            slow,
+            bounce
+        );
+        self.access_x_delay = Duration::from_millis(u64::from(slow) + u64::from(bounce));
+        self.debounce_delay = Duration::from_millis(bounce.into());
+        Ok(())
+    }
+
+    /// Notes a key press with the modifiers of its event. False for a bounce, the same key
+    /// again within the delay of the bounce keys.
+    pub fn key_pressed(
+        &mut self,
+        key: Keycode,
+        state: x11rb::protocol::xproto::KeyButMask,
+    ) -> bool {
+        let now = Instant::now();
+        self.event_ctrl = state.contains(x11rb::protocol::xproto::KeyButMask::CONTROL);
+        let bounce = self.last_press.is_some_and(|(last, time)| {
+            last == key && now.duration_since(time) < self.debounce_delay
+        });
+        if !bounce {
+            self.last_press = Some((key, now));
+        }
+        !bounce
+    }
+
+    /// Whether Ctrl applies to the key being pressed, also when a Ctrl latched by the sticky keys
+    /// has already been let go of in the state by the time the press is handled.
+    pub fn ctrl_active(&self) -> bool {
+        self.event_ctrl
+            || self.mod_name_is_active(
+                names::XKB_MOD_NAME_CTRL,
+                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+            )
+    }
+
+    /// Extra time for pressing a key again with the slow or bounce keys.
+    pub fn access_x_delay(&self) -> Duration {
+        self.access_x_delay
+    }
+
+    /// Index of the effective layout.
+    pub fn layout_index(&self) -> u32 {
+        unsafe {
+            ffi::xkb_state_serialize_layout(
+                self.state,
+                xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE,
+            )
+        }
+    }
+
+    pub fn layout_name(&self) -> Option<String> {
+        unsafe {
+            let keymap = ffi::xkb_state_get_keymap(self.state);
+            let name = ffi::xkb_keymap_layout_get_name(keymap, self.layout_index());
+            (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Index of the locked layout, the one that stays after e.g. a group latch is released.
+    pub fn locked_layout(&self) -> u32 {
+        unsafe {
+            ffi::xkb_state_serialize_layout(
+                self.state,
+                xkb_state_component::XKB_STATE_LAYOUT_LOCKED,
+            )
+        }
+    }
+
+    fn num_layouts(&self) -> u32 {
+        unsafe { ffi::xkb_keymap_num_layouts(ffi::xkb_state_get_keymap(self.state)) }
+    }
+
+    /// Index of the layout with the name, e.g. "English (US)", or with the short name from the
+    /// rules, e.g. "us".
+    pub fn find_layout(&self, name: &str, short_names: &[&str]) -> Option<u32> {
+        (0..self.num_layouts()).find(|&i| {
+            let long = unsafe {
+                let name =
+                    ffi::xkb_keymap_layout_get_name(ffi::xkb_state_get_keymap(self.state), i);
+                (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy())
+            };
+            long.is_some_and(|long| long.eq_ignore_ascii_case(name))
+                || short_names
+                    .get(i as usize)
+                    .is_some_and(|short| short.trim() == name)
+        })
+    }
+
+    /// Asks the server to lock the layout.
+    pub fn lock_layout(&self, index: u32) -> Result<()> {
+        self.conn.xkb_latch_lock_state(
+            xkb_x11::ID::USE_CORE_KBD.into(),
+            0_u8.into(),
+            0_u8.into(),
+            true,
+            (index as u8).into(),
+            0_u8.into(),
+            false,
+            0,
+        )?;
+        Ok(())
+    }
+
+    /// Asks the server to lock the next layout, wrapping around.
+    pub fn lock_next_layout(&self) -> Result<()> {
+        let layouts = self.num_layouts();
+        if layouts < 2 {
+            return Ok(());
+        }
+        self.lock_layout((self.layout_index() + 1) % layouts)
+    }
+
     pub fn key_get_one_sym(&self, key: Keycode) -> Keysym {
         unsafe { ffi::xkb_state_key_get_one_sym(self.state, key) }
     }
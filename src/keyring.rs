@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use futures_util::StreamExt as _;
+use log::debug;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, Proxy};
+use zeroize::Zeroize as _;
+
+use crate::bail;
+use crate::errors::{Context as _, Result};
+use crate::secret::{Passphrase, SecBuf};
+
+const SERVICE: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+// (session, parameters, value, content type)
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// An item to create in the default collection of the Secret Service.
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub label: String,
+    pub attributes: HashMap<String, String>,
+}
+
+impl Item {
+    /// Stores the secret replacing any item with the same attributes.
+    pub async fn store(&self, pass: &Passphrase) -> Result<()> {
+        let service = SecretService::open().await?;
+        let collection = service
+            .proxy(DEFAULT_COLLECTION, "org.freedesktop.Secret.Collection")
+            .await?;
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label",
+                Value::from(self.label.as_str()),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(self.attributes.clone()),
+            ),
+        ]);
+        let value = pass.encode_utf8(false);
+        let secret = (
+            &service.session,
+            &[] as &[u8],
+            value.unsecure(),
+            "text/plain",
+        );
+        let ret: zbus::Result<(OwnedObjectPath, OwnedObjectPath)> = collection
+            .call("CreateItem", &(properties, secret, true))
+            .await;
+        drop(value);
+
+        let (_, prompt) = ret.context("CreateItem")?;
+        if prompt.as_str() != "/" {
+            // The collection is locked
+            service.prompt(&prompt).await?;
+        }
+        service.close().await?;
+        debug!("secret stored");
+        Ok(())
+    }
+}
+
+/// Returns the secret of the first item matching all the attributes.
+pub async fn lookup(attributes: &[(String, String)]) -> Result<Option<Passphrase>> {
+    let attributes: HashMap<_, _> = attributes.iter().cloned().collect();
+    let service = SecretService::open().await?;
+    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service
+        .service
+        .call("SearchItems", &(&attributes,))
+        .await
+        .context("SearchItems")?;
+    let item = if let Some(item) = unlocked.into_iter().next() {
+        item
+    } else if let Some(item) = locked.into_iter().next() {
+        let (_, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) = service
+            .service
+            .call("Unlock", &(vec![&item],))
+            .await
+            .context("Unlock")?;
+        if prompt.as_str() != "/" {
+            service.prompt(&prompt).await?;
+        }
+        item
+    } else {
+        debug!("no matching item in the keyring");
+        return Ok(None);
+    };
+
+    let item = service
+        .proxy(item.as_str(), "org.freedesktop.Secret.Item")
+        .await?;
+    let (_, mut params, mut value, _): Secret = item
+        .call("GetSecret", &(&service.session,))
+        .await
+        .context("GetSecret")?;
+    params.zeroize();
+    service.close().await?;
+
+    let pass = match std::str::from_utf8(&value) {
+        Ok(s) => {
+            let len = s.chars().count();
+            let mut pass = SecBuf::new(vec!['X'; len]);
+            pass.insert_many(0, s.chars(), len);
+            Ok(Some(Passphrase(pass)))
+        }
+        Err(err) => Err(err).context("the secret is not valid utf8"),
+    };
+    value.zeroize();
+    Ok(pass?)
+}
+
+struct SecretService {
+    conn: Connection,
+    service: Proxy<'static>,
+    session: OwnedObjectPath,
+}
+
+impl SecretService {
+    async fn open() -> Result<Self> {
+        let conn = Connection::session()
+            .await
+            .context("connect to the session bus")?;
+        let service = Proxy::new(
+            &conn,
+            SERVICE,
+            SERVICE_PATH,
+            "org.freedesktop.Secret.Service",
+        )
+        .await
+        .context("org.freedesktop.Secret.Service proxy")?;
+        // The transport is a local socket so no need for encryption
+        let (_, session): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .await
+            .context("OpenSession")?;
+        Ok(Self {
+            conn,
+            service,
+            session,
+        })
+    }
+
+    async fn close(&self) -> Result<()> {
+        let session = self
+            .proxy(self.session.as_str(), "org.freedesktop.Secret.Session")
+            .await?;
+        session.call_noreply("Close", &()).await.context("Close")?;
+        Ok(())
+    }
+
+    async fn proxy<'a>(&self, path: &'a str, interface: &'a str) -> Result<Proxy<'a>> {
+        Ok(Proxy::new(&self.conn, SERVICE, path, interface)
+            .await
+            .with_context(|| format!("{interface} proxy"))?)
+    }
+
+    async fn prompt(&self, path: &OwnedObjectPath) -> Result<()> {
+        let prompt = self
+            .proxy(path.as_str(), "org.freedesktop.Secret.Prompt")
+            .await?;
+        let mut completed = prompt
+            .receive_signal("Completed")
+            .await
+            .context("Completed")?;
+        prompt
+            .call_noreply("Prompt", &("",))
+            .await
+            .context("Prompt")?;
+        let Some(msg) = completed.next().await else {
+            bail!("the secret service went away");
+        };
+        let (dismissed, _): (bool, OwnedValue) = msg.body().deserialize().context("Completed")?;
+        if dismissed {
+            bail!("unlocking the keyring was dismissed");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::used_underscore_binding)]
+#![allow(clippy::non_ascii_literal)]
+#![allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+#![allow(clippy::option_if_let_else)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    // _NET_* atoms
+    clippy::pub_underscore_fields
+)]
+
+use clap::crate_name;
+use x11rb::atom_manager;
+use x11rb::xcb_ffi::XCBConnection;
+
+pub mod audit;
+pub mod backbuffer;
+pub mod buildinfo;
+pub mod caller;
+pub mod config;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod dialog;
+pub mod dpi;
+pub mod envelope;
+pub mod errors;
+pub mod event;
+pub mod hooks;
+pub mod icon;
+pub mod image;
+pub mod inhibit;
+pub mod keyboard;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+pub mod monitor;
+pub mod queue;
+pub mod screenshot;
+pub mod secret;
+#[cfg(feature = "xsmp")]
+pub mod session;
+pub mod state;
+pub mod sudo;
+#[cfg(feature = "svg")]
+pub mod svg;
+pub mod template;
+pub mod tty;
+pub mod xresources;
+
+pub const CLASS: &str = "SshAskpass";
+pub const NAME: &str = crate_name!();
+
+// A collection of the atoms we will need.
+atom_manager! {
+    pub AtomCollection: AtomCollectionCookie {
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        WM_LOCALE_NAME,
+        _NET_WM_ICON,
+        _NET_WM_ICON_NAME,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_PING,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_MODAL,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_OPAQUE_REGION,
+        _NET_FRAME_EXTENTS,
+        _NET_WM_USER_TIME,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_MOVERESIZE,
+        _MOTIF_WM_HINTS,
+        _XKB_RULES_NAMES,
+        UTF8_STRING,
+        CLIPBOARD,
+        XSEL_DATA,
+        INCR,
+    }
+}
+
+pub type XId = u32;
+
+pub type Connection = XCBConnection;
@@ -5,62 +5,32 @@
 #![allow(clippy::option_if_let_else)]
 
 use std::os::unix::ffi::OsStrExt as _;
+use std::os::unix::io::AsRawFd as _;
 use std::path::PathBuf;
 
-use clap::{crate_name, Args, Command, FromArgMatches as _, Parser};
-use log::{debug, error, info};
+use clap::{Args, Command, FromArgMatches as _, Parser};
+use log::{debug, error, info, warn};
 use tokio::io::unix::AsyncFd;
 use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
 use tokio::time::Instant;
 use x11rb::connection::{Connection as _, RequestConnection as _};
+use x11rb::properties;
 use x11rb::protocol::xproto::{
     self, ColormapWrapper, ConnectionExt as _, CursorWrapper, Window, WindowWrapper,
 };
-use x11rb::{atom_manager, properties};
 // for change_propertyN()
 use x11rb::protocol::randr::{self, ConnectionExt as _};
 use x11rb::protocol::render::{self, ConnectionExt as _, PictType};
 use x11rb::wrapper::ConnectionExt as _;
 use x11rb::xcb_ffi::XCBConnection;
 
-mod backbuffer;
-mod config;
-mod dialog;
-mod errors;
-mod event;
-mod keyboard;
-mod secret;
-
-use errors::{Context as _, Result};
-use secret::Passphrase;
-
-pub const CLASS: &str = "SshAskpass";
-pub const NAME: &str = crate_name!();
-
-// A collection of the atoms we will need.
-atom_manager! {
-    pub AtomCollection: AtomCollectionCookie {
-        WM_PROTOCOLS,
-        WM_DELETE_WINDOW,
-        WM_LOCALE_NAME,
-        _NET_WM_ICON_NAME,
-        _NET_WM_NAME,
-        _NET_WM_PID,
-        _NET_WM_PING,
-        _NET_WM_WINDOW_TYPE,
-        _NET_WM_WINDOW_TYPE_DIALOG,
-        _NET_WM_STATE,
-        _NET_WM_STATE_ABOVE,
-        UTF8_STRING,
-        CLIPBOARD,
-        XSEL_DATA,
-        INCR,
-    }
-}
-
-pub type XId = u32;
-
-pub type Connection = XCBConnection;
+use xaskpass::audit::Ending;
+use xaskpass::envelope::{Envelope, Method};
+use xaskpass::errors::{Context as _, Error, Result};
+use xaskpass::event::Outcome;
+use xaskpass::{backbuffer, config, dialog, event, keyboard, monitor, screenshot, state, template};
+use xaskpass::{bail, AtomCollection, Connection, CLASS, NAME};
 
 fn get_deadline(conn: &Connection, window: Window) -> Result<u128> {
     let has_randr = conn
@@ -195,13 +165,52 @@ fn find_xcb_visualtype(conn: &Connection, visual_id: u32) -> Option<xproto::Visu
     None
 }
 
+// The top level window containing the window, which the window manager knows about.
+fn top_level(
+    conn: &Connection,
+    root: xproto::Window,
+    mut window: xproto::Window,
+) -> Option<xproto::Window> {
+    loop {
+        let Some(tree) = conn.query_tree(window).ok().and_then(|c| c.reply().ok()) else {
+            warn!("parent window {:#x} not found", window);
+            return None;
+        };
+        if tree.root != root {
+            warn!("parent window {:#x} is on another screen", window);
+            return None;
+        }
+        if tree.parent == root {
+            return Some(window);
+        }
+        window = tree.parent;
+    }
+}
+
+// The time of the user action in the startup notification id of the launcher, e.g.
+// "xterm-1234-host-0_TIME123456".
+fn startup_notification_time() -> Option<u32> {
+    let id = std::env::var("DESKTOP_STARTUP_ID").ok()?;
+    let (_, time) = id.rsplit_once("_TIME")?;
+    time.parse()
+        .ok()
+        .filter(|&time| time != x11rb::CURRENT_TIME)
+}
+
 #[allow(clippy::too_many_lines)]
 async fn run_xcontext(
-    config: config::Config,
-    opts: Opts,
+    mut config: config::Config,
+    opts: &Opts,
     startup_time: Instant,
-) -> Result<Option<Passphrase>> {
-    let (conn, screen_num) = XCBConnection::connect(None).context("X11 connect")?;
+    labels: Option<&mut mpsc::UnboundedReceiver<String>>,
+    connected: Option<(XCBConnection, usize)>,
+) -> Result<Outcome> {
+    let inhibit_screensaver = config.inhibit_screensaver;
+    let (conn, default_screen) = match connected {
+        Some(connected) => connected,
+        None => XCBConnection::connect(None).context("X11 connect")?,
+    };
+    let screen_num = opts.screen.unwrap_or(default_screen);
     let xfd = AsyncFd::new(conn).context("asyncfd failed")?;
     let conn = xfd.get_ref();
 
@@ -216,7 +225,13 @@ async fn run_xcontext(
     conn.flush()?;
 
     let setup = conn.setup();
-    let screen = setup.roots.get(screen_num).expect("unknown screen");
+    let Some(screen) = setup.roots.get(screen_num) else {
+        bail!(
+            "no screen {} (the display has {})",
+            screen_num,
+            setup.roots.len()
+        );
+    };
     debug!(
         "screen {screen_num} root depth: {}, root visual {}",
         screen.root_depth, screen.root_visual
@@ -246,19 +261,124 @@ async fn run_xcontext(
     };
 
     let visual_type = find_xcb_visualtype(conn, visualid).unwrap();
+    config.dialog.dither &= visual_type.bits_per_rgb_value < 10;
 
     let surface = backbuffer::XcbSurface::new(conn, screen.root, depth, &visual_type, 1, 1)?;
     let backbuffer = backbuffer::Backbuffer::new(conn, screen.root, surface)?;
     conn.flush()?;
+    let header = if opts.basic_auth {
+        Some(config.dialog.username_label.clone())
+    } else if config.sudo_header && !opts.demo {
+        xaskpass::sudo::SudoInfo::detect(opts.label.as_deref()).map(|sudo| sudo.header())
+    } else {
+        None
+    };
+    let caller = if config.show_caller && !opts.demo {
+        xaskpass::caller::describe()
+    } else {
+        None
+    };
+    let monitors = monitor::monitors(conn, screen)?;
+    let px_per_mm = monitor::px_per_mm(&monitors, screen);
+    config.dialog.scale = xaskpass::dpi::scale(conn, screen_num, config.dialog.scale);
+    if xaskpass::dpi::xsetting_int(conn, screen_num, "Xaskpass/ReduceTransparency")
+        .is_some_and(|reduce| reduce != 0)
+    {
+        debug!("reduce transparency from XSETTINGS");
+        config.dialog.reduce_transparency = true;
+    }
+    if config.dialog.icon_theme.is_none() {
+        config.dialog.icon_theme =
+            xaskpass::dpi::xsetting_string(conn, screen_num, "Net/IconThemeName");
+    }
+    let layout = config.dialog.layout_opts.layout;
+    let themes = if config.dialog.theme_key.is_some() {
+        let mut themes = vec![config.dialog.clone()];
+        themes.extend(config::Loader::new().load_themes(&config.dialog.themes));
+        themes
+    } else {
+        Vec::new()
+    };
+    let min_entropy = config.dialog.min_entropy;
     let mut dialog = dialog::Dialog::new(
         config.dialog,
         // TODO should be private
         &backbuffer.cr,
+        px_per_mm,
         opts.label.as_deref(),
-        opts.debug,
+        header.as_deref(),
+        caller.as_deref(),
     )?;
-    let (window_width, window_height) = dialog.window_size(&backbuffer.cr);
+    dialog.set_reflow(config.resizable);
+    dialog.set_draggable(config.frameless);
+    if config.early_focus_loss == config::EarlyFocusLoss::Hint {
+        dialog.reserve_focus_hint();
+    }
+    dialog.set_themes(themes);
+    if opts.demo {
+        dialog.fill_demo();
+    } else if opts.basic_auth {
+        dialog.set_mode(dialog::Mode::BasicAuth(config.basic_auth_output));
+    } else {
+        dialog.set_mode(dialog::Mode::detect(
+            opts.label.as_deref().unwrap_or_default(),
+            config.prompt_heuristics,
+        ));
+        let label = opts.label.as_deref().unwrap_or_default().to_lowercase();
+        let new_passphrase = opts.new_passphrase
+            || config.prompt_heuristics
+                && (label.contains("new pass") || label.contains("new pin"));
+        if let Some(bits) = min_entropy.filter(|_| new_passphrase) {
+            dialog.set_min_entropy(bits);
+        }
+    }
+    #[cfg(feature = "keyring")]
+    {
+        let attributes = if opts.keyring_attributes.is_empty() {
+            &opts.keyring_lookup
+        } else {
+            &opts.keyring_attributes
+        };
+        if !attributes.is_empty() && !opts.demo {
+            dialog.set_keyring(xaskpass::keyring::Item {
+                label: opts.label.clone().unwrap_or_else(|| NAME.into()),
+                attributes: attributes.iter().cloned().collect(),
+            });
+        }
+    }
+    if let Some(attempt) = opts.attempt {
+        dialog.set_attempt(attempt, opts.attempts);
+    }
+    if let Some(ref error) = opts.error {
+        dialog.set_error(error);
+    }
+    if let Some(secs) = opts.deadline {
+        dialog.set_deadline(startup_time + std::time::Duration::from_secs(secs));
+    }
+    let (dialog_width, dialog_height) = dialog.window_size(&backbuffer.cr);
+    let window_sizes = config.resizable.then(|| {
+        state::WindowSizes::load().unwrap_or_else(|err| {
+            warn!("{}", err);
+            state::WindowSizes::default()
+        })
+    });
+    // Never smaller than what the dialog needs
+    let (window_width, window_height) = window_sizes
+        .as_ref()
+        .and_then(|sizes| sizes.get(layout))
+        .map_or((dialog_width, dialog_height), |(width, height)| {
+            (width.max(dialog_width), height.max(dialog_height))
+        });
     debug!("window width: {}, height: {}", window_width, window_height);
+    // Without the frame until the window manager tells its extents
+    let center = if config.center {
+        monitor::primary(&monitors).copied()
+    } else {
+        None
+    };
+    let (window_x, window_y) = center.map_or((0, 0), |m| {
+        m.centered((window_width, window_height), [0; 4])
+    });
 
     let colormap = if visual_type.visual_id == screen.root_visual {
         None
@@ -277,8 +397,8 @@ async fn run_xcontext(
         conn,
         depth,
         screen.root,
-        0, // x
-        0, // y
+        window_x as i16,
+        window_y as i16,
         window_width,
         window_height,
         0, // border_width
@@ -289,6 +409,7 @@ async fn run_xcontext(
                 xproto::EventMask::EXPOSURE
                     | xproto::EventMask::KEY_PRESS
                     | xproto::EventMask::STRUCTURE_NOTIFY
+                    | xproto::EventMask::PROPERTY_CHANGE
                     | xproto::EventMask::BUTTON_PRESS
                     | xproto::EventMask::BUTTON_RELEASE
                     | xproto::EventMask::POINTER_MOTION
@@ -306,22 +427,9 @@ async fn run_xcontext(
 
     let atoms = atoms.reply()?;
 
-    let hostname = std::env::var_os("HOSTNAME").unwrap_or_else(gethostname::gethostname);
-    let mut title = if let Some(t) = config.title {
-        t
-    } else {
-        let ppid = std::os::unix::process::parent_id();
-        let name = i32::try_from(ppid).ok().and_then(|pid| {
-            procfs::process::Process::new(pid)
-                .ok()
-                .and_then(|p| p.status().ok().map(|s| s.name))
-        });
-        format!("{}[{ppid}]", name.as_deref().unwrap_or(""))
-    };
-    if config.show_hostname {
-        title.push('@');
-        title.push_str(&hostname.to_string_lossy());
-    }
+    let hostname = template::hostname();
+    let title = template::Vars::from_env()
+        .expand(config.title.as_deref().unwrap_or(template::DEFAULT_TITLE));
     conn.change_property8(
         xproto::PropMode::REPLACE,
         window,
@@ -389,13 +497,43 @@ async fn run_xcontext(
         xproto::AtomEnum::ATOM,
         &[atoms._NET_WM_WINDOW_TYPE_DIALOG],
     )?;
-    // be above of other windows
+    if config.frameless {
+        // The flags say that only the decorations are given, and those are none
+        conn.change_property32(
+            xproto::PropMode::REPLACE,
+            window,
+            atoms._MOTIF_WM_HINTS,
+            atoms._MOTIF_WM_HINTS,
+            &[2, 0, 0, 0, 0],
+        )?;
+    }
+    let parent = if config.modal {
+        opts.parent
+            .or_else(|| std::env::var("WINDOWID").ok()?.parse().ok())
+            .and_then(|parent| top_level(conn, screen.root, parent))
+    } else {
+        None
+    };
+    let state = if let Some(parent) = parent {
+        debug!("modal for window {:#x}", parent);
+        conn.change_property32(
+            xproto::PropMode::REPLACE,
+            window,
+            xproto::AtomEnum::WM_TRANSIENT_FOR,
+            xproto::AtomEnum::WINDOW,
+            &[parent],
+        )?;
+        atoms._NET_WM_STATE_MODAL
+    } else {
+        // be above of other windows
+        atoms._NET_WM_STATE_ABOVE
+    };
     conn.change_property32(
         xproto::PropMode::REPLACE,
         window,
         atoms._NET_WM_STATE,
         xproto::AtomEnum::ATOM,
-        &[atoms._NET_WM_STATE_ABOVE],
+        &[state],
     )?;
     // get a client message instead of connection error when the user closes the window
     conn.change_property32(
@@ -406,17 +544,55 @@ async fn run_xcontext(
         &[atoms.WM_DELETE_WINDOW, atoms._NET_WM_PING],
     )?;
 
+    let feedback_led = if let Some(ref led) = config.led_feedback {
+        Some(conn.intern_atom(false, led.as_bytes())?.reply()?.atom)
+    } else {
+        None
+    };
+
+    let user_time = startup_notification_time();
+    if let Some(user_time) = user_time {
+        debug!("user time from the startup notification: {}", user_time);
+        conn.change_property32(
+            xproto::PropMode::REPLACE,
+            window,
+            atoms._NET_WM_USER_TIME,
+            xproto::AtomEnum::CARDINAL,
+            &[user_time],
+        )?;
+    }
+
     // NOTE cannot set urgent with _NET_WM_STATE_ABOVE
     let wm_hints = properties::WmHints {
         input: Some(true),
         initial_state: Some(properties::WmHintsState::Normal),
         ..properties::WmHints::default()
     };
-    // TODO icon?
     wm_hints.set(conn, window)?;
+    if let Some(ref path) = config.icon {
+        match xaskpass::icon::load(path) {
+            Ok(icon) => {
+                conn.change_property32(
+                    xproto::PropMode::REPLACE,
+                    window,
+                    atoms._NET_WM_ICON,
+                    xproto::AtomEnum::CARDINAL,
+                    &icon,
+                )?;
+            }
+            Err(err) => warn!("icon {}: {:#}", path, err),
+        }
+    }
 
     let mut size_hints = properties::WmSizeHints {
-        min_size: Some((window_width.into(), window_height.into())),
+        position: center.map(|_| {
+            (
+                properties::WmSizeHintsSpecification::UserSpecified,
+                window_x,
+                window_y,
+            )
+        }),
+        min_size: Some((dialog_width.into(), dialog_height.into())),
         ..properties::WmSizeHints::default()
     };
     if !config.resizable {
@@ -427,6 +603,11 @@ async fn run_xcontext(
 
     debug!("map window");
     conn.map_window(window)?;
+    if config.wake_display {
+        if let Err(err) = xaskpass::inhibit::wake(conn) {
+            warn!("could not wake the display: {}", err);
+        }
+    }
     debug!("flush");
     conn.flush()?;
 
@@ -459,6 +640,29 @@ async fn run_xcontext(
     debug!("keyboard init");
     let keyboard = keyboard::Keyboard::new(conn)?;
     dialog.set_keyboard(&keyboard);
+    let force_layout = if let Some(ref name) = config.force_layout {
+        let rules = conn
+            .get_property(
+                false,
+                screen.root,
+                atoms._XKB_RULES_NAMES,
+                xproto::AtomEnum::STRING,
+                0,
+                1024,
+            )?
+            .reply()?
+            .value;
+        // rules, model, layout, variant and options
+        let rules = String::from_utf8_lossy(&rules);
+        let short_names: Vec<&str> = rules.split('\0').nth(2).unwrap_or("").split(',').collect();
+        let index = keyboard.find_layout(name, &short_names);
+        if index.is_none() {
+            warn!("keyboard layout '{}' not found", name);
+        }
+        index
+    } else {
+        None
+    };
 
     let input_cursor = if let Some(cursor_handle) = cursor_handle {
         debug!("cursor init");
@@ -477,6 +681,9 @@ async fn run_xcontext(
     debug!("dialog init");
     let mut backbuffer = backbuffer.reply()?;
     backbuffer.init(window, &mut dialog)?;
+    if (window_width, window_height) != (dialog_width, dialog_height) {
+        backbuffer.request_resize(window_width, window_height);
+    }
 
     let mut xcontext = event::XContext::new(event::Config {
         keyboard,
@@ -487,19 +694,421 @@ async fn run_xcontext(
         width: window_width,
         height: window_height,
         grab_keyboard: config.grab_keyboard,
+        focus_loss_grace: config
+            .cancel_on_focus_loss
+            .then(|| std::time::Duration::from_millis(config.focus_loss_grace)),
         startup_time,
         input_cursor,
         compositor_atom,
-        debug: opts.debug,
         cycle_deadline,
         root: screen.root,
+        resizable: config.resizable,
+        user_time,
+        above_removed: parent.is_none().then_some(config.above_removed),
+        center,
+        warp_pointer: config.warp_pointer,
+        early_focus_loss: config.early_focus_loss,
+        redraw_batch: config
+            .remote_redraw_interval
+            .filter(|_| remote_display())
+            .map(std::time::Duration::from_millis),
+        recenter: config
+            .recenter
+            .then(|| monitor::primary(&monitors).copied())
+            .flatten(),
+        monitors: if config.follow_monitor_dpi {
+            monitors
+        } else {
+            Vec::new()
+        },
+        feedback_led,
+        force_layout,
+        labels,
     })?;
     debug!("init took {}ms", startup_time.elapsed().as_millis());
 
-    xcontext.run_events(dialog).await
+    let _inhibitor = if inhibit_screensaver {
+        xaskpass::inhibit::Inhibitor::new(conn)
+            .map_err(|err| warn!("could not keep the screen from blanking: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    let ret = Box::pin(xcontext.run_events(dialog))
+        .await
+        .map_err(Error::lost);
+    // Not the size of the screen
+    if let Some(mut sizes) = window_sizes.filter(|_| !xcontext.is_maximized()) {
+        let size = xcontext.window_size();
+        if size != (window_width, window_height) {
+            sizes.set(layout, size);
+            if let Err(err) = sizes.save() {
+                warn!("{}", err);
+            }
+        }
+    }
+    ret
+}
+
+/// Whether DISPLAY names a host, e.g. `localhost:10.0` when forwarded over ssh.
+fn remote_display() -> bool {
+    let display = std::env::var("DISPLAY").unwrap_or_default();
+    let host = display.rsplit_once(':').map_or("", |(host, _)| host);
+    !host.is_empty() && host != "unix" && !host.starts_with('/')
 }
 
-#[derive(Parser)]
+/// Reads the passphrase from the terminal instead, see `XASKPASS_FALLBACK_TTY`.
+async fn run_tty(config: &config::Config, opts: &Opts) -> Result<Outcome> {
+    let label = opts
+        .label
+        .clone()
+        .unwrap_or_else(|| config.dialog.label.clone());
+    let mode = if opts.basic_auth {
+        dialog::Mode::BasicAuth(config.basic_auth_output)
+    } else {
+        dialog::Mode::detect(&label, config.prompt_heuristics)
+    };
+    let timeout = config
+        .dialog
+        .input_timeout
+        .map(std::time::Duration::from_secs);
+    let timeout_action = config.dialog.timeout_action;
+    let (mut prompt, _cancel) = xaskpass::tty::Prompt::open(timeout)?;
+    // Dropping `_cancel` with this future ends the read, and with it the blocking task that the
+    // runtime waits for on exit
+    tokio::task::spawn_blocking(move || prompt.run(&label, mode, timeout_action))
+        .await
+        .context("tty prompt")?
+}
+
+/// Sends each line read from stdin as a new label.
+fn read_labels() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    // Not a tokio task so that exiting is not held up by a blocked read
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Runs the dialog on `connected` or a new connection, re-prompting once with a new
+/// connection if the X connection is lost while the dialog is shown. Failing again is
+/// `Error::Lost`, which exits with code 3.
+async fn run_prompt(
+    config: &config::Config,
+    opts: &Opts,
+    startup_time: Instant,
+    mut labels: Option<&mut mpsc::UnboundedReceiver<String>>,
+    connected: Option<(XCBConnection, usize)>,
+) -> Result<Outcome> {
+    let tty_fallback = xaskpass::tty::fallback_enabled();
+    if tty_fallback && std::env::var_os("DISPLAY").is_none_or(|display| display.is_empty()) {
+        return run_tty(config, opts).await;
+    }
+    match Box::pin(run_xcontext(
+        config.clone(),
+        opts,
+        startup_time,
+        labels.as_deref_mut(),
+        connected,
+    ))
+    .await
+    {
+        Err(err) if tty_fallback && err.is_connect_failure() => {
+            warn!("{}; reading from the terminal", err);
+            run_tty(config, opts).await
+        }
+        Err(Error::Lost(err)) => {
+            warn!("{}; prompting again", err);
+            Box::pin(run_xcontext(
+                config.clone(),
+                opts,
+                startup_time,
+                labels,
+                None,
+            ))
+            .await
+            .map_err(|err| {
+                if err.is_connect_failure() {
+                    Error::Lost(Box::new(err))
+                } else {
+                    err.lost()
+                }
+            })
+        }
+        ret => ret,
+    }
+}
+
+async fn run_main(
+    config: &config::Config,
+    opts: &Opts,
+    startup_time: Instant,
+    connected: Option<(XCBConnection, usize)>,
+    parent: Option<xaskpass::caller::Watch>,
+) -> Result<i32> {
+    #[cfg(feature = "dbus")]
+    if opts.daemon {
+        run_daemon(config, opts).await?;
+        return Ok(0);
+    }
+    #[cfg(feature = "keyring")]
+    if !opts.keyring_lookup.is_empty() && !opts.demo {
+        match xaskpass::keyring::lookup(&opts.keyring_lookup).await {
+            Ok(Some(pass)) => {
+                debug!("found the secret in the keyring");
+                pass.write_stdout().unwrap();
+                return Ok(0);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("keyring lookup failed: {}", err),
+        }
+    }
+    let watched = opts
+        .watch_pid
+        .map(xaskpass::caller::Watch::pid)
+        .or_else(|| parent.filter(|_| config.cancel_on_parent_exit));
+    // Before the caller may have gone away
+    let caller = config
+        .audit_log
+        .is_some()
+        .then(xaskpass::caller::describe)
+        .flatten();
+    let mut labels = opts.label_stdin.then(read_labels);
+    #[cfg(feature = "xsmp")]
+    let mut session = xaskpass::session::Session::open().unwrap_or_else(|err| {
+        warn!("{}", err);
+        None
+    });
+    let ret = tokio::select! {
+        ret = async {
+            let _turn = if config.queue_prompts {
+                xaskpass::queue::wait_turn()
+                    .await
+                    .map_err(|err| warn!("{:#}", err))
+                    .ok()
+            } else {
+                None
+            };
+            run_prompt(config, opts, startup_time, labels.as_mut(), connected).await
+        } => ret?,
+        () = async {
+            #[cfg(feature = "xsmp")]
+            if let Some(session) = &mut session {
+                match session.died().await {
+                    Ok(()) => return,
+                    Err(err) => warn!("{}", err),
+                }
+            }
+            std::future::pending::<()>().await;
+        } => {
+            info!("the session is ending");
+            Outcome::Cancelled
+        }
+        () = async {
+            match watched {
+                Some(watched) => watched.exited().await,
+                None => std::future::pending().await,
+            }
+        } => {
+            info!("the requesting process exited");
+            Outcome::Cancelled
+        }
+        () = async {
+            match opts.deadline {
+                Some(secs) => tokio::time::sleep_until(startup_time + std::time::Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        } => {
+            info!("the deadline passed");
+            report(config, opts, caller.as_deref(), Ending::Timeout, startup_time, None);
+            return Ok(4);
+        }
+    };
+    Ok(finish(config, opts, caller.as_deref(), ret, startup_time).await)
+}
+
+/// Prints the passphrase if there is one and returns the exit code.
+async fn finish(
+    config: &config::Config,
+    opts: &Opts,
+    caller: Option<&str>,
+    outcome: Outcome,
+    startup_time: Instant,
+) -> i32 {
+    let (pass, event, method, remember) = match outcome {
+        Outcome::Ok(pass, method, remember) => (Some(pass), Ending::Ok, Some(method), remember),
+        Outcome::Cancelled => (None, Ending::Cancel, None, None),
+        Outcome::TimedOut(Some((pass, method, remember))) => {
+            (Some(pass), Ending::Timeout, Some(method), remember)
+        }
+        Outcome::TimedOut(None) => (None, Ending::Timeout, None, None),
+    };
+    let ret = match pass {
+        Some(_) if opts.demo => {
+            debug!("demo: not printing the passphrase");
+            0
+        }
+        Some(ref pass) => {
+            pass.write_stdout().unwrap();
+            0
+        }
+        None => {
+            debug!("cancelled");
+            1
+        }
+    };
+    report(config, opts, caller, event, startup_time, method);
+    if let (Some(remember), Some(pass)) = (remember, pass) {
+        // The caller has what it needs, so do not keep it waiting for the keyring
+        release_stdout();
+        store_in_keyring(remember, &pass).await;
+    }
+    ret
+}
+
+#[cfg(feature = "keyring")]
+const KEYRING_TIMEOUT: std::time::Duration = std::time::Duration::from_mins(2);
+
+// Points stdout to /dev/null so that the reader of the passphrase sees its end.
+fn release_stdout() {
+    match std::fs::File::options().write(true).open("/dev/null") {
+        Ok(null) => {
+            if unsafe { libc::dup2(null.as_raw_fd(), 1) } == -1 {
+                debug!("dup2 stdout: {}", std::io::Error::last_os_error());
+            }
+        }
+        Err(err) => debug!("/dev/null: {}", err),
+    }
+}
+
+/// Stores the passphrase as the item of the remember button. Does not wait forever for an
+/// unlock prompt of the keyring.
+#[cfg(feature = "keyring")]
+async fn store_in_keyring(item: xaskpass::keyring::Item, pass: &xaskpass::secret::Passphrase) {
+    match tokio::time::timeout(KEYRING_TIMEOUT, item.store(pass)).await {
+        Ok(Ok(())) => debug!("stored the secret in the keyring"),
+        Ok(Err(err)) => warn!("could not store the secret in the keyring: {}", err),
+        Err(_) => warn!("could not store the secret in the keyring: timed out"),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+#[allow(clippy::unused_async)]
+async fn store_in_keyring(item: std::convert::Infallible, _: &xaskpass::secret::Passphrase) {
+    match item {}
+}
+
+/// Writes the audit record and the `--result-fd` envelope and starts the hook for how the
+/// prompt ended.
+fn report(
+    config: &config::Config,
+    opts: &Opts,
+    caller: Option<&str>,
+    event: Ending,
+    startup_time: Instant,
+    method: Option<Method>,
+) {
+    if let Some(ref target) = config.audit_log {
+        let vars = template::Vars::from_env();
+        let record = xaskpass::audit::Record {
+            time: xaskpass::audit::now(),
+            user: &vars.user,
+            caller,
+            title: &vars.expand(config.title.as_deref().unwrap_or(template::DEFAULT_TITLE)),
+            label: opts.label.as_deref(),
+            outcome: event,
+        };
+        if let Err(err) = record.write(target) {
+            warn!("{:#}", err);
+        }
+    }
+    write_envelope(opts, event.as_str(), startup_time, method);
+    let hook = match event {
+        Ending::Ok => &config.hooks.on_ok,
+        Ending::Cancel => &config.hooks.on_cancel,
+        Ending::Timeout => &config.hooks.on_timeout,
+    };
+    xaskpass::hooks::run(hook, event.as_str());
+}
+
+fn write_envelope(opts: &Opts, outcome: &str, startup_time: Instant, method: Option<Method>) {
+    if let Some(fd) = opts.result_fd {
+        let envelope = Envelope {
+            outcome,
+            duration_ms: u64::try_from(startup_time.elapsed().as_millis()).unwrap_or(u64::MAX),
+            method,
+            retries: opts.attempt.map_or(0, |attempt| attempt - 1),
+        };
+        if let Err(err) = envelope.write_fd(fd) {
+            warn!("{:#}", err);
+        }
+    }
+}
+
+#[cfg(feature = "dbus")]
+async fn run_daemon(config: &config::Config, opts: &Opts) -> Result<()> {
+    use xaskpass::dbus::{Daemon, Outcome};
+    use xaskpass::event;
+
+    let mut daemon = Daemon::new().await?;
+    info!("waiting for prompts on the session bus");
+    // Every prompt has its own X connection and dialog
+    let prompts = tokio::task::LocalSet::new();
+    prompts
+        .run_until(async {
+            while let Some(request) = daemon.requests.recv().await {
+                let mut config = config.clone();
+                request.options.apply(&mut config);
+                let mut opts = opts.clone();
+                opts.label = Some(request.label);
+                let mut labels = request.labels;
+                tokio::task::spawn_local(async move {
+                    let outcome = tokio::select! {
+                        ret = async {
+                            // One dialog at a time, they would fight over the keyboard grab
+                            let _turn = xaskpass::queue::wait_turn()
+                                .await
+                                .map_err(|err| warn!("{:#}", err))
+                                .ok();
+                            run_prompt(&config, &opts, Instant::now(), Some(&mut labels), None).await
+                        } => match ret {
+                            Ok(event::Outcome::Ok(pass, _, remember) | event::Outcome::TimedOut(Some((pass, _, remember)))) => (Outcome::Ok(pass), remember),
+                            Ok(event::Outcome::Cancelled | event::Outcome::TimedOut(None)) => (Outcome::Cancelled, None),
+                            Err(err) => {
+                                error!("{}", err);
+                                (Outcome::Error(err.to_string()), None)
+                            }
+                        },
+                        () = request.cancel.notified() => {
+                            debug!("cancelled over D-Bus");
+                            (Outcome::Cancelled, None)
+                        }
+                    };
+                    let (outcome, remember) = outcome;
+                    // The dialog is gone by now, so an unlock prompt can have the keyboard
+                    if let (Outcome::Ok(pass), Some(remember)) = (&outcome, remember) {
+                        store_in_keyring(remember, pass).await;
+                    }
+                    if request.reply.send(outcome).is_err() {
+                        debug!("prompt caller went away");
+                    }
+                });
+            }
+        })
+        .await;
+    Ok(())
+}
+
+#[derive(Parser, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 #[command(
     version = env!("XASKPASS_BUILD_FULL_VERSION"),
     about,
@@ -522,7 +1131,12 @@ struct Opts {
     config: Option<PathBuf>,
 
     #[arg(short, long)]
-    /// Include additional and sensitive information in logs.
+    /// Apply [profiles.<PROFILE>] from the configuration. By default the profile named after
+    /// --name or else after the parent process is used if there is one.
+    profile: Option<String>,
+
+    #[arg(short, long)]
+    /// Include timestamps and module names in logs. Secrets are never logged.
     debug: bool,
 
     /// Label in the dialog.
@@ -531,10 +1145,132 @@ struct Opts {
     /// Output default config to stdout.
     #[arg(long)]
     gen_config: bool,
+
+    /// Convert an x11-ssh-askpass resource file to a config and output it.
+    #[arg(long, value_name = "PATH")]
+    import_xresources: Option<PathBuf>,
+
+    /// PNG file to use as the window icon instead of the configured one.
+    #[arg(long, value_name = "PATH")]
+    icon: Option<String>,
+
+    /// Output a JSON Schema of the config for editors.
+    #[arg(long)]
+    gen_schema: bool,
+
+    /// Render the dialog to a PNG file without connecting to an X server.
+    #[arg(long, value_name = "PATH")]
+    screenshot: Option<PathBuf>,
+
+    /// Print the computed geometry of the dialog as JSON without connecting to an X server.
+    #[arg(long)]
+    dump_layout: bool,
+
+    /// Print the version, the enabled features and the library versions for bug reports.
+    #[arg(long)]
+    buildinfo: bool,
+
+    /// Print the screens, monitors and DPI settings detected and the scale that would be used.
+    #[arg(long)]
+    dpi_info: bool,
+
+    /// Show all the widgets with a dummy passphrase for trying out themes. Nothing is printed.
+    #[arg(long)]
+    demo: bool,
+
+    /// Ask for a username and then a password and print them as configured by `basic_auth_output`.
+    #[arg(long)]
+    basic_auth: bool,
+
+    /// Keep running and show a dialog for each Prompt call on the D-Bus session bus.
+    #[cfg(feature = "dbus")]
+    #[arg(long)]
+    daemon: bool,
+
+    /// Show a checkbox to store the secret in the Secret Service with this attribute. Can be
+    /// given multiple times.
+    #[cfg(feature = "keyring")]
+    #[arg(long = "keyring-attribute", value_name = "KEY=VALUE", value_parser = parse_attribute)]
+    keyring_attributes: Vec<(String, String)>,
+
+    /// Print the secret of the keyring item matching this attribute instead of prompting if
+    /// there is one. Can be given multiple times. Also used for remembering if
+    /// --keyring-attribute is not given.
+    #[cfg(feature = "keyring")]
+    #[arg(long = "keyring-lookup", value_name = "KEY=VALUE", value_parser = parse_attribute)]
+    keyring_lookup: Vec<(String, String)>,
+
+    /// Show a countdown and exit with code 4 when it runs out, e.g. for boot time prompts that
+    /// must not block.
+    #[arg(long, value_name = "SECONDS")]
+    deadline: Option<u64>,
+
+    /// The passphrase is being set, so warn if it is weaker than `dialog.min_entropy`.
+    #[arg(long)]
+    new_passphrase: bool,
+
+    /// Show this in red above the indicator, e.g. "Wrong passphrase, try again" from a wrapper
+    /// that calls xaskpass again after a failure.
+    #[arg(long, value_name = "TEXT")]
+    error: Option<String>,
+
+    /// Which try this is when xaskpass is called again after a wrong passphrase. It is shown
+    /// under the label and from the second one on OK is held off for `dialog.retry_backoff`,
+    /// doubled each time.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    attempt: Option<u32>,
+
+    /// The number of tries allowed, shown with --attempt.
+    #[arg(long, value_name = "N", requires = "attempt")]
+    attempts: Option<u32>,
+
+    /// Also write how the prompt ended as a JSON line to this file descriptor: the outcome (ok,
+    /// cancel, timeout or error), how long it took, whether the passphrase was typed or pasted and the number of retries.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(3..))]
+    result_fd: Option<i32>,
+
+    /// Replace the label with each line read from stdin while the dialog is shown, e.g. to
+    /// count down the attempts left.
+    #[arg(long)]
+    label_stdin: bool,
+
+    /// Cancel when this process exits, whether or not `cancel_on_parent_exit` is set.
+    #[arg(long, value_name = "PID")]
+    watch_pid: Option<i32>,
+
+    /// Override dialog.scale from the configuration.
+    #[arg(long)]
+    scale: Option<f64>,
+
+    /// The X screen to show the dialog on instead of the one of $DISPLAY.
+    #[arg(long, value_name = "N")]
+    screen: Option<usize>,
+
+    /// The window to be a modal dialog of with `modal` in the configuration, instead of
+    /// $WINDOWID. Decimal or hexadecimal with 0x.
+    #[arg(long, value_name = "WINDOW", value_parser = parse_window)]
+    parent: Option<xproto::Window>,
+}
+
+fn parse_window(arg: &str) -> std::result::Result<xproto::Window, String> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => xproto::Window::from_str_radix(hex, 16),
+        None => arg.parse(),
+    }
+    .map_err(|err| format!("invalid window '{arg}': {err}"))
+}
+
+#[cfg(feature = "keyring")]
+fn parse_attribute(arg: &str) -> std::result::Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.into(), value.into())),
+        _ => Err(format!("expected KEY=VALUE, got '{arg}'")),
+    }
 }
 
 fn run() -> i32 {
     let startup_time = Instant::now();
+    let parent = xaskpass::caller::Watch::parent();
 
     let cfg_loader = config::Loader::new();
     let mut help = format!(
@@ -563,29 +1299,98 @@ fn run() -> i32 {
         env!("XASKPASS_BUILD_FULL_VERSION")
     );
 
-    match run_logged(&cfg_loader, opts, startup_time) {
+    match run_logged(&cfg_loader, &opts, startup_time, parent) {
         Ok(ret) => ret,
         Err(err) => {
             error!("{}", err);
-            2
+            if matches!(err, Error::Lost(_)) {
+                3
+            } else {
+                2
+            }
         }
     }
 }
 
-fn run_logged(cfg_loader: &config::Loader, opts: Opts, startup_time: Instant) -> Result<i32> {
+fn xresources_fallback(conn: &XCBConnection, name: &str) -> toml::Table {
+    match x11rb::resource_manager::new_from_default(conn) {
+        Ok(db) => xaskpass::xresources::fallback(&db, name),
+        Err(err) => {
+            debug!("no X resources: {:#}", err);
+            toml::Table::new()
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn run_logged(
+    cfg_loader: &config::Loader,
+    opts: &Opts,
+    startup_time: Instant,
+    parent: Option<xaskpass::caller::Watch>,
+) -> Result<i32> {
     if opts.gen_config {
         let cfg = config::Config::default();
         config::Loader::print(&cfg)?;
         return Ok(0);
     }
+    if let Some(ref path) = opts.import_xresources {
+        let data = std::fs::read(path).context("X resource file")?;
+        let base = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        let db = x11rb::resource_manager::Database::new_from_data_with_base_directory(&data, base);
+        config::Loader::print(&xaskpass::xresources::import(&db)?)?;
+        return Ok(0);
+    }
+    if opts.gen_schema {
+        config::Loader::print_schema()?;
+        return Ok(0);
+    }
+    if opts.buildinfo {
+        xaskpass::buildinfo::print()?;
+        return Ok(0);
+    }
+
+    if let Some(fd) = opts.result_fd {
+        // Before an X connection could get the same number
+        xaskpass::envelope::check_fd(fd)?;
+    }
+    // Also the connection of the dialog, so that the X resources come from the same server
+    let mut connected = if opts.screenshot.is_some() || opts.dump_layout {
+        None
+    } else {
+        XCBConnection::connect(None)
+            .map_err(|err| debug!("X11 connect: {}", err))
+            .ok()
+    };
 
     debug!("load config");
-    let config = if let Some(ref path) = opts.config {
-        config::Loader::load_path(path)?
+    let profile = config::Profile {
+        explicit: opts.profile.as_deref(),
+        candidates: vec![opts.name.clone(), template::Vars::from_env().cmd],
+        fallback: connected
+            .as_ref()
+            .map(|(conn, _)| xresources_fallback(conn, &opts.name))
+            .unwrap_or_default(),
+    };
+    let mut config = if let Some(ref path) = opts.config {
+        config::Loader::load_path(path, &profile)?
     } else {
-        cfg_loader.load()?
+        cfg_loader.load(&profile)?
     };
     debug!("config loaded");
+    config.dialog.label = template::Vars::from_env().expand(&config.dialog.label);
+    if opts.scale.is_some() {
+        config.dialog.scale = opts.scale;
+    }
+    if opts.icon.is_some() {
+        config.icon.clone_from(&opts.icon);
+    }
+    if opts.demo {
+        // The only layout with every widget
+        config.dialog.layout_opts.layout = dialog::layout::Layout::Center;
+        config.grab_keyboard = false;
+        config.cancel_on_focus_loss = false;
+    }
 
     //let locale_os = dialog::getlocale().context("getlocale")?;
     //debug!("string locale: {}", locale_os.to_string_lossy());
@@ -599,13 +1404,34 @@ fn run_logged(cfg_loader: &config::Loader, opts: Opts, startup_time: Instant) ->
 
     // bind the return value to prevent it from being dropped
     let _runtime_guard = runtime.enter();
+
+    if let Some(ref path) = opts.screenshot {
+        screenshot::write_png(config.dialog, opts.label.as_deref(), opts.demo, path)?;
+        return Ok(0);
+    }
+    if opts.dump_layout {
+        screenshot::print_layout(config.dialog, opts.label.as_deref(), opts.demo)?;
+        return Ok(0);
+    }
+    if opts.dpi_info {
+        let (conn, default_screen) = match connected.take() {
+            Some(connected) => connected,
+            None => XCBConnection::connect(None).context("X11 connect")?,
+        };
+        xaskpass::dpi::print_info(
+            &conn,
+            opts.screen.unwrap_or(default_screen),
+            config.dialog.scale,
+        )?;
+        return Ok(0);
+    }
+
     // Initialize signals soon so objects are dropped properly when a signal is received.
     let mut sigint = signal(SignalKind::interrupt()).unwrap();
     let mut sighup = signal(SignalKind::hangup()).unwrap();
     let mut sigterm = signal(SignalKind::terminate()).unwrap();
 
-    let mut mainret = 1;
-    runtime.block_on(async {
+    let ret = runtime.block_on(async {
         tokio::select! {
             _ = sigint.recv() => {
                 info!("got sigint");
@@ -616,22 +1442,26 @@ fn run_logged(cfg_loader: &config::Loader, opts: Opts, startup_time: Instant) ->
             _ = sigterm.recv() => {
                 info!("got sigterm");
             }
-            ret = run_xcontext(config, opts, startup_time) => {
-                match ret? {
-                    Some(pass) => {
-                        pass.write_stdout().unwrap();
-                        mainret = 0;
-                    }
-                    None => {
-                        debug!("cancelled");
-                    },
-                }
+            ret = run_main(&config, opts, startup_time, connected.take(), parent) => {
+                return ret.map(Some);
             }
         }
-        Ok(()) as Result<()>
-    })?;
+        Ok(None)
+    });
+    let ret = match ret {
+        Ok(Some(ret)) => ret,
+        // The prompt did not get to write the envelope
+        Ok(None) => {
+            write_envelope(opts, "error", startup_time, None);
+            1
+        }
+        Err(err) => {
+            write_envelope(opts, "error", startup_time, None);
+            return Err(err);
+        }
+    };
     debug!("exit");
-    Ok(mainret)
+    Ok(ret)
 }
 
 fn main() {
@@ -0,0 +1,145 @@
+use log::debug;
+use x11rb::connection::RequestConnection as _;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xinerama::{self, ConnectionExt as _};
+use x11rb::protocol::xproto;
+
+use crate::errors::Result;
+use crate::Connection;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Monitor {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    /// 0 if unknown
+    pub width_mm: u32,
+    pub height_mm: u32,
+    pub primary: bool,
+}
+
+impl Monitor {
+    pub fn from_screen(screen: &xproto::Screen) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels,
+            height: screen.height_in_pixels,
+            width_mm: screen.width_in_millimeters.into(),
+            height_mm: screen.height_in_millimeters.into(),
+            primary: true,
+        }
+    }
+
+    /// Pixels per millimeter if the physical size is known.
+    pub fn px_per_mm(&self) -> Option<f64> {
+        (self.width_mm > 0).then(|| f64::from(self.width) / f64::from(self.width_mm))
+    }
+
+    /// The position that centers a window of the size on the monitor together with its frame
+    /// `extents` (left, right, top, bottom), for the default north west gravity.
+    pub fn centered(&self, (width, height): (u16, u16), extents: [u32; 4]) -> (i32, i32) {
+        let [left, right, top, bottom] = extents.map(|e| i32::try_from(e).unwrap_or(0));
+        (
+            i32::from(self.x) + (i32::from(self.width) - i32::from(width) - left - right) / 2,
+            i32::from(self.y) + (i32::from(self.height) - i32::from(height) - top - bottom) / 2,
+        )
+    }
+}
+
+/// The monitors of the screen from RANDR, or from Xinerama on servers without RANDR 1.5, or
+/// else the whole screen as one.
+pub fn monitors(conn: &Connection, screen: &xproto::Screen) -> Result<Vec<Monitor>> {
+    if let Some(monitors) = randr_monitors(conn, screen.root)? {
+        return Ok(monitors);
+    }
+    if let Some(monitors) = xinerama_monitors(conn)? {
+        return Ok(monitors);
+    }
+    debug!("no monitor information, using the screen");
+    Ok(vec![Monitor::from_screen(screen)])
+}
+
+/// The primary monitor or the first one.
+pub fn primary(monitors: &[Monitor]) -> Option<&Monitor> {
+    monitors
+        .iter()
+        .find(|m| m.primary)
+        .or_else(|| monitors.first())
+}
+
+/// Pixels per millimeter of the primary monitor, or else of the screen, or else of 96 dpi.
+pub fn px_per_mm(monitors: &[Monitor], screen: &xproto::Screen) -> f64 {
+    primary(monitors)
+        .and_then(Monitor::px_per_mm)
+        .or_else(|| Monitor::from_screen(screen).px_per_mm())
+        .unwrap_or(96.0 / 25.4)
+}
+
+fn randr_monitors(conn: &Connection, root: xproto::Window) -> Result<Option<Vec<Monitor>>> {
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        return Ok(None);
+    }
+    let version = conn.randr_query_version(1, 5)?.reply()?;
+    if (version.major_version, version.minor_version) < (1, 5) {
+        debug!(
+            "randr {}.{} has no monitors",
+            version.major_version, version.minor_version
+        );
+        return Ok(None);
+    }
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+    if reply.monitors.is_empty() {
+        return Ok(None);
+    }
+    let monitors = reply
+        .monitors
+        .iter()
+        .map(|m| Monitor {
+            x: m.x,
+            y: m.y,
+            width: m.width,
+            height: m.height,
+            width_mm: m.width_in_millimeters,
+            height_mm: m.height_in_millimeters,
+            primary: m.primary,
+        })
+        .collect();
+    debug!("randr monitors: {:?}", monitors);
+    Ok(Some(monitors))
+}
+
+fn xinerama_monitors(conn: &Connection) -> Result<Option<Vec<Monitor>>> {
+    if conn
+        .extension_information(xinerama::X11_EXTENSION_NAME)?
+        .is_none()
+        || conn.xinerama_is_active()?.reply()?.state == 0
+    {
+        return Ok(None);
+    }
+    let reply = conn.xinerama_query_screens()?.reply()?;
+    if reply.screen_info.is_empty() {
+        return Ok(None);
+    }
+    let monitors = reply
+        .screen_info
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Monitor {
+            x: s.x_org,
+            y: s.y_org,
+            width: s.width,
+            height: s.height,
+            width_mm: 0,
+            height_mm: 0,
+            // Xinerama has no primary but it usually comes first
+            primary: i == 0,
+        })
+        .collect();
+    debug!("xinerama monitors: {:?}", monitors);
+    Ok(Some(monitors))
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::os::fd::AsRawFd as _;
+use std::time::Duration;
+
+use log::info;
+
+use crate::errors::{Context as _, Result};
+use crate::NAME;
+
+const LOCK: &str = "prompt.lock";
+const POLL: Duration = Duration::from_millis(100);
+
+/// Held while this process prompts. Released when dropped or when the process exits.
+pub struct Turn {
+    _file: File,
+}
+
+/// Waits until no other xaskpass of the user is prompting.
+pub async fn wait_turn() -> Result<Turn> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME).context("XDG directories")?;
+    let path = xdg_dirs
+        .place_runtime_file(LOCK)
+        .context("prompt lock directory")?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .context("prompt lock")?;
+    let mut waiting = false;
+    loop {
+        // Polled so that waiting can be cancelled like the prompt itself
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(Turn { _file: file });
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err).context("prompt lock")?;
+        }
+        if !waiting {
+            info!("waiting for another prompt to close");
+            waiting = true;
+        }
+        tokio::time::sleep(POLL).await;
+    }
+}
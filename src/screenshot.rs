@@ -0,0 +1,63 @@
+use std::io::Write as _;
+use std::path::Path;
+
+use log::debug;
+
+use crate::config;
+use crate::dialog::Dialog;
+use crate::errors::{Context as _, Result};
+
+// The returned context is only good for measuring the dialog.
+fn measure(
+    mut config: config::Dialog,
+    label: Option<&str>,
+    demo: bool,
+) -> Result<(Dialog, cairo::Context)> {
+    let surface =
+        cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).context("image surface")?;
+    let cr = cairo::Context::new(&surface).context("cairo context")?;
+    // Screenshots get shared, so keep the anti-phishing phrase and picture out of them
+    if let Some(ref mut phrase) = config.secret_phrase {
+        *phrase = "\u{2022}".repeat(phrase.chars().count());
+    }
+    // Like a 96 dpi screen
+    let mut dialog = Dialog::new(config, &cr, 96.0 / 25.4, label, None, None)?;
+    dialog.hide_secret_logo();
+    if demo {
+        dialog.fill_demo();
+    }
+    Ok((dialog, cr))
+}
+
+/// Renders the dialog to a PNG file without connecting to an X server.
+pub fn write_png(
+    config: config::Dialog,
+    label: Option<&str>,
+    demo: bool,
+    path: &Path,
+) -> Result<()> {
+    let (mut dialog, cr) = measure(config, label, demo)?;
+    let (width, height) = dialog.window_size(&cr);
+    debug!("screenshot width: {}, height: {}", width, height);
+
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width.into(), height.into())
+        .context("image surface")?;
+    let image_cr = cairo::Context::new(&surface).context("cairo context")?;
+    image_cr.set_matrix(cr.matrix());
+    dialog.cairo_context_changed(&image_cr);
+    dialog.init(&image_cr);
+    drop(image_cr);
+    surface.flush();
+
+    let mut file = std::fs::File::create(path).context("screenshot file")?;
+    surface.write_to_png(&mut file).context("write png")?;
+    Ok(())
+}
+
+/// Prints the computed geometry of the dialog as JSON without connecting to an X server.
+pub fn print_layout(config: config::Dialog, label: Option<&str>, demo: bool) -> Result<()> {
+    let (dialog, cr) = measure(config, label, demo)?;
+    let json = serde_json::to_string_pretty(&dialog.geometry(&cr)).context("json serialize")?;
+    writeln!(std::io::stdout(), "{json}").context("write layout")?;
+    Ok(())
+}
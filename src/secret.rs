@@ -1,6 +1,23 @@
+use std::fmt;
 use std::io::Write as _;
 use std::os::unix::io::FromRawFd as _;
 
+/// Wraps a value derived from the passphrase or from the keys used to type it so that it is
+/// never printed, whatever the log level or `--debug`.
+pub struct Sensitive<T>(pub T);
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
 pub struct Passphrase(pub(crate) SecBuf<char>);
 
 impl Passphrase {
@@ -11,6 +28,16 @@ impl Passphrase {
         // TODO any more guarantees that this is safe?
         let mut stdout = unsafe { std::fs::File::from_raw_fd(1) };
 
+        let buf = self.encode_utf8(true);
+        let ret = stdout.write_all(buf.unsecure());
+
+        // avoid closing stdout
+        std::mem::forget(stdout);
+
+        ret
+    }
+
+    pub fn encode_utf8(&self, newline: bool) -> SecBuf<u8> {
         // Keep the encoded values in secure buffer too
         // A buffer of length four is large enough to encode any char.
         // Add space for newline
@@ -20,24 +47,62 @@ impl Passphrase {
             buf.len += ret.len();
         }
 
-        buf.buf.unsecure_mut()[buf.len] = b'\n';
-        buf.len += 1;
-
-        let ret = stdout.write_all(buf.unsecure());
+        if newline {
+            buf.buf.unsecure_mut()[buf.len] = b'\n';
+            buf.len += 1;
+        }
+        buf
+    }
+}
 
-        // avoid closing stdout
-        std::mem::forget(stdout);
+/// A rough estimate of the bits of entropy from the length and the kinds of characters used.
+pub fn entropy_bits(chars: &[char]) -> f64 {
+    let has = |f: fn(&char) -> bool| chars.iter().any(f);
+    let mut pool = 0;
+    if has(char::is_ascii_lowercase) {
+        pool += 26;
+    }
+    if has(char::is_ascii_uppercase) {
+        pool += 26;
+    }
+    if has(char::is_ascii_digit) {
+        pool += 10;
+    }
+    if has(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if has(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+    f64::from(u32::try_from(chars.len()).unwrap()) * f64::from(pool).log2()
+}
 
-        ret
+impl SecBuf<char> {
+    /// Overwrites the whole buffer, not only what is in use, and empties it.
+    pub fn wipe(&mut self) {
+        self.buf.unsecure_mut().fill('\0');
+        self.len = 0;
     }
 }
 
-#[derive(Debug)]
 pub struct SecBuf<T: Copy + std::fmt::Debug> {
     pub(crate) buf: secstr::SecVec<T>,
     pub(crate) len: usize,
 }
 
+// The length of the passphrase is a secret too
+impl<T: Copy + std::fmt::Debug> fmt::Debug for SecBuf<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecBuf")
+            .field("buf", &self.buf)
+            .field("len", &Sensitive(self.len))
+            .finish()
+    }
+}
+
 impl<T: Copy + std::fmt::Debug> SecBuf<T> {
     pub fn new(buf: Vec<T>) -> Self {
         Self {
@@ -89,6 +154,8 @@ impl<T: Copy + std::fmt::Debug> SecBuf<T> {
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
     #[test]
@@ -100,4 +167,72 @@ mod tests {
         buf.len = 2;
         assert_eq!(buf.unsecure(), ['a', 'X']);
     }
+
+    #[test]
+    fn redacted() {
+        let mut buf = SecBuf::new(vec!['X'; 20]);
+        buf.push('s');
+        assert_eq!(
+            format!("{buf:?}"),
+            "SecBuf { buf: ***SECRET***, len: <redacted> }"
+        );
+        assert_eq!(format!("{:?}", Sensitive("secret")), "<redacted>");
+        assert_eq!(format!("{}", Sensitive('s')), "<redacted>");
+    }
+
+    #[test]
+    fn entropy() {
+        assert!(entropy_bits(&[]) == 0.0);
+        let weak: Vec<char> = "password".chars().collect();
+        let strong: Vec<char> = "correct horse battery staple".chars().collect();
+        assert!(entropy_bits(&weak) < 40.0);
+        assert!(entropy_bits(&strong) > 100.0);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Insert(String),
+        Backspace(usize),
+        Move(usize),
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            ".{0,8}".prop_map(Edit::Insert),
+            (0usize..8).prop_map(Edit::Backspace),
+            any::<usize>().prop_map(Edit::Move),
+        ]
+    }
+
+    proptest! {
+        // Edits the buffer the way the Strings indicator does and compares it to a Vec.
+        #[test]
+        fn edits(capacity in 0usize..24, edits in proptest::collection::vec(edit(), 0..48)) {
+            let mut buf = SecBuf::new(vec!['X'; capacity]);
+            let mut model: Vec<char> = Vec::new();
+            let mut cursor = 0;
+            for edit in edits {
+                match edit {
+                    Edit::Insert(s) => {
+                        let inserted = buf.insert_many(cursor, s.chars(), s.chars().count());
+                        let fits = std::cmp::min(capacity - model.len(), s.chars().count());
+                        prop_assert_eq!(inserted, fits);
+                        model.splice(cursor..cursor, s.chars().take(fits));
+                        cursor += inserted;
+                    }
+                    Edit::Backspace(n) => {
+                        let n = std::cmp::min(n, cursor);
+                        if n > 0 {
+                            buf.delete(cursor - n, cursor - 1);
+                            model.drain(cursor - n..cursor);
+                            cursor -= n;
+                        }
+                    }
+                    Edit::Move(to) => cursor = to % (model.len() + 1),
+                }
+                prop_assert!(buf.len <= capacity);
+                prop_assert_eq!(buf.unsecure(), &model[..]);
+            }
+        }
+    }
 }
@@ -0,0 +1 @@
+#include <X11/SM/SMlib.h>
@@ -0,0 +1,228 @@
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_ulong};
+
+use log::{debug, trace, warn};
+use tokio::io::unix::AsyncFd;
+
+use crate::errors::{Context as _, Result};
+
+mod ffi;
+
+#[allow(clippy::cast_possible_wrap)]
+const PROTO_MAJOR: c_int = ffi::SmProtoMajor as c_int;
+#[allow(clippy::cast_possible_wrap)]
+const PROTO_MINOR: c_int = ffi::SmProtoMinor as c_int;
+
+/// Registration with the X session manager so that the prompt is cancelled when the session
+/// ends instead of the X connection just going away.
+pub struct Session {
+    // Deregistered before the connection is closed
+    fd: Option<AsyncFd<c_int>>,
+    smc: ffi::SmcConn,
+    ice: ffi::IceConn,
+    // Set by the die callback
+    died: Box<Cell<bool>>,
+}
+
+impl Session {
+    /// Connects to the session manager in `SESSION_MANAGER` if there is one.
+    pub fn open() -> Result<Option<Self>> {
+        if std::env::var_os("SESSION_MANAGER").is_none() {
+            debug!("no session manager");
+            return Ok(None);
+        }
+        unsafe {
+            // The defaults exit the process
+            ffi::IceSetIOErrorHandler(Some(ice_io_error));
+            ffi::SmcSetErrorHandler(Some(smc_error));
+        }
+
+        let died = Box::new(Cell::new(false));
+        let client_data = std::ptr::from_ref::<Cell<bool>>(&died).cast_mut().cast();
+        let mut callbacks = ffi::SmcCallbacks {
+            save_yourself: ffi::SmcCallbacks__bindgen_ty_1 {
+                callback: Some(save_yourself),
+                client_data,
+            },
+            die: ffi::SmcCallbacks__bindgen_ty_2 {
+                callback: Some(die),
+                client_data,
+            },
+            save_complete: ffi::SmcCallbacks__bindgen_ty_3 {
+                callback: Some(ignored),
+                client_data,
+            },
+            shutdown_cancelled: ffi::SmcCallbacks__bindgen_ty_4 {
+                callback: Some(ignored),
+                client_data,
+            },
+        };
+        let mut client_id: *mut c_char = std::ptr::null_mut();
+        let mut error = [0 as c_char; 256];
+        let smc = unsafe {
+            ffi::SmcOpenConnection(
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                PROTO_MAJOR,
+                PROTO_MINOR,
+                c_ulong::from(
+                    ffi::SmcSaveYourselfProcMask
+                        | ffi::SmcDieProcMask
+                        | ffi::SmcSaveCompleteProcMask
+                        | ffi::SmcShutdownCancelledProcMask,
+                ),
+                std::ptr::from_mut(&mut callbacks),
+                std::ptr::null(),
+                std::ptr::from_mut(&mut client_id),
+                c_int::try_from(error.len()).unwrap(),
+                error.as_mut_ptr(),
+            )
+        };
+        if smc.is_null() {
+            let error = unsafe { CStr::from_ptr(error.as_ptr()) };
+            crate::bail!("session manager: {}", error.to_string_lossy());
+        }
+        if !client_id.is_null() {
+            debug!("session client id {}", unsafe {
+                CStr::from_ptr(client_id).to_string_lossy()
+            });
+            unsafe { libc::free(client_id.cast()) };
+        }
+
+        let ice = unsafe { ffi::SmcGetIceConnection(smc) };
+        let mut session = Self {
+            fd: None,
+            smc,
+            ice,
+            died,
+        };
+        session.set_properties();
+        let fd = unsafe { ffi::IceConnectionNumber(ice) };
+        session.fd = Some(AsyncFd::new(fd).context("session manager fd")?);
+        Ok(Some(session))
+    }
+
+    // The properties the protocol requires, asking never to be restarted.
+    fn set_properties(&self) {
+        let program = std::env::args()
+            .next()
+            .unwrap_or_else(|| crate::NAME.into());
+        let program = CString::new(program).unwrap_or_else(|_| CString::new(crate::NAME).unwrap());
+        let user = CString::new(unsafe { libc::getuid() }.to_string()).unwrap();
+        let mut restart_never = ffi::SmRestartNever as u8;
+
+        let value = |s: &CString| ffi::SmPropValue {
+            length: c_int::try_from(s.as_bytes().len()).unwrap_or(c_int::MAX),
+            value: s.as_ptr().cast_mut().cast(),
+        };
+        let mut restart_style = [ffi::SmPropValue {
+            length: 1,
+            value: std::ptr::from_mut(&mut restart_never).cast(),
+        }];
+        let mut program_value = [value(&program)];
+        let mut user_value = [value(&user)];
+        let mut clone_value = [value(&program)];
+        let mut restart_value = [value(&program)];
+        let prop = |name: &[u8], type_: &[u8], vals: &mut [ffi::SmPropValue]| ffi::SmProp {
+            name: name.as_ptr().cast_mut().cast(),
+            type_: type_.as_ptr().cast_mut().cast(),
+            num_vals: c_int::try_from(vals.len()).unwrap(),
+            vals: vals.as_mut_ptr(),
+        };
+        let mut props = [
+            prop(ffi::SmRestartStyleHint, ffi::SmCARD8, &mut restart_style),
+            prop(ffi::SmProgram, ffi::SmARRAY8, &mut program_value),
+            prop(ffi::SmUserID, ffi::SmARRAY8, &mut user_value),
+            prop(ffi::SmCloneCommand, ffi::SmLISTofARRAY8, &mut clone_value),
+            prop(
+                ffi::SmRestartCommand,
+                ffi::SmLISTofARRAY8,
+                &mut restart_value,
+            ),
+        ];
+        let mut props: Vec<*mut ffi::SmProp> = props.iter_mut().map(std::ptr::from_mut).collect();
+        let len = c_int::try_from(props.len()).unwrap();
+        unsafe { ffi::SmcSetProperties(self.smc, len, props.as_mut_ptr()) };
+    }
+
+    /// Answers the session manager until it tells the session is ending.
+    pub async fn died(&mut self) -> Result<()> {
+        let fd = self.fd.as_ref().expect("session manager fd");
+        loop {
+            let mut guard = fd.readable().await.context("session manager poll")?;
+            // Messages are read one at a time
+            loop {
+                trace!("processing session manager messages");
+                let status = unsafe {
+                    ffi::IceProcessMessages(self.ice, std::ptr::null_mut(), std::ptr::null_mut())
+                };
+                if status != ffi::IceProcessMessagesStatus::IceProcessMessagesSuccess {
+                    crate::bail!("session manager connection lost");
+                }
+                if self.died.get() {
+                    return Ok(());
+                }
+                if !readable_now(*fd.get_ref()) {
+                    guard.clear_ready();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.fd = None;
+        unsafe { ffi::SmcCloseConnection(self.smc, 0, std::ptr::null_mut()) };
+    }
+}
+
+fn readable_now(fd: c_int) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(std::ptr::from_mut(&mut pollfd), 1, 0) > 0 }
+}
+
+unsafe extern "C" fn save_yourself(
+    smc: ffi::SmcConn,
+    _client_data: ffi::SmPointer,
+    _save_type: c_int,
+    shutdown: c_int,
+    _interact_style: c_int,
+    _fast: c_int,
+) {
+    debug!("save yourself, shutdown: {}", shutdown != 0);
+    // Nothing to save
+    ffi::SmcSaveYourselfDone(smc, 1);
+}
+
+unsafe extern "C" fn die(_smc: ffi::SmcConn, client_data: ffi::SmPointer) {
+    debug!("the session manager says die");
+    (*client_data.cast::<Cell<bool>>()).set(true);
+}
+
+unsafe extern "C" fn ignored(_smc: ffi::SmcConn, _client_data: ffi::SmPointer) {}
+
+unsafe extern "C" fn ice_io_error(_ice: ffi::IceConn) {
+    warn!("session manager connection error");
+}
+
+unsafe extern "C" fn smc_error(
+    _smc: ffi::SmcConn,
+    _swap: c_int,
+    opcode: c_int,
+    _sequence: c_ulong,
+    class: c_int,
+    severity: c_int,
+    _values: ffi::SmPointer,
+) {
+    warn!(
+        "session manager error: opcode {}, class {}, severity {}",
+        opcode, class, severity
+    );
+}
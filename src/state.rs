@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::dialog::layout::Layout;
+use crate::errors::{Context as _, Result};
+use crate::NAME;
+
+const WINDOW_SIZES: &str = "window-sizes.toml";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Size {
+    width: u16,
+    height: u16,
+}
+
+/// Window sizes the user has resized the dialog to, by layout. Kept in the XDG state
+/// directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WindowSizes(BTreeMap<String, Size>);
+
+impl WindowSizes {
+    pub fn load() -> Result<Self> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME).context("XDG directories")?;
+        let Some(path) = xdg_dirs.find_state_file(WINDOW_SIZES) else {
+            return Ok(Self::default());
+        };
+        debug!("loading window sizes from {}", path.display());
+        let data = std::fs::read_to_string(&path).context("window sizes")?;
+        Ok(toml::from_str(&data).context("window sizes toml")?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME).context("XDG directories")?;
+        let path = xdg_dirs
+            .place_state_file(WINDOW_SIZES)
+            .context("window sizes directory")?;
+        debug!("saving window sizes to {}", path.display());
+        let data = toml::to_string(self).context("window sizes toml")?;
+        std::fs::write(&path, data).context("window sizes")?;
+        Ok(())
+    }
+
+    pub fn get(&self, layout: Layout) -> Option<(u16, u16)> {
+        self.0
+            .get(&format!("{layout:?}"))
+            .map(|size| (size.width, size.height))
+    }
+
+    pub fn set(&mut self, layout: Layout, (width, height): (u16, u16)) {
+        self.0.insert(format!("{layout:?}"), Size { width, height });
+    }
+}
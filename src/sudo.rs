@@ -0,0 +1,232 @@
+use std::ffi::CStr;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use log::debug;
+
+/// Who is asking for what when we are run as `SUDO_ASKPASS`.
+#[derive(Debug)]
+pub struct SudoInfo {
+    pub user: Option<String>,
+    pub target_user: Option<String>,
+    pub tty: Option<PathBuf>,
+}
+
+impl SudoInfo {
+    /// Returns `None` unless the parent is sudo or `label` is sudo's prompt. With the prompt
+    /// sudo may also be further up, e.g. when `SUDO_ASKPASS` is a script.
+    pub fn detect(label: Option<&str>) -> Option<Self> {
+        let prompt_user = label.and_then(prompt_user);
+        let mut pid = i32::try_from(std::os::unix::process::parent_id()).ok()?;
+        let levels = if prompt_user.is_some() { 4 } else { 1 };
+        let mut sudo = None;
+        for _ in 0..levels {
+            let Ok(process) = procfs::process::Process::new(pid) else {
+                break;
+            };
+            let (Ok(status), Ok(stat)) = (process.status(), process.stat()) else {
+                break;
+            };
+            if status.name == "sudo" {
+                sudo = Some((process, status, stat));
+                break;
+            }
+            pid = stat.ppid;
+        }
+        let me = match sudo {
+            Some((process, status, stat)) => {
+                let cmdline = process.cmdline().unwrap_or_default();
+                Self {
+                    user: user_name(status.ruid).or_else(|| prompt_user.map(Into::into)),
+                    target_user: Some(
+                        target_user(cmdline.get(1..).unwrap_or_default())
+                            .unwrap_or_else(|| "root".into()),
+                    ),
+                    tty: tty_path(stat.tty_nr()),
+                }
+            }
+            None => Self {
+                user: Some(prompt_user?.into()),
+                target_user: None,
+                tty: procfs::process::Process::myself()
+                    .and_then(|me| me.stat())
+                    .ok()
+                    .and_then(|stat| tty_path(stat.tty_nr())),
+            },
+        };
+        debug!("sudo: {:?}", me);
+        Some(me)
+    }
+
+    pub fn header(&self) -> String {
+        let mut header = format!(
+            "sudo: {} → {}",
+            self.user.as_deref().unwrap_or("?"),
+            self.target_user.as_deref().unwrap_or("?")
+        );
+        if let Some(ref tty) = self.tty {
+            let _ = write!(header, " on {}", tty.display());
+        }
+        header
+    }
+}
+
+// The user in sudo's default prompt, "[sudo] password for %p: "
+fn prompt_user(label: &str) -> Option<&str> {
+    label
+        .strip_prefix("[sudo] password for ")?
+        .trim_end()
+        .strip_suffix(':')
+        .filter(|user| !user.is_empty())
+}
+
+// From the device number, as the fds of sudo are not readable by the user
+fn tty_path((major, minor): (i32, i32)) -> Option<PathBuf> {
+    let path = match major {
+        // Unix98 pseudo-terminals
+        136..=143 => format!("/dev/pts/{}", (major - 136) * 256 + minor),
+        4 if minor < 64 => format!("/dev/tty{minor}"),
+        4 => format!("/dev/ttyS{}", minor - 64),
+        _ => return None,
+    };
+    Some(PathBuf::from(path))
+}
+
+pub(crate) fn user_name(uid: u32) -> Option<String> {
+    // Not thread safe but nothing else calls getpw*
+    let pw = unsafe { libc::getpwuid(uid) };
+    if pw.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr((*pw).pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+// sudo options that take an argument
+const SHORT_WITH_ARG: &str = "CDghprtTUu";
+const LONG_WITH_ARG: [&str; 11] = [
+    "chdir",
+    "chroot",
+    "close-from",
+    "command-timeout",
+    "group",
+    "host",
+    "other-user",
+    "prompt",
+    "role",
+    "type",
+    "user",
+];
+
+fn target_user(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" || !arg.starts_with('-') {
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+            if LONG_WITH_ARG.contains(&name) {
+                let value = value.or_else(|| args.next().cloned());
+                if name == "user" {
+                    return value;
+                }
+            }
+            continue;
+        }
+        for (i, c) in arg.char_indices().skip(1) {
+            if SHORT_WITH_ARG.contains(c) {
+                let rest = &arg[i + c.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next().cloned()
+                } else {
+                    Some(rest.to_owned())
+                };
+                if c == 'u' {
+                    return value;
+                }
+                break;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.into()).collect()
+    }
+
+    #[test]
+    fn default_prompt() {
+        assert_eq!(prompt_user("[sudo] password for alice: "), Some("alice"));
+        assert_eq!(prompt_user("[sudo] password for alice:"), Some("alice"));
+        assert_eq!(prompt_user("[sudo] password for : "), None);
+    }
+
+    #[test]
+    fn custom_prompt() {
+        // sudo -p
+        assert_eq!(prompt_user("Password for alice on host: "), None);
+        assert_eq!(prompt_user("[sudo] password for alice"), None);
+        // Not run by sudo in the tests
+        assert!(SudoInfo::detect(Some("Password please: ")).is_none());
+        assert!(SudoInfo::detect(None).is_none());
+    }
+
+    #[test]
+    fn detect_from_prompt() {
+        let sudo = SudoInfo::detect(Some("[sudo] password for alice: ")).unwrap();
+        assert_eq!(sudo.user.as_deref(), Some("alice"));
+        assert_eq!(sudo.target_user, None);
+    }
+
+    #[test]
+    fn tty() {
+        assert_eq!(tty_path((136, 3)), Some("/dev/pts/3".into()));
+        assert_eq!(tty_path((137, 1)), Some("/dev/pts/257".into()));
+        assert_eq!(tty_path((4, 1)), Some("/dev/tty1".into()));
+        assert_eq!(tty_path((4, 65)), Some("/dev/ttyS1".into()));
+        // No controlling terminal
+        assert_eq!(tty_path((0, 0)), None);
+    }
+
+    #[test]
+    fn header_without_tty() {
+        let sudo = SudoInfo {
+            user: Some("alice".into()),
+            target_user: None,
+            tty: None,
+        };
+        assert_eq!(sudo.header(), "sudo: alice → ?");
+        let sudo = SudoInfo {
+            tty: Some("/dev/pts/3".into()),
+            ..sudo
+        };
+        assert_eq!(sudo.header(), "sudo: alice → ? on /dev/pts/3");
+    }
+
+    #[test]
+    fn target() {
+        assert_eq!(
+            target_user(&args(&["-u", "bob", "ls"])).as_deref(),
+            Some("bob")
+        );
+        assert_eq!(target_user(&args(&["-ubob", "ls"])).as_deref(), Some("bob"));
+        assert_eq!(target_user(&args(&["-Hu", "bob"])).as_deref(), Some("bob"));
+        assert_eq!(target_user(&args(&["--user=bob"])).as_deref(), Some("bob"));
+        assert_eq!(
+            target_user(&args(&["--user", "bob"])).as_deref(),
+            Some("bob")
+        );
+        assert_eq!(target_user(&args(&["-p", "-u", "ls"])), None);
+        assert_eq!(target_user(&args(&["--", "-u", "bob"])), None);
+        assert_eq!(target_user(&args(&["ls", "-u", "bob"])), None);
+    }
+}
@@ -0,0 +1 @@
+#include <librsvg/rsvg.h>
@@ -0,0 +1,74 @@
+use std::ffi::CString;
+use std::ptr::null_mut;
+
+use pango::glib;
+use pango::glib::translate::from_glib_full;
+
+use crate::errors::{Context as _, Result};
+
+mod ffi;
+
+/// An SVG file drawn with librsvg at whatever size is needed.
+pub struct Image {
+    handle: *mut ffi::RsvgHandle,
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image").finish_non_exhaustive()
+    }
+}
+
+impl Image {
+    pub fn load(path: &str) -> Result<Self> {
+        let path = CString::new(path).context("svg path")?;
+        let mut error = null_mut();
+        let handle = unsafe {
+            ffi::rsvg_handle_new_from_file(path.as_ptr(), std::ptr::from_mut(&mut error))
+        };
+        if handle.is_null() {
+            return Err(glib_error(error)).context("svg load")?;
+        }
+        Ok(Self { handle })
+    }
+
+    /// Draws the image centered in the rectangle and scaled to fit it.
+    pub fn render(
+        &self,
+        cr: &cairo::Context,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<()> {
+        let viewport = ffi::RsvgRectangle {
+            x,
+            y,
+            width,
+            height,
+        };
+        let mut error = null_mut();
+        let ok = unsafe {
+            ffi::rsvg_handle_render_document(
+                self.handle,
+                cr.to_raw_none().cast(),
+                std::ptr::from_ref(&viewport),
+                std::ptr::from_mut(&mut error),
+            )
+        };
+        if ok == 0 {
+            return Err(glib_error(error)).context("svg render")?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { glib::gobject_ffi::g_object_unref(self.handle.cast()) };
+    }
+}
+
+fn glib_error(error: *mut ffi::GError) -> glib::Error {
+    unsafe { from_glib_full(error.cast::<glib::ffi::GError>()) }
+}
@@ -0,0 +1,93 @@
+use std::ffi::OsString;
+
+/// The title used when none is configured.
+pub const DEFAULT_TITLE: &str = "{cmd}[{pid}]@{hostname}";
+
+/// Values for the placeholders in the configured title and label.
+#[derive(Debug, Clone)]
+pub struct Vars {
+    pub hostname: String,
+    pub user: String,
+    /// Pid of the process that started us
+    pub pid: u32,
+    /// Name of the process that started us
+    pub cmd: String,
+}
+
+impl Vars {
+    pub fn from_env() -> Self {
+        let pid = std::os::unix::process::parent_id();
+        let cmd = i32::try_from(pid)
+            .ok()
+            .and_then(|pid| procfs::process::Process::new(pid).ok())
+            .and_then(|p| p.status().ok())
+            .map(|s| s.name)
+            .unwrap_or_default();
+        Self {
+            hostname: hostname().to_string_lossy().into_owned(),
+            user: crate::sudo::user_name(unsafe { libc::getuid() }).unwrap_or_default(),
+            pid,
+            cmd,
+        }
+    }
+
+    /// Replaces `{hostname}`, `{user}`, `{pid}` and `{cmd}`. `{{` and `}}` are literal braces
+    /// and unknown placeholders are left as they are.
+    pub fn expand(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(i) = rest.find(['{', '}']) {
+            out.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                out.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            let value = rest.find('}').filter(|&end| end > 0).and_then(|end| {
+                let value = match &rest[1..end] {
+                    "hostname" => self.hostname.clone(),
+                    "user" => self.user.clone(),
+                    "pid" => self.pid.to_string(),
+                    "cmd" => self.cmd.clone(),
+                    _ => return None,
+                };
+                Some((value, end))
+            });
+            if let Some((value, end)) = value {
+                out.push_str(&value);
+                rest = &rest[end + 1..];
+            } else {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+pub fn hostname() -> OsString {
+    std::env::var_os("HOSTNAME").unwrap_or_else(gethostname::gethostname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand() {
+        let vars = Vars {
+            hostname: "host".into(),
+            user: "me".into(),
+            pid: 42,
+            cmd: "ssh".into(),
+        };
+        assert_eq!(vars.expand(DEFAULT_TITLE), "ssh[42]@host");
+        assert_eq!(
+            vars.expand("{user} {{user}} {nope} {"),
+            "me {user} {nope} {"
+        );
+        assert_eq!(vars.expand("}}{cmd}}"), "}ssh}");
+    }
+}
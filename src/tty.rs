@@ -0,0 +1,347 @@
+use std::fs::File;
+use std::io::{Read as _, Write as _};
+use std::os::unix::io::{AsRawFd as _, FromRawFd as _, OwnedFd};
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::config::{BasicAuthOutput, TimeoutAction};
+use crate::dialog::Mode;
+use crate::envelope::Method;
+use crate::errors::{Context as _, Result};
+use crate::event::Outcome;
+use crate::secret::{Passphrase, SecBuf};
+
+/// Set to `1` to read the passphrase from the terminal when there is no usable display.
+pub const FALLBACK_ENV: &str = "XASKPASS_FALLBACK_TTY";
+
+pub fn fallback_enabled() -> bool {
+    std::env::var_os(FALLBACK_ENV).is_some_and(|v| v == "1")
+}
+
+enum Line {
+    Done(SecBuf<u8>),
+    Eof,
+    TimedOut(SecBuf<u8>),
+    Cancelled,
+}
+
+enum Wait {
+    Input,
+    TimedOut,
+    Cancelled,
+}
+
+/// Ends the read of its `Prompt` when dropped, so that a blocked read does not keep the
+/// process alive after a signal or a deadline.
+pub struct Cancel(#[allow(dead_code)] OwnedFd);
+
+/// A line prompt on the controlling terminal that edits the input itself so that nothing
+/// typed reaches the terminal's own line buffer.
+pub struct Prompt {
+    tty: File,
+    saved: libc::termios,
+    // The inactivity timeout, restarted by every key
+    timeout: Option<Duration>,
+    // Hangs up once the `Cancel` is dropped
+    cancelled: OwnedFd,
+}
+
+impl Prompt {
+    pub fn open(timeout: Option<Duration>) -> Result<(Self, Cancel)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            Err(std::io::Error::last_os_error()).context("pipe")?;
+        }
+        let (cancelled, cancel) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context("no display and no controlling terminal")?;
+        let fd = tty.as_raw_fd();
+        let mut saved = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, std::ptr::from_mut(&mut saved)) } != 0 {
+            Err(std::io::Error::last_os_error()).context("tcgetattr")?;
+        }
+        let mut termios = saved;
+        // Keep ISIG so that Ctrl+C still interrupts, which drops the `Cancel`
+        termios.c_lflag &= !(libc::ECHO | libc::ICANON);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, std::ptr::from_ref(&termios)) } != 0 {
+            Err(std::io::Error::last_os_error()).context("tcsetattr")?;
+        }
+        Ok((
+            Self {
+                tty,
+                saved,
+                timeout,
+                cancelled,
+            },
+            Cancel(cancel),
+        ))
+    }
+
+    /// Asks as the dialog would in `mode`.
+    pub fn run(
+        &mut self,
+        label: &str,
+        mode: Mode,
+        timeout_action: TimeoutAction,
+    ) -> Result<Outcome> {
+        debug!("prompting on the terminal, mode: {:?}", mode);
+        let outcome = match mode {
+            Mode::Secret => {
+                self.write(&format!("{label} "))?;
+                match self.read_line(false)? {
+                    Line::Done(line) => Outcome::Ok(decode(&line)?, Method::Typed, None),
+                    Line::Eof | Line::Cancelled => Outcome::Cancelled,
+                    Line::TimedOut(line) => Outcome::TimedOut(match timeout_action {
+                        TimeoutAction::Cancel => None,
+                        TimeoutAction::Accept => Some((decode(&line)?, Method::Typed, None)),
+                        TimeoutAction::AcceptEmpty => {
+                            Some((decode(&SecBuf::new(Vec::new()))?, Method::Typed, None))
+                        }
+                    }),
+                }
+            }
+            Mode::Confirm => {
+                self.write(&format!("{label} [y/N] "))?;
+                match self.read_line(true)? {
+                    Line::Done(line) if matches!(line.unsecure().first(), Some(b'y' | b'Y')) => {
+                        let mut yes = SecBuf::new(vec![0; 3]);
+                        yes.insert_many(0, *b"yes", 3);
+                        Outcome::Ok(decode(&yes)?, Method::Typed, None)
+                    }
+                    Line::Done(_) | Line::Eof | Line::Cancelled => Outcome::Cancelled,
+                    // Never confirm on behalf of the user
+                    Line::TimedOut(_) => Outcome::TimedOut(None),
+                }
+            }
+            Mode::Touch => {
+                // The caller kills us once it is done waiting
+                self.write(&format!("{label}\n"))?;
+                match self.read_line(false)? {
+                    Line::Done(_) | Line::Eof | Line::Cancelled => Outcome::Cancelled,
+                    Line::TimedOut(_) => Outcome::TimedOut(None),
+                }
+            }
+            Mode::BasicAuth(output) => {
+                self.write("Username: ")?;
+                let username = match self.read_line(true)? {
+                    Line::Done(line) => line,
+                    Line::Eof | Line::Cancelled => return Ok(Outcome::Cancelled),
+                    Line::TimedOut(_) => return Ok(Outcome::TimedOut(None)),
+                };
+                self.write(&format!("{label} "))?;
+                match self.read_line(false)? {
+                    Line::Done(password) => {
+                        let separator = match output {
+                            BasicAuthOutput::Colon => b':',
+                            BasicAuthOutput::Lines => b'\n',
+                        };
+                        let len = username.len + 1 + password.len;
+                        let mut buf = SecBuf::new(vec![0; len]);
+                        let bytes = username
+                            .unsecure()
+                            .iter()
+                            .chain(std::iter::once(&separator))
+                            .chain(password.unsecure())
+                            .copied();
+                        buf.insert_many(0, bytes, len);
+                        Outcome::Ok(decode(&buf)?, Method::Typed, None)
+                    }
+                    Line::Eof | Line::Cancelled => Outcome::Cancelled,
+                    Line::TimedOut(_) => Outcome::TimedOut(None),
+                }
+            }
+        };
+        Ok(outcome)
+    }
+
+    fn write(&self, s: &str) -> Result<()> {
+        (&self.tty).write_all(s.as_bytes()).context("tty write")?;
+        Ok(())
+    }
+
+    fn wait_input(&self) -> Result<Wait> {
+        let mut pollfds = [
+            libc::pollfd {
+                fd: self.tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.cancelled.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        let ms = self.timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+        loop {
+            let n = unsafe { libc::poll(pollfds.as_mut_ptr(), 2, ms) };
+            if n == 0 {
+                return Ok(Wait::TimedOut);
+            }
+            if n > 0 {
+                return Ok(if pollfds[1].revents == 0 {
+                    Wait::Input
+                } else {
+                    Wait::Cancelled
+                });
+            }
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                Err(err).context("tty poll")?;
+            }
+        }
+    }
+
+    fn read_line(&mut self, echo: bool) -> Result<Line> {
+        let erase = self.saved.c_cc[libc::VERASE];
+        let kill = self.saved.c_cc[libc::VKILL];
+        let eof = self.saved.c_cc[libc::VEOF];
+
+        let mut line: SecBuf<u8> = SecBuf::new(vec![0; 2048]);
+        let mut byte = [0];
+        loop {
+            match self.wait_input()? {
+                Wait::Input => {}
+                Wait::TimedOut => {
+                    self.write("\n")?;
+                    info!("input timeout");
+                    return Ok(Line::TimedOut(line));
+                }
+                Wait::Cancelled => {
+                    self.write("\n")?;
+                    return Ok(Line::Cancelled);
+                }
+            }
+            if (&self.tty).read(&mut byte).context("tty read")? == 0 {
+                self.write("\n")?;
+                return Ok(Line::Eof);
+            }
+            match byte[0] {
+                b'\n' | b'\r' => {
+                    self.write("\n")?;
+                    return Ok(Line::Done(line));
+                }
+                c if c == eof && line.len == 0 => {
+                    self.write("\n")?;
+                    return Ok(Line::Eof);
+                }
+                c if c == erase || c == 0x7f || c == 0x08 => {
+                    if pop_char(&mut line) && echo {
+                        self.write("\x08 \x08")?;
+                    }
+                }
+                c if c == kill => {
+                    while pop_char(&mut line) {
+                        if echo {
+                            self.write("\x08 \x08")?;
+                        }
+                    }
+                }
+                // Other control characters, including the escape sequences of the arrow keys
+                c if c < 0x20 => {}
+                c => {
+                    if line.push(c) && echo {
+                        (&self.tty).write_all(&byte).context("tty write")?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Prompt {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(
+                self.tty.as_raw_fd(),
+                libc::TCSAFLUSH,
+                std::ptr::from_ref(&self.saved),
+            )
+        };
+    }
+}
+
+/// Removes the last UTF-8 encoded character.
+fn pop_char(line: &mut SecBuf<u8>) -> bool {
+    let Some(start) = line
+        .unsecure()
+        .iter()
+        .rposition(|&b| b & 0b1100_0000 != 0b1000_0000)
+    else {
+        return false;
+    };
+    line.delete(start, line.len - 1);
+    true
+}
+
+fn decode(line: &SecBuf<u8>) -> Result<Passphrase> {
+    let s = std::str::from_utf8(line.unsecure()).context("the input is not UTF-8")?;
+    let len = s.chars().count();
+    let mut pass = SecBuf::new(vec!['\0'; len]);
+    pass.insert_many(0, s.chars(), len);
+    Ok(Passphrase(pass))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(s: &[u8]) -> SecBuf<u8> {
+        let mut line = SecBuf::new(vec![0; 64]);
+        for &b in s {
+            assert!(line.push(b));
+        }
+        line
+    }
+
+    #[test]
+    fn pop_utf8() {
+        let mut line = line("aé€😀".as_bytes());
+        assert!(pop_char(&mut line));
+        assert_eq!(line.unsecure(), "aé€".as_bytes());
+        assert!(pop_char(&mut line));
+        assert_eq!(line.unsecure(), "aé".as_bytes());
+        assert!(pop_char(&mut line));
+        assert_eq!(line.unsecure(), b"a");
+        assert!(pop_char(&mut line));
+        assert!(line.unsecure().is_empty());
+        assert!(!pop_char(&mut line));
+    }
+
+    #[test]
+    fn pop_partial() {
+        // A continuation byte with no start byte before it
+        let mut line = line(&[0x80, 0x80]);
+        assert!(!pop_char(&mut line));
+        assert_eq!(line.len, 2);
+    }
+
+    #[test]
+    fn decode_utf8() {
+        let pass = decode(&line("pässwörd €".as_bytes())).unwrap();
+        assert_eq!(pass.0.unsecure(), "pässwörd €".chars().collect::<Vec<_>>());
+        assert!(decode(&line(b"")).unwrap().0.unsecure().is_empty());
+        assert!(decode(&line(&[b'a', 0xff])).is_err());
+        // Cut in the middle of a character
+        assert!(decode(&line(&"é".as_bytes()[..1])).is_err());
+    }
+
+    #[test]
+    fn fallback_env() {
+        std::env::set_var(FALLBACK_ENV, "0");
+        assert!(!fallback_enabled());
+        std::env::set_var(FALLBACK_ENV, "1");
+        assert!(fallback_enabled());
+        std::env::remove_var(FALLBACK_ENV);
+        assert!(!fallback_enabled());
+    }
+}
@@ -0,0 +1,307 @@
+//! x11-ssh-askpass style X resources, used below the config file or imported to one.
+
+use log::warn;
+use serde::Deserialize as _;
+use toml::{Table, Value};
+use x11rb::resource_manager::Database;
+
+use crate::config::{Config, Rgba};
+use crate::errors::{Context as _, Result};
+use crate::CLASS;
+
+/// Dialog settings given for the instance `name` or the `SshAskpass` class, e.g.
+/// `SshAskpass*background: #2b2e37`. Entries matching every program, like `*background`, are
+/// left out.
+pub fn fallback(db: &Database, name: &str) -> Table {
+    let lookup = |resource: &str, class: &str| {
+        let value = db.get_string(&format!("{name}.{resource}"), &format!("{CLASS}.{class}"))?;
+        let any_program = db.get_string(&format!("_.{resource}"), &format!("_.{class}"));
+        (any_program != Some(value)).then(|| value.trim().to_owned())
+    };
+
+    let mut dialog = Table::new();
+    for key in ["background", "foreground"] {
+        let class = format!("Dialog.{}{}", key[..1].to_uppercase(), &key[1..]);
+        if let Some(color) = lookup(&format!("dialog.{key}"), &class) {
+            match color.parse::<Rgba>() {
+                Ok(_) => {
+                    dialog.insert(key.into(), Value::String(color));
+                }
+                Err(err) => warn!("X resource {}: {}", key, err),
+            }
+        }
+    }
+    if let Some(font) = lookup("dialog.font", "Dialog.Font") {
+        let pango = pango_font(&font);
+        if font.starts_with('-') && pango == font {
+            warn!("X resource font: not an XLFD name '{}'", font);
+        } else {
+            dialog.insert("font".into(), Value::String(pango));
+        }
+    }
+
+    let mut table = Table::new();
+    if let Some(grab) = lookup("grabKeyboard", "GrabKeyboard") {
+        match grab.to_lowercase().as_str() {
+            "true" | "on" | "yes" | "1" => {
+                table.insert("grab_keyboard".into(), Value::Boolean(true));
+            }
+            "false" | "off" | "no" | "0" => {
+                table.insert("grab_keyboard".into(), Value::Boolean(false));
+            }
+            _ => warn!("X resource grabKeyboard: not a boolean '{}'", grab),
+        }
+    }
+    if !dialog.is_empty() {
+        table.insert("dialog".into(), Value::Table(dialog));
+    }
+    table
+}
+
+/// Converts an x11-ssh-askpass theme, like its `SshAskpass.ad`, to a config. The beveled
+/// buttons become square ones with the bottom shadow as the border that lightens when pressed.
+pub fn import(db: &Database) -> Result<Config> {
+    let mut dialog = Table::new();
+    insert(
+        &mut dialog,
+        "foreground",
+        color(get(db, "foreground", "Foreground")),
+    );
+    insert(
+        &mut dialog,
+        "background",
+        color(get(db, "background", "Background")),
+    );
+    insert(
+        &mut dialog,
+        "font",
+        get(db, "font", "Font").map(|font| Value::String(pango_font(font))),
+    );
+    for (key, name) in [("ok_button", "okButton"), ("cancel_button", "cancelButton")] {
+        dialog.insert(key.into(), Value::Table(import_button(db, name)));
+    }
+    dialog.insert("indicator".into(), Value::Table(import_indicator(db)));
+
+    let mut table = Table::new();
+    table.insert("dialog".into(), Value::Table(dialog));
+    Ok(Config::deserialize(table).context("Config Toml")?)
+}
+
+/// A resource of the x11-ssh-askpass dialog or its widgets.
+fn get<'a>(db: &'a Database, name: &str, class: &str) -> Option<&'a str> {
+    db.get_string(
+        &format!("x11-ssh-askpass.dialog.{name}"),
+        &format!("{CLASS}.Dialog.{class}"),
+    )
+    .map(str::trim)
+}
+
+fn import_button(db: &Database, name: &str) -> Table {
+    let get = |resource: &str, class: &str| {
+        get(
+            db,
+            &format!("{name}.{resource}"),
+            &format!("Button.{class}"),
+        )
+    };
+    let mut button = Table::new();
+    insert(&mut button, "label", get("label", "Label").map(Value::from));
+    insert(
+        &mut button,
+        "foreground",
+        color(get("foreground", "Foreground")),
+    );
+    if let Some(background) = color(get("background", "Background")) {
+        for key in ["background", "background_hover", "background_pressed"] {
+            button.insert(key.into(), background.clone());
+        }
+    }
+    for (key, resource, class) in [
+        ("border_width", "shadowThickness", "ShadowThickness"),
+        (
+            "horizontal_spacing",
+            "horizontalSpacing",
+            "HorizontalSpacing",
+        ),
+        ("vertical_spacing", "verticalSpacing", "VerticalSpacing"),
+    ] {
+        insert(&mut button, key, number(get(resource, class)));
+    }
+    for (key, resource, class) in [
+        ("border_color", "bottomShadowColor", "BottomShadowColor"),
+        ("border_color_pressed", "topShadowColor", "TopShadowColor"),
+    ] {
+        insert(&mut button, key, color(get(resource, class)));
+    }
+    for key in ["radius_x", "radius_y"] {
+        button.insert(key.into(), Value::Float(0.0));
+    }
+    for key in ["pressed_adjustment_x", "pressed_adjustment_y"] {
+        button.insert(key.into(), Value::Float(1.0));
+    }
+    button
+}
+
+fn import_indicator(db: &Database) -> Table {
+    let get = |resource: &str, class: &str| {
+        get(
+            db,
+            &format!("indicator.{resource}"),
+            &format!("Indicator.{class}"),
+        )
+    };
+    let mut classic = Table::new();
+    insert(
+        &mut classic,
+        "min_count",
+        integer(get("minimumCount", "MinimumCount")),
+    );
+    insert(
+        &mut classic,
+        "max_count",
+        integer(get("maximumCount", "MaximumCount")),
+    );
+    for (key, resource, class) in [
+        ("element_width", "width", "Width"),
+        ("element_height", "height", "Height"),
+        (
+            "horizontal_spacing",
+            "horizontalSpacing",
+            "HorizontalSpacing",
+        ),
+    ] {
+        insert(&mut classic, key, number(get(resource, class)));
+    }
+    for key in ["radius_x", "radius_y"] {
+        classic.insert(key.into(), Value::Float(0.0));
+    }
+    classic.insert("fill".into(), Value::from("Progressive"));
+
+    let mut indicator = Table::new();
+    indicator.insert("type".into(), Value::from("Classic"));
+    indicator.insert("classic".into(), Value::Table(classic));
+    insert(
+        &mut indicator,
+        "indicator_color",
+        color(get("foreground", "Foreground")),
+    );
+    insert(
+        &mut indicator,
+        "background",
+        color(get("background", "Background")),
+    );
+    insert(
+        &mut indicator,
+        "border_width",
+        number(get("borderWidth", "BorderWidth")),
+    );
+    insert(
+        &mut indicator,
+        "border_color",
+        color(get("borderColor", "BorderColor")),
+    );
+    indicator
+}
+
+fn insert(table: &mut Table, key: &str, value: Option<Value>) {
+    if let Some(value) = value {
+        table.insert(key.into(), value);
+    }
+}
+
+fn color(value: Option<&str>) -> Option<Value> {
+    let value = value?;
+    match value.parse::<Rgba>() {
+        Ok(color) => Some(Value::String(color.to_string())),
+        Err(err) => {
+            warn!("skipping color '{}': {}", value, err);
+            None
+        }
+    }
+}
+
+fn number(value: Option<&str>) -> Option<Value> {
+    let value = value?;
+    match value.parse::<f64>() {
+        Ok(number) => Some(Value::Float(number)),
+        Err(err) => {
+            warn!("skipping number '{}': {}", value, err);
+            None
+        }
+    }
+}
+
+fn integer(value: Option<&str>) -> Option<Value> {
+    let value = value?;
+    match value.parse::<u16>() {
+        Ok(number) => Some(Value::Integer(number.into())),
+        Err(err) => {
+            warn!("skipping count '{}': {}", value, err);
+            None
+        }
+    }
+}
+
+/// A Pango description of an XLFD name like
+/// `-adobe-helvetica-bold-r-normal-*-*-120-*-*-*-*-iso8859-1`.
+fn pango_font(xlfd: &str) -> String {
+    let fields: Vec<&str> = xlfd.split('-').collect();
+    if !xlfd.starts_with('-') || fields.len() < 9 {
+        return xlfd.into();
+    }
+    let mut font = vec![fields[2]];
+    if fields[3] != "*" && fields[3] != "medium" && fields[3] != "regular" {
+        font.push(fields[3]);
+    }
+    match fields[4] {
+        "i" => font.push("italic"),
+        "o" => font.push("oblique"),
+        _ => {}
+    }
+    let size;
+    if let Ok(decipoints) = fields[8].parse::<u32>() {
+        size = format!("{}", f64::from(decipoints) / 10.0);
+        font.push(&size);
+    } else if fields[7].parse::<u32>().is_ok() {
+        size = format!("{}px", fields[7]);
+        font.push(&size);
+    }
+    font.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_askpass_entries() {
+        let db = Database::new_from_data(
+            b"*background: #000000\n\
+              SshAskpass*foreground: #ffffff\n\
+              xaskpass.grabKeyboard: on\n\
+              SshAskpass*font: -misc-fixed-*\n",
+        );
+        let table = fallback(&db, "xaskpass");
+        assert_eq!(table["grab_keyboard"].as_bool(), Some(true));
+        let dialog = table["dialog"].as_table().unwrap();
+        assert_eq!(dialog["foreground"].as_str(), Some("#ffffff"));
+        assert!(!dialog.contains_key("background"));
+        assert!(!dialog.contains_key("font"));
+        assert!(fallback(&db, "other").get("grab_keyboard").is_none());
+
+        let db = Database::new_from_data(
+            b"SshAskpass*font: -adobe-helvetica-bold-r-normal-*-*-120-*-*-*-*-iso8859-1\n",
+        );
+        let table = fallback(&db, "xaskpass");
+        assert_eq!(table["dialog"]["font"].as_str(), Some("helvetica bold 12"));
+    }
+
+    #[test]
+    fn xlfd() {
+        assert_eq!(
+            pango_font("-adobe-helvetica-bold-r-normal-*-*-120-*-*-*-*-iso8859-1"),
+            "helvetica bold 12"
+        );
+        assert_eq!(pango_font("Sans 10"), "Sans 10");
+    }
+}
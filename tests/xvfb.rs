@@ -0,0 +1,362 @@
+// Runs the real binary against a private Xvfb server and drives it with XTEST.
+// The tests are skipped when Xvfb is not installed.
+
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const XK_RETURN: u32 = 0xff0d;
+const XK_ESCAPE: u32 = 0xff1b;
+
+struct Xvfb {
+    display: String,
+    child: Child,
+}
+
+impl Xvfb {
+    fn start(num: u32) -> Option<Self> {
+        let display = format!(":{num}");
+        let child = match Command::new("Xvfb")
+            .args([&display, "-nolisten", "tcp", "-screen", "0", "1024x768x24"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                eprintln!("skipping: cannot run Xvfb: {err}");
+                return None;
+            }
+        };
+        let me = Self { display, child };
+        let socket = PathBuf::from(format!("/tmp/.X11-unix/X{num}"));
+        wait_for(|| socket.exists().then_some(())).expect("Xvfb did not create its socket in time");
+        Some(me)
+    }
+
+    fn connect(&self) -> RustConnection {
+        wait_for(|| x11rb::connect(Some(&self.display)).ok())
+            .expect("cannot connect to Xvfb")
+            .0
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Askpass {
+    child: Child,
+}
+
+impl Askpass {
+    fn spawn(xvfb: &Xvfb, config_home: &Path, args: &[&str]) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_xaskpass"))
+            .args(args)
+            .env("DISPLAY", &xvfb.display)
+            .env("XDG_CONFIG_HOME", config_home)
+            .env("XDG_CONFIG_DIRS", config_home)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("spawn xaskpass");
+        Self { child }
+    }
+
+    fn wait(mut self) -> (ExitStatus, String) {
+        let status = wait_for(|| self.child.try_wait().unwrap()).expect("xaskpass did not exit");
+        let mut out = String::new();
+        self.child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        (status, out)
+    }
+}
+
+impl Drop for Askpass {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if let Some(v) = f() {
+            return Some(v);
+        }
+        sleep(Duration::from_millis(20));
+    }
+    None
+}
+
+fn find_dialog(conn: &RustConnection, root: xproto::Window) -> Option<xproto::Window> {
+    let tree = conn.query_tree(root).ok()?.reply().ok()?;
+    tree.children.into_iter().find(|&w| {
+        let mapped = conn
+            .get_window_attributes(w)
+            .ok()
+            .and_then(|c| c.reply().ok())
+            .is_some_and(|a| a.map_state == xproto::MapState::VIEWABLE);
+        let class = conn
+            .get_property(
+                false,
+                w,
+                xproto::AtomEnum::WM_CLASS,
+                xproto::AtomEnum::STRING,
+                0,
+                64,
+            )
+            .ok()
+            .and_then(|c| c.reply().ok())
+            .map(|p| p.value)
+            .unwrap_or_default();
+        mapped && class.starts_with(b"xaskpass\0")
+    })
+}
+
+struct Input<'a> {
+    conn: &'a RustConnection,
+    root: xproto::Window,
+    keysyms: Vec<u32>,
+    keysyms_per_keycode: usize,
+    min_keycode: u8,
+}
+
+impl<'a> Input<'a> {
+    fn new(conn: &'a RustConnection, root: xproto::Window) -> Self {
+        let setup = conn.setup();
+        let min_keycode = setup.min_keycode;
+        let count = setup.max_keycode - min_keycode + 1;
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, count)
+            .unwrap()
+            .reply()
+            .unwrap();
+        Self {
+            conn,
+            root,
+            keysyms: mapping.keysyms,
+            keysyms_per_keycode: mapping.keysyms_per_keycode.into(),
+            min_keycode,
+        }
+    }
+
+    fn keycode(&self, keysym: u32) -> u8 {
+        let index = self
+            .keysyms
+            .chunks(self.keysyms_per_keycode)
+            .position(|syms| syms.first() == Some(&keysym))
+            .unwrap_or_else(|| panic!("no keycode for keysym {keysym:#x}"));
+        self.min_keycode + u8::try_from(index).unwrap()
+    }
+
+    fn key(&self, keysym: u32) {
+        let keycode = self.keycode(keysym);
+        for type_ in [xproto::KEY_PRESS_EVENT, xproto::KEY_RELEASE_EVENT] {
+            self.conn
+                .xtest_fake_input(type_, keycode, x11rb::CURRENT_TIME, self.root, 0, 0, 0)
+                .unwrap();
+        }
+        self.conn.sync().unwrap();
+    }
+
+    fn text(&self, text: &str) {
+        for c in text.chars() {
+            self.key(u32::from(c));
+        }
+    }
+
+    fn click(&self, x: i16, y: i16) {
+        self.conn
+            .xtest_fake_input(
+                xproto::MOTION_NOTIFY_EVENT,
+                0,
+                x11rb::CURRENT_TIME,
+                self.root,
+                x,
+                y,
+                0,
+            )
+            .unwrap();
+        for type_ in [xproto::BUTTON_PRESS_EVENT, xproto::BUTTON_RELEASE_EVENT] {
+            self.conn
+                .xtest_fake_input(type_, 1, x11rb::CURRENT_TIME, self.root, 0, 0, 0)
+                .unwrap();
+        }
+        self.conn.sync().unwrap();
+    }
+}
+
+struct Session {
+    _xvfb: Xvfb,
+    conn: RustConnection,
+    root: xproto::Window,
+    dialog: xproto::Window,
+    askpass: Askpass,
+    _config_home: TempDir,
+}
+
+impl Session {
+    fn start(num: u32) -> Option<Self> {
+        Self::start_with(num, &[])
+    }
+
+    fn start_with(num: u32, args: &[&str]) -> Option<Self> {
+        let xvfb = Xvfb::start(num)?;
+        let conn = xvfb.connect();
+        let root = conn.setup().roots[0].root;
+        let config_home = TempDir::new(&format!("xaskpass-xvfb-{num}"));
+        let askpass = Askpass::spawn(&xvfb, &config_home.0, args);
+        let dialog =
+            wait_for(|| find_dialog(&conn, root)).expect("the dialog window was not mapped");
+        conn.set_input_focus(xproto::InputFocus::PARENT, dialog, x11rb::CURRENT_TIME)
+            .unwrap();
+        conn.sync().unwrap();
+        // Give the dialog a moment to process its first expose
+        sleep(Duration::from_millis(200));
+        Some(Self {
+            _xvfb: xvfb,
+            conn,
+            root,
+            dialog,
+            askpass,
+            _config_home: config_home,
+        })
+    }
+
+    fn input(&self) -> Input<'_> {
+        Input::new(&self.conn, self.root)
+    }
+
+    fn geometry(&self) -> xproto::GetGeometryReply {
+        self.conn
+            .get_geometry(self.dialog)
+            .unwrap()
+            .reply()
+            .unwrap()
+    }
+}
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Each test uses its own display so that they can run in parallel.
+fn display_num(offset: u32) -> u32 {
+    100 + (std::process::id() % 100) * 10 + offset
+}
+
+#[test]
+fn enter_prints_passphrase() {
+    let Some(session) = Session::start(display_num(0)) else {
+        return;
+    };
+    let input = session.input();
+    input.text("hunter");
+    input.key(XK_RETURN);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(out, "hunter\n");
+}
+
+#[test]
+fn escape_cancels() {
+    let Some(session) = Session::start(display_num(1)) else {
+        return;
+    };
+    let input = session.input();
+    input.text("abc");
+    input.key(XK_ESCAPE);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(1));
+    assert_eq!(out, "");
+}
+
+#[test]
+fn backspace_edits_passphrase() {
+    let Some(session) = Session::start(display_num(2)) else {
+        return;
+    };
+    let input = session.input();
+    input.text("abcd");
+    input.key(0xff08); // BackSpace
+    input.key(XK_RETURN);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(out, "abc\n");
+}
+
+#[test]
+fn click_outside_buttons_keeps_prompting() {
+    let Some(session) = Session::start(display_num(3)) else {
+        return;
+    };
+    let input = session.input();
+    let geom = session.geometry();
+    // The top left corner of the dialog is never a button
+    input.click(geom.x + 1, geom.y + 1);
+    input.text("x");
+    input.key(XK_RETURN);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(out, "x\n");
+}
+
+#[test]
+fn confirm_prints_yes() {
+    let Some(session) = Session::start_with(display_num(4), &["Allow use of key? (yes/no)"]) else {
+        return;
+    };
+    let input = session.input();
+    // Typing does nothing in confirm mode
+    input.text("abc");
+    input.key(XK_RETURN);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(out, "yes\n");
+}
+
+#[test]
+fn basic_auth_prints_username_and_password() {
+    let Some(session) = Session::start_with(display_num(5), &["--basic-auth"]) else {
+        return;
+    };
+    let input = session.input();
+    input.text("user");
+    input.key(XK_RETURN);
+    input.text("pass");
+    input.key(XK_RETURN);
+    let (status, out) = session.askpass.wait();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(out, "user:pass\n");
+}